@@ -0,0 +1,908 @@
+use crate::book::Book;
+use crate::book_generator::book_generator;
+use crate::calibre::write_sidecars;
+use crate::credentials::{
+    access_token, load_credentials, save_credentials, wipe_credentials, Credentials,
+};
+use crate::decrypt::{combine_zip, unzip_book};
+use crate::download::fetch_resumable;
+use crate::error::{AppError, ErrorKind};
+use crate::helpers::{clear_residue, compare_versions, logout_from_app, random_company};
+use crate::jobs::{CancelToken, ResidueGuard};
+use crate::journal::{mark_failed, Journal, Stage};
+use crate::library::{
+    apply_sync, cached_books, fresh_cached_books, record_attempt, update_cached_book, LibrarySync,
+};
+use crate::output::{work_dir, OutputLayout, OutputSettings};
+use crate::progress::DownloadProgress;
+use crate::request::{reject_unauthorized, send, Endpoint};
+use crate::session::SessionManager;
+use crate::settings::Settings;
+use crate::sources::{self, is_kept};
+use crate::storage::preflight;
+use crate::store::StoreBackend;
+use base64::{engine::general_purpose, Engine as _};
+use chrono::Utc;
+use openssl::sha::Sha1;
+use rand::Rng;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use std::fs;
+use std::option::Option;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{info_span, Instrument};
+use uuid::Uuid;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AuthResult {
+    pub username: String,
+    pub auth: String,
+    pub device_name: String,
+    pub device_uid: String,
+    pub app_type: String,
+    /// Unix time after which the token has to be renewed.
+    pub expires: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DownloadInfo {
+    pub url: String,
+    pub header: String,
+}
+
+fn nonce() -> String {
+    let mut rng = rand::thread_rng();
+    let mut sb = String::with_capacity(64);
+    for _ in 0..64 {
+        sb.push_str(&rng.gen_range(0..10).to_string());
+    }
+    sb
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Operation {
+    MULTIPLY,
+    DIVIDE,
+    #[allow(dead_code)]
+    ADD,
+    #[allow(dead_code)]
+    SUBTRACT,
+    #[allow(dead_code)]
+    MODULE,
+}
+
+fn generate_secret() -> String {
+    const COMPOSITION_LOCAL_MAP_KEY: i32 = 202;
+    const PROVIDER_MAPS_KEY: i32 = 204;
+
+    let left_operands: Vec<i32> = vec![
+        17,
+        106,
+        COMPOSITION_LOCAL_MAP_KEY,
+        PROVIDER_MAPS_KEY,
+        106,
+        PROVIDER_MAPS_KEY,
+        96,
+        106,
+        33,
+        96,
+        25,
+        17,
+        49,
+        33,
+        27,
+        33,
+        13,
+        194,
+        7,
+        25,
+        106,
+        106,
+        19,
+        13,
+        25,
+        5,
+        13,
+        COMPOSITION_LOCAL_MAP_KEY,
+        200,
+        49,
+        96,
+        28,
+    ];
+    let right_operands: Vec<i32> = vec![
+        3, 2, 2, 2, 2, 2, 2, 2, 3, 2, 2, 3, 2, 3, 2, 3, 4, 2, 7, 2, 2, 2, 3, 4, 2, 11, 4, 2, 2, 2,
+        2, 2,
+    ];
+    let operations: Vec<Operation> = vec![
+        Operation::MULTIPLY,
+        Operation::DIVIDE,
+        Operation::DIVIDE,
+        Operation::DIVIDE,
+        Operation::DIVIDE,
+        Operation::DIVIDE,
+        Operation::DIVIDE,
+        Operation::DIVIDE,
+        Operation::MULTIPLY,
+        Operation::DIVIDE,
+        Operation::MULTIPLY,
+        Operation::MULTIPLY,
+        Operation::MULTIPLY,
+        Operation::MULTIPLY,
+        Operation::MULTIPLY,
+        Operation::MULTIPLY,
+        Operation::MULTIPLY,
+        Operation::DIVIDE,
+        Operation::MULTIPLY,
+        Operation::MULTIPLY,
+        Operation::DIVIDE,
+        Operation::DIVIDE,
+        Operation::MULTIPLY,
+        Operation::MULTIPLY,
+        Operation::MULTIPLY,
+        Operation::MULTIPLY,
+        Operation::MULTIPLY,
+        Operation::DIVIDE,
+        Operation::DIVIDE,
+        Operation::MULTIPLY,
+        Operation::DIVIDE,
+        Operation::MULTIPLY,
+    ];
+
+    let mut secret = String::new();
+    for i in 0..operations.len() {
+        let left = left_operands[i];
+        let right = right_operands[i];
+        let op = operations[i];
+        let result = match op {
+            Operation::MULTIPLY => left * right,
+            Operation::DIVIDE => left / right,
+            Operation::ADD => left + right,
+            Operation::SUBTRACT => left - right,
+            Operation::MODULE => left % right,
+        };
+        secret.push(std::char::from_u32(result as u32).unwrap_or('\0'));
+    }
+    secret
+}
+
+fn byte_array_to_hex_string(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut result, &byte| {
+        let hex = format!("{:x}", (byte as u16) + 256);
+        result.push_str(&hex[1..]);
+        result
+    })
+}
+
+fn checksum(time: u64, nonce: &str) -> String {
+    let secret = generate_secret();
+    // println!("Generated secret: {:?}", secret);
+
+    let string = format!("{}{}{}", time, nonce, secret);
+    // println!("Checksum input: {}", string);
+
+    let mut hasher = Sha1::new();
+    hasher.update(string.as_bytes());
+    let result = hasher.finish();
+
+    let hex_result = byte_array_to_hex_string(&result);
+    // println!("Calculated checksum: {}", hex_result);
+
+    hex_result
+}
+
+fn request_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+fn get_request_check() -> String {
+    let request_nonce = nonce();
+    let request_time = request_time();
+    // println!("Nonce: {}, Time: {}", request_nonce, request_time);
+    let checksum = checksum(request_time, &request_nonce);
+
+    let json = json!({
+        "nonce": request_nonce,
+        "checksum": checksum,
+        "timestamp": request_time.to_string(),
+        "platform": "android"
+    });
+
+    // println!("Request JSON: {}", json.to_string());
+
+    let encoded = general_purpose::STANDARD_NO_PAD.encode(json.to_string().as_bytes());
+    // println!("Request check: {}", encoded);
+    encoded
+}
+
+pub async fn get_initial_auth(
+    client: &Client,
+    store: &dyn StoreBackend,
+) -> Result<(String, u64), AppError> {
+    let response = send(Endpoint::Token, || {
+        client
+            .post(store.url(Endpoint::Token))
+            .form(&[
+                ("grant_type", "client_credentials"),
+                ("client_secret", "cfb6113dfb4ccba4da7fd18c4dd8da6d"),
+                ("client_id", "accounts_manager"),
+                ("platform", &"android".to_string()),
+            ])
+            .header("X-Request-Check", get_request_check())
+    })
+    .await
+    .map_err(|e| {
+        AppError::network("502", "Could not retrieve initial access token!").with_detail(e)
+    })
+    .and_then(|response| reject_unauthorized(response, "501"))?;
+
+    let response_text = response.text().await.map_err(|e| {
+        AppError::network("502", "Could not retrieve initial access token text!").with_detail(e)
+    })?;
+
+    // println!("Token response: {}", response_text);
+
+    let response_data: HashMap<String, serde_json::Value> = serde_json::from_str(&response_text)
+        .map_err(|e| {
+            AppError::network("502", "Could not parse initial access token JSON!")
+                .with_detail(format!("{} \n text: {}", e, response_text))
+        })?;
+
+    if let (Some(access_token), Some(expires_in)) = (
+        response_data.get("access_token").and_then(|v| v.as_str()),
+        response_data.get("expires_in").and_then(|v| v.as_u64()),
+    ) {
+        return Ok((access_token.to_string(), expires_in));
+    }
+
+    Err(AppError::auth(
+        "501",
+        "Could not retrieve initial access token!",
+    ))
+}
+
+pub async fn pre_auth(
+    client: &Client,
+    store: &dyn StoreBackend,
+    session: &SessionManager,
+) -> Result<bool, AppError> {
+    let settings = Settings::load()?;
+    if settings.initial_token.is_some() || load_credentials()?.is_some() {
+        session.get(client, store).await.map(|_| true)
+    } else {
+        Ok(false)
+    }
+}
+
+pub async fn auth(
+    client: &Client,
+    store: &dyn StoreBackend,
+    email: Option<&str>,
+    password: Option<&str>,
+) -> Result<AuthResult, AppError> {
+    let settings = Settings::load()?;
+    let saved = load_credentials()?.and_then(|c| c.access_token);
+    if let (Some(auth), Some(expires)) = (saved, settings.expires) {
+        if expires > Utc::now().timestamp() as u64 {
+            return Ok(AuthResult {
+                username: settings.username.unwrap_or_default(),
+                auth,
+                device_name: settings.device_name.unwrap_or_default(),
+                device_uid: settings.device_uid.unwrap_or_default(),
+                app_type: store.id().to_string(),
+                expires,
+            });
+        }
+    }
+
+    login(client, store, email, password).await
+}
+
+/// Token lifetimes come as seconds from now; older settings files stored
+/// them as a timestamp already.
+fn expiry_timestamp(expires: u64) -> u64 {
+    if expires < 1_000_000_000 {
+        Utc::now().timestamp() as u64 + expires
+    } else {
+        expires
+    }
+}
+
+/// Logs in to the store, ignoring any saved token. Missing credentials are
+/// taken from the credential store.
+pub async fn login(
+    client: &Client,
+    store: &dyn StoreBackend,
+    email: Option<&str>,
+    password: Option<&str>,
+) -> Result<AuthResult, AppError> {
+    let settings = Settings::load()?;
+    let saved = load_credentials()?.unwrap_or_default();
+    let email = email.unwrap_or(&saved.email);
+    let password = password.unwrap_or(&saved.password);
+    if email.is_empty() || password.is_empty() {
+        return Err(AppError::auth(
+            "505z",
+            "Can not login! empty username or password!",
+        ));
+    }
+
+    let (initial_token, expires) = get_initial_auth(client, store).await?;
+    let expires = expiry_timestamp(expires);
+
+    let uuid = Uuid::new_v4().to_string();
+    let device_uid = settings.device_uid.clone().unwrap_or(uuid);
+    let device_name = settings
+        .device_name
+        .as_deref()
+        .unwrap_or_else(|| random_company());
+
+    let response = send(Endpoint::Login, || {
+        client
+            .post(store.url(Endpoint::Login))
+            .form(&[
+                ("access_token", &initial_token.to_string()),
+                ("deviceUID", &device_uid.to_string()),
+                ("appId", &"1".to_string()),
+                ("email", &email.to_string()),
+                ("deviceName", &device_name.to_string()),
+                ("password", &password.to_string()),
+                ("prev_access_token", &"x_access".to_string()),
+                ("platform", &"android".to_string()),
+            ])
+            .header("X-Request-Check", get_request_check())
+    })
+    .await
+    .map_err(|e| AppError::network("505y", "Can not login! check your info!").with_detail(e))
+    .and_then(|response| reject_unauthorized(response, "505y"))?;
+
+    let response_text = response
+        .text()
+        .await
+        .map_err(|e| AppError::network("505x", "Cannot read response text!").with_detail(e))?;
+
+    // println!("Auth response text: {}", response_text);
+
+    let response_data: HashMap<String, serde_json::Value> = serde_json::from_str(&response_text)
+        .map_err(|e| {
+            AppError::network("505x", "Can not parse login response!")
+                .with_detail(format!("{} \ntext: {}", e, response_text))
+        })?;
+
+    if let Some(result) = response_data.get("result") {
+        let username = result
+            .get("user")
+            .and_then(|u| u.get("fullName").or_else(|| u.get("nickname")))
+            .and_then(|n| n.as_str())
+            .unwrap_or_default()
+            .to_string();
+
+        let auth = result
+            .get("access_token")
+            .and_then(|a| a.as_str())
+            .unwrap_or_default()
+            .to_string();
+
+        Settings::update(|settings| {
+            settings.app = Some(store.id().to_string());
+            settings.initial_token = Some(initial_token.clone());
+            settings.expires = Some(expires);
+            settings.username = Some(username.clone());
+            settings.device_name = Some(device_name.to_string());
+            settings.device_uid = Some(device_uid.clone());
+        })?;
+        save_credentials(&Credentials {
+            email: email.to_string(),
+            password: password.to_string(),
+            access_token: Some(auth.clone()),
+        })?;
+
+        return Ok(AuthResult {
+            username,
+            auth,
+            device_name: device_name.to_string(),
+            device_uid: device_uid.clone().to_string(),
+            app_type: store.id().to_string(),
+            expires,
+        });
+    }
+
+    Err(AppError::auth("505t", "Can not login! check your info!").with_detail("null data"))
+}
+
+/// The library, from the local catalog when it is recent enough.
+pub async fn get_user_books(
+    client: &Client,
+    store: &dyn StoreBackend,
+    session: &SessionManager,
+) -> Result<Vec<Book>, AppError> {
+    if let Some(books) = fresh_cached_books()? {
+        return Ok(books);
+    }
+    sync_library(client, store, session)
+        .await
+        .map(|sync| sync.books)
+}
+
+/// Fetches the store list, merges it into the local catalog and reports
+/// what changed since the last sync.
+pub async fn sync_library(
+    client: &Client,
+    store: &dyn StoreBackend,
+    session: &SessionManager,
+) -> Result<LibrarySync, AppError> {
+    let remote = fetch_user_books(client, store, session).await?;
+    apply_sync(remote)
+}
+
+async fn fetch_user_books(
+    client: &Client,
+    store: &dyn StoreBackend,
+    session: &SessionManager,
+) -> Result<Vec<Book>, AppError> {
+    let response = session
+        .with_session(client, store, |auth_result| async move {
+            send(Endpoint::UserBooks, || {
+                client
+                    .post(store.url(Endpoint::UserBooks))
+                    .form(&[
+                        ("access_token", &auth_result.auth),
+                        ("platform", &"android".to_string()),
+                        ("deviceName", &auth_result.device_name),
+                        ("deviceUID", &auth_result.device_uid),
+                    ])
+                    .header("X-Request-Check", get_request_check())
+            })
+            .await
+            .map_err(|e| {
+                AppError::network("601", "There was a problem retrieving the books list!")
+                    .with_detail(e)
+            })
+            .and_then(|response| reject_unauthorized(response, "601"))
+        })
+        .await?;
+
+    let response_data: HashMap<String, serde_json::Value> = response.json().await.map_err(|e| {
+        AppError::network("601-1", "There was a problem retrieving the books list!").with_detail(e)
+    })?;
+    if let Some(result) = response_data.get("result") {
+        return Ok(result
+            .as_array()
+            .unwrap_or(&vec![])
+            .iter()
+            .map(|item| store.parse_book(item))
+            .collect());
+    }
+
+    Err(
+        AppError::network("600", "There was a problem retrieving the books list!")
+            .with_detail("empty api response"),
+    )
+}
+
+pub async fn get_download_info(
+    client: &Client,
+    store: &dyn StoreBackend,
+    session: &SessionManager,
+    book: &Book,
+) -> Result<DownloadInfo, AppError> {
+    let response = session
+        .with_session(client, store, |auth_result| async move {
+            send(Endpoint::DownloadInfo, || {
+                client
+                    .post(store.url(Endpoint::DownloadInfo))
+                    .form(&[
+                        ("access_token", auth_result.auth.as_str()),
+                        ("file_id", book.current_file_id()),
+                        ("platform", "android"),
+                    ])
+                    .header("X-Request-Check", get_request_check())
+            })
+            .await
+            .map_err(|e| {
+                AppError::network("801", "Could not get download info! check your info!")
+                    .with_detail(e)
+            })
+            .and_then(|response| reject_unauthorized(response, "801"))
+        })
+        .await?;
+
+    let response_data: HashMap<String, serde_json::Value> = response.json().await.map_err(|e| {
+        AppError::network("801", "Could not get download info! check your info!").with_detail(e)
+    })?;
+
+    if let Some(result) = response_data.get("result") {
+        let url = result
+            .get("body")
+            .and_then(|v| v.as_str())
+            .unwrap_or_default()
+            .to_string();
+        let header = result
+            .get("header")
+            .and_then(|v| v.as_str())
+            .unwrap_or_default()
+            .to_string();
+        return Ok(DownloadInfo { url, header });
+    }
+
+    Err(AppError::network(
+        "800",
+        "Could not get download info! check your info!",
+    ))
+}
+
+/// Streams the book package to disk, reporting progress through `on_progress`.
+/// Returns where it went: `<id>.zip`, or `<id>.zip.body` for a package that
+/// still has to be combined with its header.
+pub async fn download_book(
+    client: &Client,
+    book: &Book,
+    on_progress: &(dyn Fn(DownloadProgress) + Send + Sync),
+    cancel: &CancelToken,
+) -> Result<PathBuf, AppError> {
+    let path = work_dir();
+    if !path.exists() {
+        fs::create_dir_all(&path)?;
+    }
+
+    let book_path = path.join(format!("{}.zip", book.id));
+    let mut book_path_write = book_path.clone();
+    if book.url.ends_with(".body") && !book.header.is_empty() {
+        book_path_write.set_extension("zip.body");
+    }
+
+    fetch_resumable(
+        client,
+        &book.url,
+        &book_path_write,
+        &book.id,
+        Some(book.size),
+        on_progress,
+        cancel,
+    )
+    .await?;
+    cancel.check()?;
+
+    if book_path_write.exists() {
+        return Ok(book_path_write);
+    }
+
+    Err(AppError::io("702-6", "File was not created successfully"))
+}
+
+/// Joins a downloaded `.zip.body` with the header from the download info
+/// into `<id>.zip`, returning it with the key found in the header. Plain
+/// packages are returned as they are, with the key the store gave.
+async fn combine_package(
+    journal: &Journal,
+    package: &Path,
+) -> Result<(PathBuf, Vec<i32>), AppError> {
+    if package.extension().is_some_and(|ext| ext != "body") {
+        return Ok((package.to_path_buf(), journal.key.clone()));
+    }
+    let zip = package.with_extension("");
+    let access_token = access_token()?.unwrap_or_default();
+    let header_key = combine_zip(package, &journal.header, &access_token, &zip)
+        .await
+        .map_err(|e| AppError::from(e).with_code("702-5"))?;
+    Ok((zip, header_key.unwrap_or_default()))
+}
+
+/// Downloads and converts a book, keeping a record of the attempt in the
+/// catalog's conversion history.
+pub async fn download_and_generate_book(
+    client: &Client,
+    store: &dyn StoreBackend,
+    session: &SessionManager,
+    book_id: &str,
+    on_progress: &(dyn Fn(DownloadProgress) + Send + Sync),
+    cancel: &CancelToken,
+) -> Result<String, AppError> {
+    let started_at = Utc::now().timestamp() as u64;
+    let span = info_span!("job", book_id);
+    let result = download_and_convert(client, store, session, book_id, on_progress, cancel)
+        .instrument(span.clone())
+        .await;
+
+    let file_id = cached_books()
+        .ok()
+        .and_then(|books| books.into_iter().find(|b| b.id == book_id))
+        .map(|b| b.current_file_id().to_string());
+    let status = record_attempt(book_id, file_id, started_at, &result);
+    span.in_scope(|| match &result {
+        Ok(path) => tracing::info!("Converted to {}", path),
+        Err(e) => tracing::warn!("Conversion {}: {}", status, e),
+    });
+    if let Err(error) = &result {
+        if error.kind() != ErrorKind::Cancelled {
+            mark_failed(book_id, error);
+        }
+    }
+    result
+}
+
+async fn download_and_convert(
+    client: &Client,
+    store: &dyn StoreBackend,
+    session: &SessionManager,
+    book_id: &str,
+    on_progress: &(dyn Fn(DownloadProgress) + Send + Sync),
+    cancel: &CancelToken,
+) -> Result<String, AppError> {
+    let mut residue = ResidueGuard::new(book_id, cancel);
+    // Only sync when the catalog does not know the book yet.
+    let user_books = match cached_books()?.into_iter().find(|b| b.id == book_id) {
+        Some(book) => vec![book],
+        None => sync_library(client, store, session).await?.books,
+    };
+    let book = user_books
+        .iter()
+        .find(|b| b.id == book_id)
+        .ok_or_else(|| {
+            let books_list: Vec<(String, String)> = user_books
+                .iter()
+                .map(|b| (b.id.clone(), b.name.clone()))
+                .collect();
+            AppError::integrity("701", format!("Book with id {} not found.", book_id))
+                .with_detail(format!("Available books: {:?}", books_list))
+        })?
+        .clone();
+    cancel.check()?;
+
+    // Pick up after the last stage that is still intact, unless the journal
+    // is for an older file of the book.
+    let file_id = book.current_file_id().to_string();
+    let mut journal = match Journal::load(book_id) {
+        Some(journal) if journal.file_id == file_id => journal,
+        Some(_) => {
+            clear_residue(book_id)?;
+            Journal::new(book_id, &file_id)
+        }
+        None => Journal::new(book_id, &file_id),
+    };
+    let resume = journal.resume_point().map(|record| record.stage);
+    if let Some(stage) = resume {
+        tracing::info!("Resuming after {:?}", stage);
+    }
+    journal.error = None;
+    journal.save()?;
+    if resume < Some(Stage::Generated) {
+        preflight(&book)?;
+    }
+
+    if resume.is_none() {
+        async {
+            let download_info = get_download_info(client, store, session, &book).await?;
+            journal.url = download_info.url.clone();
+            journal.header = download_info.header.clone();
+            journal.key = book.key.clone();
+            let package = download_book(
+                client,
+                &Book {
+                    url: download_info.url,
+                    header: download_info.header,
+                    ..book.clone()
+                },
+                on_progress,
+                cancel,
+            )
+            .await?;
+            journal.record(Stage::Downloaded, &package)
+        }
+        .instrument(info_span!("stage", stage = "download"))
+        .await?;
+    }
+    cancel.check()?;
+
+    if resume < Some(Stage::Combined) {
+        let package = journal.artifact(Stage::Downloaded).ok_or_else(|| {
+            AppError::integrity(
+                "702-6",
+                "The downloaded package is missing from the journal",
+            )
+        })?;
+        let (zip, key) = combine_package(&journal, &package)
+            .instrument(info_span!("stage", stage = "combine"))
+            .await?;
+        journal.key = key;
+        journal.record(Stage::Combined, &zip)?;
+    }
+    cancel.check()?;
+
+    if resume < Some(Stage::Extracted) {
+        // Whatever an interrupted extraction left is written over.
+        let folder = work_dir().join(book_id);
+        if folder.exists() {
+            fs::remove_dir_all(&folder)?;
+        }
+        let unzip_cancel = cancel.clone();
+        let unzip_book_info = Book {
+            key: journal.key.clone(),
+            ..book.clone()
+        };
+        let span = info_span!("stage", stage = "extract");
+        tokio::task::spawn_blocking(move || {
+            span.in_scope(|| unzip_book(unzip_book_info, unzip_cancel))
+        })
+        .await
+        .map_err(|e| AppError::conversion("701-1", "Book extraction task failed").with_detail(e))?
+        .map_err(|e| AppError::from(e).with_code("701-2"))?;
+        journal.record(Stage::Extracted, &folder)?;
+    }
+
+    let settings = OutputSettings::load();
+    let keep_source = settings.keep_sources && !is_kept(book_id, &file_id);
+    let calibre = settings.layout == OutputLayout::Calibre;
+    let cover = if keep_source || calibre || book.book_type == "epub" {
+        book_cover(client, &book).await
+    } else {
+        None
+    };
+    let generated_book = match journal.artifact(Stage::Generated) {
+        Some(generated) if resume == Some(Stage::Generated) => generated,
+        _ => {
+            // Audio and PDF files are moved out of the folder, so it is kept
+            // before the output is generated.
+            if keep_source {
+                keep(&book, &file_id, store.id(), cover.clone())
+                    .instrument(info_span!("stage", stage = "keep"))
+                    .await;
+            }
+            let generated = book_generator(book.clone(), cover.as_deref(), cancel)
+                .instrument(info_span!("stage", stage = "generate"))
+                .await
+                .map_err(|e| AppError::from(e).with_code("701-3"))?;
+            journal.record(Stage::Generated, &generated)?;
+            generated
+        }
+    };
+    if calibre {
+        if let Err(e) = write_sidecars(&book, &generated_book, store.id(), cover.as_deref()) {
+            tracing::warn!("Could not write the Calibre metadata: {}", e);
+        }
+    }
+
+    update_cached_book(book_id, |cached| {
+        cached.book_path = Some(generated_book.display().to_string());
+        cached.downloaded_at = Some(Utc::now().timestamp() as u64);
+        cached.url = journal.url;
+        cached.header = journal.header;
+        cached.key = journal.key;
+        cached.downloaded_file_id = Some(file_id);
+        cached.update_available = false;
+    })?;
+
+    clear_residue(book_id)?;
+    residue.disarm();
+    Ok(generated_book.display().to_string())
+}
+
+/// Keeps the extraction folder and the cover for `regenerate_book`. Not
+/// worth failing the job over, so errors are only logged.
+async fn keep(book: &Book, file_id: &str, store: &'static str, cover: Option<Vec<u8>>) {
+    let (book, file_id) = (book.clone(), file_id.to_string());
+    let kept = move || sources::keep(&book, &file_id, store, cover.as_deref());
+    match tokio::task::spawn_blocking(kept).await {
+        Ok(Ok(path)) => tracing::info!("Kept the source in {}", path.display()),
+        Ok(Err(e)) => tracing::warn!("{}", e),
+        Err(e) => tracing::warn!("Keeping the source failed: {}", e),
+    }
+}
+
+/// The store cover of `book`, for EPUBs, kept sources and Calibre. Books go
+/// on without one when it can not be fetched.
+async fn book_cover(client: &Client, book: &Book) -> Option<Vec<u8>> {
+    let url = book
+        .cover
+        .as_deref()
+        .filter(|url| url.starts_with("http"))?;
+    let fetch = async {
+        let response = client.get(url).send().await?.error_for_status()?;
+        Ok::<_, reqwest::Error>(response.bytes().await?.to_vec())
+    };
+    match fetch.await {
+        Ok(cover) => Some(cover),
+        Err(e) => {
+            tracing::warn!("Could not fetch the cover: {}", e);
+            None
+        }
+    }
+}
+
+pub async fn logout(client: &Client, store: &dyn StoreBackend) -> Result<bool, AppError> {
+    let saved = Settings::load().and_then(|settings| Ok((settings, access_token()?)));
+    // The secrets go first, whatever happens with the store below.
+    wipe_credentials()?;
+    let (settings, access_token) = match saved {
+        Ok((settings, Some(access_token))) => (settings, access_token),
+        Ok(_) => {
+            logout_from_app()?;
+            return Ok(true);
+        }
+        Err(e) => {
+            // Nothing to tell the store without a token, and the session in
+            // the settings can not be cleared; the credentials are gone.
+            tracing::warn!("Logging out with unreadable settings: {}", e);
+            return Ok(true);
+        }
+    };
+    let params = json!({
+        "access_token": access_token,
+        "deviceUID": settings.device_uid,
+        "appId": "1",
+        "platform": "android",
+    });
+
+    let response = send(Endpoint::Logout, || {
+        client
+            .post(store.url(Endpoint::Logout))
+            .json(&params)
+            .header("X-Request-Check", get_request_check())
+    })
+    .await;
+
+    logout_from_app()?;
+
+    match response {
+        Ok(resp) => {
+            let response_data: HashMap<String, serde_json::Value> =
+                resp.json().await.map_err(|e| {
+                    AppError::network("503", "Could not logout! check your info!").with_detail(e)
+                })?;
+            if response_data.contains_key("result") {
+                Ok(response_data["result"].as_bool().unwrap_or(false))
+            } else {
+                Err(AppError::network(
+                    "503",
+                    "Could not logout! check your info!",
+                ))
+            }
+        }
+        Err(error) => {
+            Err(AppError::network("504", "Could not logout! check your info!").with_detail(error))
+        }
+    }
+}
+
+pub async fn check_for_new_version(client: &Client) -> Result<serde_json::Value, AppError> {
+    let response = send(Endpoint::VersionCheck, || {
+        client.get("https://api.github.com/repos/abdumu/jarir-reader/releases/latest")
+    })
+    .await
+    .map_err(|e| AppError::network("901", "Could not check for new version!").with_detail(e))?;
+
+    let response_data: HashMap<String, serde_json::Value> = response
+        .json()
+        .await
+        .map_err(|e| AppError::network("901", "Could not check for new version!").with_detail(e))?;
+
+    if let (Some(tag_name), Some(name), Some(published_at)) = (
+        response_data
+            .get("tag_name")
+            .and_then(|v| v.as_str())
+            .map(|v| v.trim_start_matches("v")),
+        response_data.get("name").and_then(|v| v.as_str()),
+        response_data.get("published_at").and_then(|v| v.as_str()),
+    ) {
+        let current_version = env!("CARGO_PKG_VERSION");
+        // println!("{} {}", tag_name, current_version);
+        if compare_versions(tag_name, current_version) {
+            return Ok(json!({
+                "new_version": true,
+                "name": name,
+                "published_at": published_at
+            }));
+        }
+    }
+
+    Ok(json!({
+        "new_version": false,
+        "name": "",
+        "published_at": ""
+    }))
+}
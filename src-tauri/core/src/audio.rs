@@ -1,9 +1,10 @@
-use crate::backend::book::Book;
-use crate::backend::cross_platform::get_app_data_path;
-use crate::backend::helpers::{clean_filename, get_book_index};
+use crate::book::Book;
+use crate::helpers::{clean_filename, get_book_index};
+use crate::jobs::CancelToken;
+use crate::output::{move_path, work_dir, OutputSettings};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::fs::{self, rename, write};
+use std::fs::{self, write};
 use std::path::PathBuf;
 use thiserror::Error;
 
@@ -21,12 +22,15 @@ pub enum BookAudioGeneratorError {
     IoError(#[from] std::io::Error),
     #[error("Serde JSON Error: {0}")]
     SerdeJsonError(#[from] serde_json::Error),
+    #[error("Cancelled")]
+    Cancelled,
 }
 pub async fn book_audio_generator(
     book: Book,
     info: Option<Value>,
+    cancel: &CancelToken,
 ) -> Result<PathBuf, BookAudioGeneratorError> {
-    let path = get_app_data_path(Some("books")).join(&book.title);
+    let path = OutputSettings::load().audio_dir(&book);
     if !path.exists() {
         fs::create_dir_all(&path)?;
     }
@@ -42,6 +46,9 @@ pub async fn book_audio_generator(
         .unwrap_or(0);
 
     for index in 1..=chapters {
+        if cancel.is_cancelled() {
+            return Err(BookAudioGeneratorError::Cancelled);
+        }
         let index = index as usize;
         let current_toc_item = toc.get(index - 1).cloned().unwrap_or(TocItem {
             title: format!("chapter-{:02}", index),
@@ -69,16 +76,18 @@ pub async fn book_audio_generator(
             path: clean_filename(&filename, " "),
         });
 
-        let new_path = get_app_data_path(Some("books"))
-            .join(clean_filename(&book.title, " "))
-            .join(clean_filename(&filename, " "));
+        let new_path = path.join(clean_filename(&filename, " "));
 
-        let old_path = get_app_data_path(Some("books"))
+        let old_path = work_dir()
             .join(&book.id)
             .join("Audio")
             .join(format!("chapter-{:03}.datx", index));
 
-        if let Err(e) = rename(&old_path, &new_path) {
+        // The output folder may be on another drive than the working one.
+        if new_path.is_file() {
+            fs::remove_file(&new_path)?;
+        }
+        if let Err(e) = move_path(&old_path, &new_path) {
             return Err(BookAudioGeneratorError::IoError(e));
         }
     }
@@ -89,9 +98,7 @@ pub async fn book_audio_generator(
         clean_filename(&book.title, " ")
     );
 
-    let m3u8_path = get_app_data_path(Some("books"))
-        .join(clean_filename(&book.title, " "))
-        .join(clean_filename(&m3u8_filename, " "));
+    let m3u8_path = path.join(clean_filename(&m3u8_filename, " "));
 
     let cover = info
         .as_ref()
@@ -0,0 +1,568 @@
+//! Command line front end to the backend, for headless servers and scheduled
+//! jobs. It shares the data dir, profiles, catalog and output folder with the
+//! app.
+
+use clap::{ArgAction, ArgGroup, Args, Parser, Subcommand};
+use jarir_reader_core::api_calls::{download_and_generate_book, login, logout, sync_library};
+use jarir_reader_core::book::Book;
+use jarir_reader_core::catalog::{BookQuery, BookSort, DownloadStatus};
+use jarir_reader_core::credentials::access_token;
+use jarir_reader_core::cross_platform::get_app_data_path;
+use jarir_reader_core::error::AppError;
+use jarir_reader_core::jobs::CancelToken;
+use jarir_reader_core::journal::{journals, Journal, Stage};
+use jarir_reader_core::library::{cached_books, query_books};
+use jarir_reader_core::logging;
+use jarir_reader_core::network::{build_client, NetworkSettings};
+use jarir_reader_core::output::output_dir;
+use jarir_reader_core::profiles::active_profile;
+use jarir_reader_core::progress::DownloadProgress;
+use jarir_reader_core::session::SessionManager;
+use jarir_reader_core::settings::Settings;
+use jarir_reader_core::storage::free_space;
+use jarir_reader_core::store::{current_store, store_for};
+use reqwest::Client;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::io::{self, BufRead, IsTerminal};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use tracing::level_filters::LevelFilter;
+
+#[derive(Parser)]
+#[command(
+    name = "jarir-reader-cli",
+    version,
+    about = "Download your purchased books and convert them to DRM-free files"
+)]
+struct Cli {
+    /// Print results as JSON instead of text.
+    #[arg(long, global = true)]
+    json: bool,
+    /// Show progress logs on stderr; twice for debug logs.
+    #[arg(short, long, global = true, action = ArgAction::Count)]
+    verbose: u8,
+    /// Keep settings, catalog and books in this folder instead of the app's.
+    #[arg(long, global = true, env = "JREADER_DATA_DIR")]
+    data_dir: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Log in to a store and fetch the book list.
+    Login {
+        #[arg(long)]
+        email: String,
+        /// Read from stdin when not given.
+        #[arg(long, env = "JREADER_PASSWORD", hide_env_values = true)]
+        password: Option<String>,
+        #[arg(long, default_value = "jarir", value_parser = ["jarir", "rufoof"])]
+        store: String,
+    },
+    /// Log out and remove the saved login. Converted books stay.
+    Logout,
+    /// List the books in the library.
+    List(ListArgs),
+    /// Download and convert books.
+    Download(DownloadArgs),
+    /// Finish converting books from their downloaded files, without
+    /// downloading them again.
+    Convert {
+        #[arg(required = true)]
+        book_ids: Vec<String>,
+    },
+    /// Show the login, the library and any unfinished jobs.
+    Status,
+}
+
+#[derive(Args)]
+struct ListArgs {
+    /// Part of the title or of an author's name.
+    #[arg(long)]
+    search: Option<String>,
+    /// `epub`, `pdf`, `mp3`, ...
+    #[arg(long = "type")]
+    book_type: Option<String>,
+    /// Exact author name.
+    #[arg(long)]
+    author: Option<String>,
+    /// `downloaded`, `not_downloaded` or `update_available`.
+    #[arg(long, value_parser = parse_enum::<DownloadStatus>)]
+    status: Option<DownloadStatus>,
+    /// `store`, `title`, `author`, `downloaded_at` or `size`.
+    #[arg(long, value_parser = parse_enum::<BookSort>)]
+    sort: Option<BookSort>,
+    #[arg(long)]
+    descending: bool,
+    #[arg(long)]
+    limit: Option<usize>,
+    #[arg(long, default_value_t = 0)]
+    offset: usize,
+    /// Fetch the book list from the store first.
+    #[arg(long)]
+    sync: bool,
+}
+
+#[derive(Args)]
+#[command(group(ArgGroup::new("books").required(true).args(["book_ids", "search", "all"])))]
+struct DownloadArgs {
+    book_ids: Vec<String>,
+    /// Every book whose title or author matches.
+    #[arg(long)]
+    search: Option<String>,
+    /// Every book that has not been converted yet.
+    #[arg(long)]
+    all: bool,
+    /// With `--all`, also books with a newer file on the store.
+    #[arg(long, requires = "all")]
+    updates: bool,
+    /// Fetch the book list from the store first.
+    #[arg(long)]
+    sync: bool,
+}
+
+/// Parses a value the way the frontend sends it, e.g. `not_downloaded`.
+fn parse_enum<T: DeserializeOwned>(value: &str) -> Result<T, String> {
+    serde_json::from_value(serde_json::Value::String(value.to_string())).map_err(|e| e.to_string())
+}
+
+struct Context {
+    client: Client,
+    session: SessionManager,
+    json: bool,
+}
+
+/// What `list` prints for a book; the download keys stay out of it.
+#[derive(Serialize)]
+struct BookRow {
+    id: String,
+    title: String,
+    authors: Vec<String>,
+    #[serde(rename = "type")]
+    book_type: String,
+    size: u64,
+    access: bool,
+    book_path: Option<String>,
+    update_available: bool,
+}
+
+impl From<Book> for BookRow {
+    fn from(book: Book) -> Self {
+        BookRow {
+            id: book.id,
+            title: book.title,
+            authors: book.authors,
+            book_type: book.book_type,
+            size: book.size,
+            access: book.access,
+            book_path: book.book_path,
+            update_available: book.update_available,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Outcome {
+    book_id: String,
+    title: String,
+    path: Option<String>,
+    error: Option<AppError>,
+}
+
+#[derive(Serialize)]
+struct Unfinished {
+    book_id: String,
+    stage: Option<Stage>,
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Status {
+    profile: String,
+    data_dir: String,
+    output_dir: String,
+    logged_in: bool,
+    username: Option<String>,
+    store: Option<String>,
+    token_expires: Option<u64>,
+    books: usize,
+    downloaded: usize,
+    updates: usize,
+    unfinished: Vec<Unfinished>,
+    free_space: Option<u64>,
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    // Set before the runtime starts any threads that could read it.
+    if let Some(dir) = &cli.data_dir {
+        std::env::set_var("JREADER_DATA_DIR", dir);
+    }
+    match tokio::runtime::Runtime::new() {
+        Ok(runtime) => runtime.block_on(start(cli)),
+        Err(e) => {
+            eprintln!("Could not start: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn start(cli: Cli) -> ExitCode {
+    logging::init(match cli.verbose {
+        0 => LevelFilter::WARN,
+        1 => LevelFilter::INFO,
+        _ => LevelFilter::DEBUG,
+    });
+
+    let client = build_client(&NetworkSettings::load()).unwrap_or_else(|e| {
+        tracing::warn!("Invalid network settings, using defaults: {}", e);
+        build_client(&NetworkSettings::default()).unwrap()
+    });
+    let context = Context {
+        client,
+        session: SessionManager::new(),
+        json: cli.json,
+    };
+    match run(&context, cli.command).await {
+        Ok(code) => code,
+        Err(e) => {
+            if context.json {
+                print_json(&serde_json::json!({ "error": e }));
+            } else {
+                print_error(&e);
+            }
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run(context: &Context, command: Command) -> Result<ExitCode, AppError> {
+    match command {
+        Command::Login {
+            email,
+            password,
+            store,
+        } => {
+            let password = match password {
+                Some(password) => password,
+                None => read_password()?,
+            };
+            let store = store_for(&store);
+            let session = login(&context.client, &*store, Some(&email), Some(&password)).await?;
+            let books = sync_library(&context.client, &*store, &context.session)
+                .await?
+                .books;
+            if context.json {
+                print_json(&serde_json::json!({
+                    "username": session.username,
+                    "store": session.app_type,
+                    "books": books.len(),
+                }));
+            } else {
+                println!(
+                    "Logged in to {} as {}, {} books",
+                    session.app_type,
+                    session.username,
+                    books.len()
+                );
+            }
+        }
+        Command::Logout => {
+            logout(&context.client, &*current_store()).await?;
+            if context.json {
+                print_json(&serde_json::json!({ "logged_out": true }));
+            } else {
+                println!("Logged out");
+            }
+        }
+        Command::List(args) => {
+            ensure_catalog(context, args.sync).await?;
+            let page = query_books(&BookQuery {
+                search: args.search,
+                book_type: args.book_type,
+                author: args.author,
+                status: args.status,
+                sort: args.sort.unwrap_or_default(),
+                descending: args.descending,
+                offset: args.offset,
+                limit: args.limit,
+            })?;
+            let rows: Vec<BookRow> = page.books.into_iter().map(BookRow::from).collect();
+            if context.json {
+                print_json(&serde_json::json!({ "total": page.total, "books": rows }));
+            } else {
+                for row in &rows {
+                    println!("{}", describe(row));
+                }
+                println!("{} of {} books", rows.len(), page.total);
+            }
+        }
+        Command::Download(args) => {
+            ensure_catalog(context, args.sync).await?;
+            let books = select_books(&args)?;
+            return Ok(convert_all(context, books).await);
+        }
+        Command::Convert { book_ids } => {
+            let known = cached_books()?;
+            let mut books = Vec::new();
+            for book_id in book_ids {
+                if Journal::load(&book_id).is_some_and(|j| j.resume_point().is_some()) {
+                    books.push(find_book(&known, &book_id));
+                } else {
+                    return Err(AppError::integrity(
+                        "1022",
+                        format!("Nothing to convert for {}, download it first", book_id),
+                    ));
+                }
+            }
+            return Ok(convert_all(context, books).await);
+        }
+        Command::Status => {
+            let status = status()?;
+            if context.json {
+                print_json(&status);
+            } else {
+                print_status(&status);
+            }
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Syncs with the store when asked to, or when there is no catalog yet.
+async fn ensure_catalog(context: &Context, sync: bool) -> Result<(), AppError> {
+    if sync || cached_books()?.is_empty() {
+        sync_library(&context.client, &*current_store(), &context.session).await?;
+    }
+    Ok(())
+}
+
+fn select_books(args: &DownloadArgs) -> Result<Vec<Book>, AppError> {
+    let known = cached_books()?;
+    if !args.book_ids.is_empty() {
+        return Ok(args
+            .book_ids
+            .iter()
+            .map(|id| find_book(&known, id))
+            .collect());
+    }
+    let books = match &args.search {
+        Some(search) => {
+            query_books(&BookQuery {
+                search: Some(search.clone()),
+                ..BookQuery::default()
+            })?
+            .books
+        }
+        None => known
+            .into_iter()
+            .filter(|b| b.book_path.is_none() || (args.updates && b.update_available))
+            .collect(),
+    };
+    Ok(books.into_iter().filter(|b| b.access).collect())
+}
+
+/// The catalog entry of `book_id`, or a bare one the pipeline looks up in
+/// the store.
+fn find_book(known: &[Book], book_id: &str) -> Book {
+    known
+        .iter()
+        .find(|b| b.id == book_id)
+        .cloned()
+        .unwrap_or_else(|| Book {
+            id: book_id.to_string(),
+            title: book_id.to_string(),
+            ..Book::default()
+        })
+}
+
+/// Downloads and converts `books` one after the other. Ctrl-C cancels the
+/// running one and skips the rest.
+async fn convert_all(context: &Context, books: Vec<Book>) -> ExitCode {
+    let cancel = CancelToken::new();
+    let on_interrupt = cancel.clone();
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            on_interrupt.cancel();
+        }
+    });
+
+    let store = current_store();
+    let show_progress = !context.json && io::stderr().is_terminal();
+    let mut outcomes = Vec::new();
+    for (index, book) in books.iter().enumerate() {
+        if !context.json {
+            println!("[{}/{}] {}", index + 1, books.len(), book.title);
+        }
+        let on_progress = |progress: DownloadProgress| {
+            if show_progress {
+                print_progress(&progress);
+            }
+        };
+        let result = download_and_generate_book(
+            &context.client,
+            &*store,
+            &context.session,
+            &book.id,
+            &on_progress,
+            &cancel,
+        )
+        .await;
+        if show_progress {
+            eprint!("\r\x1b[K");
+        }
+        match &result {
+            Ok(path) if !context.json => println!("  {}", path),
+            Err(e) if !context.json => print_error(e),
+            _ => {}
+        }
+        outcomes.push(Outcome {
+            book_id: book.id.clone(),
+            title: book.title.clone(),
+            path: result.as_ref().ok().cloned(),
+            error: result.err(),
+        });
+        if cancel.is_cancelled() {
+            break;
+        }
+    }
+
+    let failed = outcomes.iter().filter(|o| o.error.is_some()).count();
+    if context.json {
+        print_json(&outcomes);
+    } else {
+        println!(
+            "{} converted, {} failed, {} skipped",
+            outcomes.len() - failed,
+            failed,
+            books.len() - outcomes.len()
+        );
+    }
+    if failed == 0 && outcomes.len() == books.len() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+fn status() -> Result<Status, AppError> {
+    let settings = Settings::load()?;
+    let books = cached_books()?;
+    let unfinished = journals()
+        .into_iter()
+        .map(|journal| Unfinished {
+            stage: journal.resume_point().map(|record| record.stage),
+            book_id: journal.book_id,
+            error: journal.error,
+        })
+        .collect();
+    let output = output_dir();
+    Ok(Status {
+        profile: active_profile(),
+        data_dir: get_app_data_path(None).display().to_string(),
+        output_dir: output.display().to_string(),
+        logged_in: access_token()?.is_some(),
+        username: settings.username,
+        store: settings.app,
+        token_expires: settings.expires,
+        downloaded: books.iter().filter(|b| b.book_path.is_some()).count(),
+        updates: books.iter().filter(|b| b.update_available).count(),
+        books: books.len(),
+        unfinished,
+        free_space: free_space(&output).ok(),
+    })
+}
+
+/// Asks without echoing on a terminal; piped passwords are read as a line.
+fn read_password() -> Result<String, AppError> {
+    if io::stdin().is_terminal() {
+        return Ok(rpassword::prompt_password("Password: ")?);
+    }
+    let mut password = String::new();
+    io::stdin().lock().read_line(&mut password)?;
+    Ok(password.trim_end_matches(['\r', '\n']).to_string())
+}
+
+fn describe(row: &BookRow) -> String {
+    let state = if row.update_available {
+        " [update]"
+    } else if row.book_path.is_some() {
+        " [downloaded]"
+    } else if !row.access {
+        " [no access]"
+    } else {
+        ""
+    };
+    format!(
+        "{:>8}  {:<5} {} - {}{}",
+        row.id,
+        row.book_type,
+        row.title,
+        row.authors.join(", "),
+        state
+    )
+}
+
+fn print_progress(progress: &DownloadProgress) {
+    let downloaded = megabytes(progress.downloaded);
+    match progress.total {
+        Some(total) => eprint!(
+            "\r\x1b[K  {:.1}/{:.1} MB ({:.1} MB/s)",
+            downloaded,
+            megabytes(total),
+            megabytes(progress.rate)
+        ),
+        None => eprint!("\r\x1b[K  {:.1} MB", downloaded),
+    }
+}
+
+fn print_status(status: &Status) {
+    match (&status.username, status.logged_in) {
+        (Some(username), true) => println!(
+            "Logged in to {} as {}",
+            status.store.as_deref().unwrap_or("jarir"),
+            username
+        ),
+        _ => println!("Not logged in"),
+    }
+    println!("Profile: {}", status.profile);
+    println!("Data: {}", status.data_dir);
+    println!("Books: {}", status.output_dir);
+    if let Some(free) = status.free_space {
+        println!("Free space: {:.1} MB", megabytes(free));
+    }
+    println!(
+        "{} books, {} downloaded, {} with updates",
+        status.books, status.downloaded, status.updates
+    );
+    for job in &status.unfinished {
+        let stage = job
+            .stage
+            .map(|stage| format!("{:?}", stage).to_lowercase())
+            .unwrap_or_else(|| "nothing".to_string());
+        match &job.error {
+            Some(error) => println!("  {}: failed after {}: {}", job.book_id, stage, error),
+            None => println!("  {}: interrupted after {}", job.book_id, stage),
+        }
+    }
+}
+
+fn print_error(error: &AppError) {
+    eprintln!("error: {}", error);
+    if let Some(detail) = error.detail() {
+        eprintln!("  {}", logging::redact(detail));
+    }
+}
+
+fn print_json<T: Serialize + ?Sized>(value: &T) {
+    println!(
+        "{}",
+        serde_json::to_string_pretty(value).expect("CLI output is always serialisable")
+    );
+}
+
+fn megabytes(bytes: u64) -> f64 {
+    bytes as f64 / (1024.0 * 1024.0)
+}
@@ -1,6 +1,7 @@
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
 pub struct Book {
     pub id: String,
     pub title: String,
@@ -21,6 +22,10 @@ pub struct Book {
     pub latest_file_id: String,
     pub size: u64,
     pub downloaded_at: Option<u64>,
+    /// The file that was converted into `book_path`.
+    pub downloaded_file_id: Option<String>,
+    /// The store has a newer file than the downloaded one.
+    pub update_available: bool,
 }
 
 impl Default for Book {
@@ -46,6 +51,19 @@ impl Default for Book {
             latest_file_id: "".to_string(),
             size: 0,
             downloaded_at: None,
+            downloaded_file_id: None,
+            update_available: false,
+        }
+    }
+}
+
+impl Book {
+    /// The newest file of the book, falling back to the one it was bought
+    /// with when the store does not list a newer one.
+    pub fn current_file_id(&self) -> &str {
+        match self.latest_file_id.as_str() {
+            "" | "0" => &self.file_id,
+            latest => latest,
         }
     }
 }
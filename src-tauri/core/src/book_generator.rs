@@ -0,0 +1,95 @@
+#[cfg(feature = "audio")]
+use crate::audio::{book_audio_generator, BookAudioGeneratorError};
+use crate::book::Book;
+#[cfg(feature = "epub")]
+use crate::epub::{book_epub_generator, EpubError};
+#[cfg(feature = "pdf")]
+use crate::helpers::clean_filename;
+use crate::helpers::get_book_index;
+use crate::jobs::CancelToken;
+#[cfg(feature = "pdf")]
+use crate::output::{move_path, work_dir, OutputSettings};
+use std::path::PathBuf;
+use thiserror::Error;
+
+/// Book types the store sells, whether or not this build can convert them.
+const KNOWN_TYPES: [&str; 3] = ["epub", "mp3", "pdf"];
+
+#[derive(Debug, Error)]
+pub enum BookGeneratorError {
+    #[error("Failed to parse info JSON file")]
+    ParseError,
+    #[error("File type {0} is not supported yet")]
+    UnsupportedFileType(String),
+    #[error("Support for {0} books was left out of this build")]
+    LeftOut(String),
+    #[error("IO Error: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("Tokio Join Error: {0}")]
+    JoinError(#[from] tokio::task::JoinError),
+    #[cfg(feature = "epub")]
+    #[error("EPUB Error: {0}")]
+    EpubError(#[from] EpubError),
+    #[cfg(feature = "audio")]
+    #[error("Audio Error: {0}")]
+    AudioError(#[from] BookAudioGeneratorError),
+    #[error("Cancelled")]
+    Cancelled,
+}
+
+/// Builds the output of the book extracted in its working folder. `cover`
+/// goes into EPUBs; the caller fetches it with the shared client or reads it
+/// from a kept source.
+#[cfg_attr(not(feature = "epub"), allow(unused_variables))]
+pub async fn book_generator(
+    book: Book,
+    cover: Option<&[u8]>,
+    cancel: &CancelToken,
+) -> Result<PathBuf, BookGeneratorError> {
+    if cancel.is_cancelled() {
+        return Err(BookGeneratorError::Cancelled);
+    }
+
+    let info: serde_json::Value =
+        get_book_index(&book.id, "info").ok_or(BookGeneratorError::ParseError)?;
+
+    let book_type = info["type"].as_str().unwrap_or_default();
+
+    match book_type {
+        #[cfg(feature = "audio")]
+        "mp3" => {
+            let res = book_audio_generator(book, Some(info), cancel).await?;
+            Ok(res)
+        }
+        #[cfg(feature = "pdf")]
+        "pdf" => {
+            let output_dir = OutputSettings::load().book_dir(&book);
+            tokio::fs::create_dir_all(&output_dir).await?;
+            let new_path = output_dir.join(format!("{}.pdf", clean_filename(&book.title, " ")));
+            let old_path = work_dir().join(&book.id).join("Text").join("DATA.DATA");
+
+            // Copied when the output folder is on another drive, which can
+            // take a while for a large file.
+            let target = new_path.clone();
+            tokio::task::spawn_blocking(move || {
+                if target.is_file() {
+                    std::fs::remove_file(&target)?;
+                }
+                move_path(&old_path, &target)
+            })
+            .await??;
+            Ok(new_path)
+        }
+        #[cfg(feature = "epub")]
+        "epub" => {
+            let res = book_epub_generator(book, Some(info), cover, cancel).await?;
+            Ok(res)
+        }
+        _ if KNOWN_TYPES.contains(&book_type) => {
+            Err(BookGeneratorError::LeftOut(book_type.to_string()))
+        }
+        _ => Err(BookGeneratorError::UnsupportedFileType(
+            book_type.to_string(),
+        )),
+    }
+}
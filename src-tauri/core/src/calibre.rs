@@ -0,0 +1,163 @@
+use crate::book::Book;
+use crate::helpers::get_book_index;
+use crate::settings::Settings;
+use regex::Regex;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+pub const METADATA: &str = "metadata.opf";
+pub const COVER: &str = "cover.jpg";
+
+/// The OPF Calibre reads when adding a book from its folder. The store id is
+/// the unique identifier and shows up in Calibre as `<store>:<id>`.
+pub fn metadata_opf(book: &Book, store: &str, language: &str, has_cover: bool) -> String {
+    let mut metadata = vec![
+        format!(
+            r#"<dc:identifier opf:scheme="{}" id="store_id">{}</dc:identifier>"#,
+            escape(store),
+            escape(&book.id)
+        ),
+        format!("<dc:title>{}</dc:title>", escape(&book.title)),
+    ];
+    for author in &book.authors {
+        metadata.push(format!(
+            r#"<dc:creator opf:role="aut">{}</dc:creator>"#,
+            escape(author)
+        ));
+    }
+    if !book.publisher.is_empty() {
+        metadata.push(format!(
+            "<dc:publisher>{}</dc:publisher>",
+            escape(&book.publisher)
+        ));
+    }
+    metadata.push(format!("<dc:language>{}</dc:language>", escape(language)));
+    for tag in [store, book.book_type.as_str()] {
+        if !tag.is_empty() {
+            metadata.push(format!("<dc:subject>{}</dc:subject>", escape(tag)));
+        }
+    }
+
+    let guide = if has_cover {
+        format!(
+            "\n  <guide>\n    <reference type=\"cover\" title=\"Cover\" href=\"{}\"/>\n  </guide>",
+            COVER
+        )
+    } else {
+        String::new()
+    };
+    format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" unique-identifier="store_id" version="2.0">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:opf="http://www.idpf.org/2007/opf">
+    {}
+  </metadata>{}
+</package>
+"#,
+        metadata.join("\n    "),
+        guide
+    )
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// The folder holding a converted book: the book itself for audio books,
+/// otherwise the one its file is in.
+fn book_folder(book_path: &Path) -> Option<&Path> {
+    if book_path.is_dir() {
+        Some(book_path)
+    } else {
+        book_path.parent()
+    }
+}
+
+/// The store a book came from, as recorded in the `metadata.opf` next to it,
+/// or the one logged in to when there is none. Logging out forgets the
+/// latter, so callers that know the store pass it to `write_sidecars`.
+pub fn recorded_store(book_path: &Path) -> String {
+    let scheme = Regex::new(r#"opf:scheme="([^"]*)" id="store_id""#).unwrap();
+    book_folder(book_path)
+        .and_then(|folder| fs::read_to_string(folder.join(METADATA)).ok())
+        .and_then(|opf| scheme.captures(&opf).map(|c| c[1].to_string()))
+        .filter(|store| !store.is_empty())
+        .or_else(|| Settings::load().ok().and_then(|settings| settings.app))
+        .unwrap_or_default()
+}
+
+/// Writes `metadata.opf`, and `cover.jpg` when there is a cover, next to the
+/// converted book at `book_path`. The language is read from the book's info
+/// while its extraction folder is there, and is Arabic otherwise.
+pub fn write_sidecars(
+    book: &Book,
+    book_path: &Path,
+    store: &str,
+    cover: Option<&[u8]>,
+) -> io::Result<()> {
+    let Some(folder) = book_folder(book_path) else {
+        return Ok(());
+    };
+    if let Some(cover) = cover {
+        fs::write(folder.join(COVER), cover)?;
+    }
+    let has_cover = folder.join(COVER).exists();
+    let language = get_book_index(&book.id, "info")
+        .and_then(|info| info["language"].as_str().map(str::to_string))
+        .unwrap_or_else(|| "ar".to_string());
+    let opf = metadata_opf(book, store, &language, has_cover);
+    fs::write(folder.join(METADATA), opf)
+}
+
+/// The cover written next to the converted book at `book_path`, if any.
+pub fn read_cover(book_path: &Path) -> Option<Vec<u8>> {
+    fs::read(book_folder(book_path)?.join(COVER)).ok()
+}
+
+/// Removes what `write_sidecars` left next to the book at `book_path`, so
+/// its folder can go once the book is moved or deleted.
+pub fn remove_sidecars(book_path: &Path) -> io::Result<()> {
+    let Some(folder) = book_folder(book_path) else {
+        return Ok(());
+    };
+    for name in [METADATA, COVER] {
+        let path = folder.join(name);
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metadata_opf_lists_identifiers_and_tags() {
+        let book = Book {
+            id: "1234".to_string(),
+            title: "Tom & Jerry".to_string(),
+            book_type: "epub".to_string(),
+            publisher: "جرير للنشر".to_string(),
+            authors: vec!["First".to_string(), "Second".to_string()],
+            ..Book::default()
+        };
+        let opf = metadata_opf(&book, "jarir", "ar", true);
+        assert!(
+            opf.contains(r#"<dc:identifier opf:scheme="jarir" id="store_id">1234</dc:identifier>"#)
+        );
+        assert!(opf.contains("<dc:title>Tom &amp; Jerry</dc:title>"));
+        assert!(opf.contains(r#"<dc:creator opf:role="aut">Second</dc:creator>"#));
+        assert!(opf.contains("<dc:publisher>جرير للنشر</dc:publisher>"));
+        assert!(opf.contains("<dc:language>ar</dc:language>"));
+        assert!(opf.contains("<dc:subject>jarir</dc:subject>"));
+        assert!(opf.contains("<dc:subject>epub</dc:subject>"));
+        assert!(opf.contains(r#"href="cover.jpg""#));
+        assert!(!metadata_opf(&book, "jarir", "ar", false).contains("<guide>"));
+    }
+}
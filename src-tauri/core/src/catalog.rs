@@ -0,0 +1,737 @@
+use crate::book::Book;
+use crate::error::AppError;
+use crate::profiles::{active_profile, profile_dir};
+use crate::settings::Settings;
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension, TransactionBehavior};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::Duration;
+
+/// Bumped with every entry added to `MIGRATIONS`.
+const SCHEMA_VERSION: usize = 1;
+
+/// `MIGRATIONS[n]` brings a catalog from schema version `n` to `n + 1`.
+const MIGRATIONS: [&str; SCHEMA_VERSION] = ["
+    CREATE TABLE books (
+        id TEXT PRIMARY KEY,
+        position INTEGER NOT NULL,
+        title TEXT NOT NULL,
+        name TEXT NOT NULL,
+        type TEXT NOT NULL,
+        publisher TEXT NOT NULL,
+        url TEXT NOT NULL,
+        cover TEXT,
+        thumb TEXT,
+        access INTEGER NOT NULL,
+        update_available INTEGER NOT NULL
+    );
+    CREATE TABLE authors (
+        id INTEGER PRIMARY KEY,
+        name TEXT NOT NULL UNIQUE
+    );
+    CREATE TABLE book_authors (
+        book_id TEXT NOT NULL REFERENCES books(id) ON DELETE CASCADE,
+        author_id INTEGER NOT NULL REFERENCES authors(id),
+        position INTEGER NOT NULL,
+        PRIMARY KEY (book_id, author_id)
+    );
+    CREATE TABLE files (
+        book_id TEXT PRIMARY KEY REFERENCES books(id) ON DELETE CASCADE,
+        file_id TEXT NOT NULL,
+        latest_file_id TEXT NOT NULL,
+        file_md5 TEXT NOT NULL,
+        size INTEGER NOT NULL,
+        header TEXT NOT NULL,
+        key TEXT NOT NULL
+    );
+    CREATE TABLE downloads (
+        book_id TEXT PRIMARY KEY REFERENCES books(id) ON DELETE CASCADE,
+        book_path TEXT NOT NULL,
+        file_id TEXT,
+        downloaded_at INTEGER
+    );
+    CREATE TABLE conversions (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        book_id TEXT NOT NULL,
+        file_id TEXT,
+        started_at INTEGER NOT NULL,
+        finished_at INTEGER NOT NULL,
+        status TEXT NOT NULL,
+        output TEXT,
+        error TEXT
+    );
+    CREATE TABLE meta (
+        key TEXT PRIMARY KEY,
+        value INTEGER NOT NULL
+    );
+    CREATE INDEX book_authors_author ON book_authors(author_id);
+    CREATE INDEX conversions_book ON conversions(book_id);
+"];
+
+const SELECT_BOOKS: &str = "
+    SELECT b.id, b.title, b.name, b.type, b.publisher, b.url, b.cover, b.thumb, b.access,
+           b.update_available, f.file_id, f.latest_file_id, f.file_md5, f.size, f.header, f.key,
+           d.book_path, d.file_id, d.downloaded_at
+    FROM books b
+    LEFT JOIN files f ON f.book_id = b.id
+    LEFT JOIN downloads d ON d.book_id = b.id";
+
+/// First author of a book, for sorting.
+const FIRST_AUTHOR: &str =
+    "(SELECT a.name FROM book_authors ba JOIN authors a ON a.id = ba.author_id
+    WHERE ba.book_id = b.id ORDER BY ba.position LIMIT 1)";
+
+fn db_error(e: rusqlite::Error) -> AppError {
+    AppError::io("1015", "Could not access the library catalog").with_detail(e)
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BookSort {
+    /// The order the store lists the books in.
+    #[default]
+    Store,
+    Title,
+    Author,
+    DownloadedAt,
+    Size,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DownloadStatus {
+    Downloaded,
+    NotDownloaded,
+    UpdateAvailable,
+}
+
+/// A search over the catalog, as sent by the frontend. Every filter is
+/// optional.
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct BookQuery {
+    /// Part of the title or of an author's name.
+    pub search: Option<String>,
+    #[serde(rename = "type")]
+    pub book_type: Option<String>,
+    /// Exact author name.
+    pub author: Option<String>,
+    pub status: Option<DownloadStatus>,
+    pub sort: BookSort,
+    pub descending: bool,
+    pub offset: usize,
+    /// Page size; everything from `offset` on when unset.
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BookPage {
+    /// Matches before paging.
+    pub total: usize,
+    pub offset: usize,
+    pub books: Vec<Book>,
+}
+
+/// One finished or failed attempt at converting a book.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct Conversion {
+    pub book_id: String,
+    pub file_id: Option<String>,
+    /// Unix time in seconds.
+    pub started_at: u64,
+    pub finished_at: u64,
+    /// `done`, `failed` or `cancelled`.
+    pub status: String,
+    pub output: Option<String>,
+    pub error: Option<String>,
+}
+
+/// The catalog kept by versions before this one under `books` in
+/// `settings.json`.
+#[derive(Debug, Deserialize, Default)]
+#[serde(default)]
+struct LegacyCache {
+    /// Milliseconds since the epoch.
+    cached_at: u64,
+    items: Vec<Book>,
+}
+
+/// The library of one profile in `library.db`. Each call opens its own
+/// connection; SQLite takes care of concurrent writers.
+pub struct Catalog {
+    conn: Connection,
+}
+
+impl Catalog {
+    /// The catalog of the active profile.
+    pub fn open() -> Result<Catalog, AppError> {
+        Self::open_for(&active_profile())
+    }
+
+    /// The catalog of `profile`, importing the old JSON cache on first use.
+    pub fn open_for(profile: &str) -> Result<Catalog, AppError> {
+        let mut catalog = Self::open_at(&profile_dir(profile).join("library.db"))?;
+        if catalog.meta("legacy_imported")?.is_none() {
+            catalog.import_legacy(profile)?;
+        }
+        Ok(catalog)
+    }
+
+    pub fn open_at(path: &Path) -> Result<Catalog, AppError> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut conn = Connection::open(path).map_err(db_error)?;
+        conn.busy_timeout(Duration::from_secs(5))
+            .map_err(db_error)?;
+        conn.pragma_update(None, "foreign_keys", true)
+            .map_err(db_error)?;
+        migrate(&mut conn)?;
+        Ok(Catalog { conn })
+    }
+
+    /// Moves `books` out of the profile's settings. Written to the catalog
+    /// before it is removed from the settings, so a failure loses nothing.
+    fn import_legacy(&mut self, profile: &str) -> Result<(), AppError> {
+        let legacy = Settings::load_for(profile)?.other.get("books").cloned();
+        if let Some(value) = legacy {
+            match serde_json::from_value::<LegacyCache>(value) {
+                Ok(cache) => {
+                    self.import_cache(cache)?;
+                    tracing::info!("Moved the book list out of settings.json");
+                }
+                Err(e) => tracing::warn!("Dropping unreadable book list: {}", e),
+            }
+            Settings::update_for(profile, |s| s.other.remove("books"))?;
+        }
+        self.set_meta("legacy_imported", 1)
+    }
+
+    fn import_cache(&mut self, cache: LegacyCache) -> Result<(), AppError> {
+        self.replace_all(|_| (cache.items, ()), cache.cached_at)
+    }
+
+    fn meta(&self, key: &str) -> Result<Option<u64>, AppError> {
+        self.conn
+            .query_row("SELECT value FROM meta WHERE key = ?1", [key], |row| {
+                row.get::<_, i64>(0)
+            })
+            .optional()
+            .map(|v| v.map(|v| v as u64))
+            .map_err(db_error)
+    }
+
+    fn set_meta(&self, key: &str, value: u64) -> Result<(), AppError> {
+        self.conn
+            .execute(
+                "INSERT OR REPLACE INTO meta (key, value) VALUES (?1, ?2)",
+                params![key, value as i64],
+            )
+            .map(|_| ())
+            .map_err(db_error)
+    }
+
+    /// When the store list was last synced, in milliseconds.
+    pub fn synced_at(&self) -> Result<Option<u64>, AppError> {
+        self.meta("synced_at")
+    }
+
+    /// Every book in store order.
+    pub fn books(&self) -> Result<Vec<Book>, AppError> {
+        self.query(&BookQuery::default()).map(|page| page.books)
+    }
+
+    pub fn book(&self, book_id: &str) -> Result<Option<Book>, AppError> {
+        load_book(&self.conn, book_id)
+    }
+
+    /// Replaces the whole list with what `merge` makes of the current one,
+    /// in a single transaction so no other writer sneaks in between.
+    pub fn replace_all<T>(
+        &mut self,
+        merge: impl FnOnce(Vec<Book>) -> (Vec<Book>, T),
+        synced_at: u64,
+    ) -> Result<T, AppError> {
+        let tx = self
+            .conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .map_err(db_error)?;
+        let local = query_books(&tx, &BookQuery::default())?.books;
+        let (books, result) = merge(local);
+
+        let write = || -> rusqlite::Result<()> {
+            tx.execute("DELETE FROM books", [])?;
+            for (position, book) in books.iter().enumerate() {
+                write_book(&tx, position as i64, book)?;
+            }
+            tx.execute(
+                "DELETE FROM authors WHERE id NOT IN (SELECT author_id FROM book_authors)",
+                [],
+            )?;
+            tx.execute(
+                "INSERT OR REPLACE INTO meta (key, value) VALUES ('synced_at', ?1)",
+                [synced_at as i64],
+            )?;
+            Ok(())
+        };
+        write().map_err(db_error)?;
+        tx.commit().map_err(db_error)?;
+        Ok(result)
+    }
+
+    /// Changes one book in place. Returns false when it is not in the
+    /// catalog.
+    pub fn update_book(
+        &mut self,
+        book_id: &str,
+        update: impl FnOnce(&mut Book),
+    ) -> Result<bool, AppError> {
+        let tx = self
+            .conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .map_err(db_error)?;
+        let Some(mut book) = load_book(&tx, book_id)? else {
+            return Ok(false);
+        };
+        update(&mut book);
+        let position: i64 = tx
+            .query_row(
+                "SELECT position FROM books WHERE id = ?1",
+                [book_id],
+                |row| row.get(0),
+            )
+            .map_err(db_error)?;
+        write_book(&tx, position, &book).map_err(db_error)?;
+        tx.commit().map_err(db_error)?;
+        Ok(true)
+    }
+
+    /// Forgets every book and conversion, as if the store was never synced.
+    pub fn clear(&mut self) -> Result<(), AppError> {
+        let tx = self
+            .conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .map_err(db_error)?;
+        tx.execute_batch(
+            "DELETE FROM books;
+            DELETE FROM authors;
+            DELETE FROM conversions;
+            DELETE FROM meta WHERE key = 'synced_at';",
+        )
+        .map_err(db_error)?;
+        tx.commit().map_err(db_error)
+    }
+
+    pub fn query(&self, query: &BookQuery) -> Result<BookPage, AppError> {
+        query_books(&self.conn, query)
+    }
+
+    pub fn record_conversion(&self, conversion: &Conversion) -> Result<(), AppError> {
+        self.conn
+            .execute(
+                "INSERT INTO conversions
+                    (book_id, file_id, started_at, finished_at, status, output, error)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![
+                    conversion.book_id,
+                    conversion.file_id,
+                    conversion.started_at as i64,
+                    conversion.finished_at as i64,
+                    conversion.status,
+                    conversion.output,
+                    conversion.error,
+                ],
+            )
+            .map(|_| ())
+            .map_err(db_error)
+    }
+
+    /// Conversions of a book, newest first.
+    pub fn conversions(&self, book_id: &str) -> Result<Vec<Conversion>, AppError> {
+        let mut statement = self
+            .conn
+            .prepare(
+                "SELECT book_id, file_id, started_at, finished_at, status, output, error
+                 FROM conversions WHERE book_id = ?1 ORDER BY id DESC",
+            )
+            .map_err(db_error)?;
+        let rows = statement
+            .query_map([book_id], |row| {
+                Ok(Conversion {
+                    book_id: row.get(0)?,
+                    file_id: row.get(1)?,
+                    started_at: row.get::<_, i64>(2)? as u64,
+                    finished_at: row.get::<_, i64>(3)? as u64,
+                    status: row.get(4)?,
+                    output: row.get(5)?,
+                    error: row.get(6)?,
+                })
+            })
+            .map_err(db_error)?;
+        rows.collect::<rusqlite::Result<_>>().map_err(db_error)
+    }
+}
+
+fn migrate(conn: &mut Connection) -> Result<(), AppError> {
+    let version: usize = conn
+        .pragma_query_value(None, "user_version", |row| row.get(0))
+        .map_err(db_error)?;
+    if version > SCHEMA_VERSION {
+        return Err(AppError::integrity(
+            "1016",
+            "The library catalog was saved by a newer version of the app",
+        )
+        .with_detail(format!("schema version {}", version)));
+    }
+
+    let tx = conn.transaction().map_err(db_error)?;
+    for (from, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        tx.execute_batch(migration)
+            .and_then(|_| tx.pragma_update(None, "user_version", from + 1))
+            .map_err(db_error)?;
+    }
+    tx.commit().map_err(db_error)
+}
+
+fn write_book(conn: &Connection, position: i64, book: &Book) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO books
+            (id, position, title, name, type, publisher, url, cover, thumb, access, update_available)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)
+         ON CONFLICT(id) DO UPDATE SET
+            position = excluded.position, title = excluded.title, name = excluded.name,
+            type = excluded.type, publisher = excluded.publisher, url = excluded.url,
+            cover = excluded.cover, thumb = excluded.thumb, access = excluded.access,
+            update_available = excluded.update_available",
+        params![
+            book.id,
+            position,
+            book.title,
+            book.name,
+            book.book_type,
+            book.publisher,
+            book.url,
+            book.cover,
+            book.thumb,
+            book.access,
+            book.update_available,
+        ],
+    )?;
+    conn.execute(
+        "INSERT OR REPLACE INTO files
+            (book_id, file_id, latest_file_id, file_md5, size, header, key)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![
+            book.id,
+            book.file_id,
+            book.latest_file_id,
+            book.file_md5,
+            book.size as i64,
+            book.header,
+            serde_json::to_string(&book.key).unwrap_or_default(),
+        ],
+    )?;
+
+    match &book.book_path {
+        Some(path) => conn.execute(
+            "INSERT OR REPLACE INTO downloads (book_id, book_path, file_id, downloaded_at)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![
+                book.id,
+                path,
+                book.downloaded_file_id,
+                book.downloaded_at.map(|t| t as i64),
+            ],
+        )?,
+        None => conn.execute("DELETE FROM downloads WHERE book_id = ?1", [&book.id])?,
+    };
+
+    conn.execute("DELETE FROM book_authors WHERE book_id = ?1", [&book.id])?;
+    for (position, author) in book.authors.iter().enumerate() {
+        conn.execute("INSERT OR IGNORE INTO authors (name) VALUES (?1)", [author])?;
+        conn.execute(
+            "INSERT OR IGNORE INTO book_authors (book_id, author_id, position)
+             SELECT ?1, id, ?2 FROM authors WHERE name = ?3",
+            params![book.id, position as i64, author],
+        )?;
+    }
+    Ok(())
+}
+
+fn read_book(conn: &Connection, row: &rusqlite::Row) -> rusqlite::Result<Book> {
+    let id: String = row.get(0)?;
+    let key = row
+        .get::<_, Option<String>>(15)?
+        .and_then(|key| serde_json::from_str(&key).ok())
+        .unwrap_or_else(|| Book::default().key);
+    let authors = conn
+        .prepare_cached(
+            "SELECT a.name FROM book_authors ba JOIN authors a ON a.id = ba.author_id
+             WHERE ba.book_id = ?1 ORDER BY ba.position",
+        )?
+        .query_map([&id], |row| row.get(0))?
+        .collect::<rusqlite::Result<_>>()?;
+
+    Ok(Book {
+        title: row.get(1)?,
+        name: row.get(2)?,
+        book_type: row.get(3)?,
+        publisher: row.get(4)?,
+        url: row.get(5)?,
+        cover: row.get(6)?,
+        thumb: row.get(7)?,
+        access: row.get(8)?,
+        update_available: row.get(9)?,
+        file_id: row.get::<_, Option<String>>(10)?.unwrap_or_default(),
+        latest_file_id: row.get::<_, Option<String>>(11)?.unwrap_or_default(),
+        file_md5: row.get::<_, Option<String>>(12)?.unwrap_or_default(),
+        size: row.get::<_, Option<i64>>(13)?.unwrap_or_default() as u64,
+        header: row.get::<_, Option<String>>(14)?.unwrap_or_default(),
+        key,
+        book_path: row.get(16)?,
+        downloaded_file_id: row.get(17)?,
+        downloaded_at: row.get::<_, Option<i64>>(18)?.map(|t| t as u64),
+        authors,
+        id,
+    })
+}
+
+fn load_book(conn: &Connection, book_id: &str) -> Result<Option<Book>, AppError> {
+    conn.query_row(
+        &format!("{} WHERE b.id = ?1", SELECT_BOOKS),
+        [book_id],
+        |row| read_book(conn, row),
+    )
+    .optional()
+    .map_err(db_error)
+}
+
+fn query_books(conn: &Connection, query: &BookQuery) -> Result<BookPage, AppError> {
+    let mut conditions: Vec<String> = Vec::new();
+    let mut values: Vec<Value> = Vec::new();
+    let mut bind = |value: Value| {
+        values.push(value);
+        values.len()
+    };
+
+    if let Some(search) = query
+        .search
+        .as_deref()
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+    {
+        let escaped = search
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_");
+        let n = bind(Value::Text(format!("%{}%", escaped)));
+        conditions.push(format!(
+            "(b.title LIKE ?{n} ESCAPE '\\' OR EXISTS (SELECT 1 FROM book_authors ba
+              JOIN authors a ON a.id = ba.author_id
+              WHERE ba.book_id = b.id AND a.name LIKE ?{n} ESCAPE '\\'))"
+        ));
+    }
+    if let Some(book_type) = &query.book_type {
+        let n = bind(Value::Text(book_type.clone()));
+        conditions.push(format!("b.type = ?{n}"));
+    }
+    if let Some(author) = &query.author {
+        let n = bind(Value::Text(author.clone()));
+        conditions.push(format!(
+            "EXISTS (SELECT 1 FROM book_authors ba JOIN authors a ON a.id = ba.author_id
+              WHERE ba.book_id = b.id AND a.name = ?{n})"
+        ));
+    }
+    match query.status {
+        Some(DownloadStatus::Downloaded) => conditions.push("d.book_path IS NOT NULL".into()),
+        Some(DownloadStatus::NotDownloaded) => conditions.push("d.book_path IS NULL".into()),
+        Some(DownloadStatus::UpdateAvailable) => conditions.push("b.update_available".into()),
+        None => {}
+    }
+
+    let filter = if conditions.is_empty() {
+        String::new()
+    } else {
+        format!(" WHERE {}", conditions.join(" AND "))
+    };
+    let total: i64 = conn
+        .query_row(
+            &format!(
+                "SELECT COUNT(*) FROM books b LEFT JOIN downloads d ON d.book_id = b.id{}",
+                filter
+            ),
+            params_from_iter(values.iter()),
+            |row| row.get(0),
+        )
+        .map_err(db_error)?;
+
+    let order = match query.sort {
+        BookSort::Store => "b.position",
+        BookSort::Title => "b.title",
+        BookSort::Author => FIRST_AUTHOR,
+        BookSort::DownloadedAt => "d.downloaded_at",
+        BookSort::Size => "f.size",
+    };
+    let direction = if query.descending { "DESC" } else { "ASC" };
+    let limit = query.limit.map_or(-1, |limit| limit as i64);
+    let sql = format!(
+        "{}{} ORDER BY {} {}, b.position LIMIT {} OFFSET {}",
+        SELECT_BOOKS, filter, order, direction, limit, query.offset
+    );
+
+    let mut statement = conn.prepare(&sql).map_err(db_error)?;
+    let books = statement
+        .query_map(params_from_iter(values.iter()), |row| read_book(conn, row))
+        .map_err(db_error)?
+        .collect::<rusqlite::Result<_>>()
+        .map_err(db_error)?;
+
+    Ok(BookPage {
+        total: total as usize,
+        offset: query.offset,
+        books,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_catalog() -> (Catalog, std::path::PathBuf) {
+        let dir = std::env::temp_dir().join(format!("jreader-catalog-{}", uuid::Uuid::new_v4()));
+        (Catalog::open_at(&dir.join("library.db")).unwrap(), dir)
+    }
+
+    fn book(id: &str, title: &str, authors: &[&str]) -> Book {
+        Book {
+            id: id.to_string(),
+            title: title.to_string(),
+            name: title.to_string(),
+            book_type: "epub".to_string(),
+            authors: authors.iter().map(|a| a.to_string()).collect(),
+            access: true,
+            file_id: format!("f{}", id),
+            ..Book::default()
+        }
+    }
+
+    fn query(catalog: &Catalog, query: BookQuery) -> BookPage {
+        catalog.query(&query).unwrap()
+    }
+
+    fn ids(page: &BookPage) -> Vec<&str> {
+        page.books.iter().map(|b| b.id.as_str()).collect()
+    }
+
+    #[test]
+    fn test_catalog_stores_and_queries_books() {
+        let (mut catalog, dir) = temp_catalog();
+        let downloaded = Book {
+            book_path: Some("/books/b.epub".to_string()),
+            downloaded_at: Some(20),
+            downloaded_file_id: Some("fb".to_string()),
+            ..book("b", "Beta", &["Ahmad", "Sara"])
+        };
+        let audio = Book {
+            book_type: "mp3".to_string(),
+            ..book("c", "Gamma_1", &["Sara"])
+        };
+        let books = vec![book("a", "Alpha", &["Zaid"]), downloaded.clone(), audio];
+        catalog.replace_all(|_| (books, ()), 1000).unwrap();
+
+        assert_eq!(catalog.synced_at().unwrap(), Some(1000));
+        let stored = catalog.book("b").unwrap().unwrap();
+        assert_eq!(stored.authors, ["Ahmad", "Sara"]);
+        assert_eq!(stored.book_path, downloaded.book_path);
+        assert_eq!(stored.downloaded_file_id.as_deref(), Some("fb"));
+        assert_eq!(stored.key, Book::default().key);
+
+        assert_eq!(ids(&query(&catalog, BookQuery::default())), ["a", "b", "c"]);
+        let search = |s: &str| BookQuery {
+            search: Some(s.to_string()),
+            ..BookQuery::default()
+        };
+        assert_eq!(ids(&query(&catalog, search("sara"))), ["b", "c"]);
+        assert_eq!(ids(&query(&catalog, search("a_1"))), ["c"]);
+        let by_type = BookQuery {
+            book_type: Some("mp3".to_string()),
+            ..BookQuery::default()
+        };
+        assert_eq!(ids(&query(&catalog, by_type)), ["c"]);
+        let by_author = BookQuery {
+            author: Some("Sara".to_string()),
+            status: Some(DownloadStatus::NotDownloaded),
+            ..BookQuery::default()
+        };
+        assert_eq!(ids(&query(&catalog, by_author)), ["c"]);
+        let page = query(
+            &catalog,
+            BookQuery {
+                sort: BookSort::Author,
+                descending: true,
+                offset: 1,
+                limit: Some(1),
+                ..BookQuery::default()
+            },
+        );
+        assert_eq!((page.total, ids(&page)), (3, vec!["c"]));
+
+        // Updates keep the position; a new list drops unlisted books.
+        assert!(catalog
+            .update_book("a", |b| b.book_path = Some("/books/a.epub".to_string()))
+            .unwrap());
+        assert!(!catalog.update_book("x", |_| {}).unwrap());
+        let downloaded = query(
+            &catalog,
+            BookQuery {
+                status: Some(DownloadStatus::Downloaded),
+                ..BookQuery::default()
+            },
+        );
+        assert_eq!(ids(&downloaded), ["a", "b"]);
+        let kept = catalog
+            .replace_all(|local| (local.into_iter().skip(1).collect(), ()), 2000)
+            .map(|_| catalog.books().unwrap());
+        assert_eq!(kept.unwrap().len(), 2);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_imports_legacy_cache_and_records_conversions() {
+        let (mut catalog, dir) = temp_catalog();
+        let legacy: LegacyCache = serde_json::from_str(
+            r#"{"cached_at": 5, "items": [{"id": "1", "title": "Old", "authors": ["A"],
+                "book_path": "/books/old.epub"}]}"#,
+        )
+        .unwrap();
+        catalog.import_cache(legacy).unwrap();
+        assert_eq!(catalog.synced_at().unwrap(), Some(5));
+        assert_eq!(
+            catalog.books().unwrap()[0].book_path.as_deref(),
+            Some("/books/old.epub")
+        );
+
+        let conversion = |status: &str, started_at| Conversion {
+            book_id: "1".to_string(),
+            file_id: Some("9".to_string()),
+            started_at,
+            finished_at: started_at + 1,
+            status: status.to_string(),
+            output: None,
+            error: None,
+        };
+        catalog.record_conversion(&conversion("failed", 1)).unwrap();
+        catalog.record_conversion(&conversion("done", 2)).unwrap();
+        let history = catalog.conversions("1").unwrap();
+        assert_eq!(history, [conversion("done", 2), conversion("failed", 1)]);
+
+        // Reopening leaves the schema and the data alone.
+        drop(catalog);
+        let reopened = Catalog::open_at(&dir.join("library.db")).unwrap();
+        assert_eq!(reopened.books().unwrap().len(), 1);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
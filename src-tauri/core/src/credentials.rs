@@ -0,0 +1,592 @@
+use crate::cross_platform::get_app_data_path;
+#[cfg(feature = "keyring")]
+use crate::cross_platform::portable_data_dir;
+use crate::error::AppError;
+use crate::logging;
+use crate::profiles::{active_profile, profile_dir};
+use crate::settings::Settings;
+use base64::{engine::general_purpose, Engine as _};
+use openssl::hash::MessageDigest;
+use openssl::pkcs5::pbkdf2_hmac;
+use openssl::rand::rand_bytes;
+use openssl::symm::{decrypt_aead, encrypt_aead, Cipher};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+const PBKDF2_ITERATIONS: usize = 200_000;
+/// Code of the errors meaning the OS keyring can not be used at all, as
+/// opposed to failing on one entry.
+#[cfg(feature = "keyring")]
+const KEYRING_UNAVAILABLE: &str = "1011-1";
+
+/// What is needed to log in again without asking the user.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct Credentials {
+    pub email: String,
+    pub password: String,
+    pub access_token: Option<String>,
+}
+
+/// Where the login secrets live. Nothing else in the app reads or writes
+/// them directly.
+pub trait CredentialStore: Send + Sync {
+    fn load(&self) -> Result<Option<Credentials>, AppError>;
+
+    fn save(&self, credentials: &Credentials) -> Result<(), AppError>;
+
+    /// Removes the credentials, overwriting them first where the backend
+    /// allows it.
+    fn wipe(&self) -> Result<(), AppError>;
+}
+
+/// Where the AES key of an `EncryptedFileStore` comes from.
+pub enum KeySource {
+    /// Derived from a passphrase the user chose.
+    Passphrase(String),
+    /// 32 random bytes in a file readable only by the user, created on first
+    /// use. Protects against the settings being copied or synced elsewhere,
+    /// not against someone with access to the account.
+    KeyFile(PathBuf),
+}
+
+/// Credentials as AES-256-GCM encrypted JSON on disk.
+pub struct EncryptedFileStore {
+    path: PathBuf,
+    key: KeySource,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Envelope {
+    version: u32,
+    salt: String,
+    nonce: String,
+    tag: String,
+    data: String,
+}
+
+fn decode(field: &str, value: &str) -> Result<Vec<u8>, AppError> {
+    general_purpose::STANDARD.decode(value).map_err(|e| {
+        AppError::decrypt("1009", "Stored credentials are damaged")
+            .with_detail(format!("{}: {}", field, e))
+    })
+}
+
+fn random<const N: usize>() -> Result<[u8; N], AppError> {
+    let mut bytes = [0u8; N];
+    rand_bytes(&mut bytes)
+        .map_err(|e| AppError::io("1010", "Could not save the credentials").with_detail(e))?;
+    Ok(bytes)
+}
+
+/// Writes `data` readable only by the current user where the platform has
+/// such permissions.
+fn write_private(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path)?;
+    file.write_all(data)?;
+    file.sync_all()
+}
+
+/// Overwrites a file with zeros before removing it, so the old bytes are not
+/// simply left in free space.
+fn shred(path: &Path) -> std::io::Result<()> {
+    if !path.exists() {
+        return Ok(());
+    }
+    let len = fs::metadata(path)?.len() as usize;
+    let mut file = OpenOptions::new().write(true).open(path)?;
+    file.write_all(&vec![0u8; len])?;
+    file.sync_all()?;
+    drop(file);
+    fs::remove_file(path)
+}
+
+impl EncryptedFileStore {
+    pub fn new(path: PathBuf, key: KeySource) -> Self {
+        EncryptedFileStore { path, key }
+    }
+
+    fn key(&self, salt: &[u8]) -> Result<[u8; 32], AppError> {
+        let mut key = [0u8; 32];
+        match &self.key {
+            KeySource::Passphrase(passphrase) => {
+                pbkdf2_hmac(
+                    passphrase.as_bytes(),
+                    salt,
+                    PBKDF2_ITERATIONS,
+                    MessageDigest::sha256(),
+                    &mut key,
+                )
+                .map_err(|e| {
+                    AppError::decrypt("1009", "Could not derive the credentials key").with_detail(e)
+                })?;
+            }
+            KeySource::KeyFile(path) => {
+                if !path.exists() {
+                    write_private(path, &random::<32>()?).map_err(|e| {
+                        AppError::io("1010", "Could not create the credentials key")
+                            .with_detail(format!("{}: {}", path.display(), e))
+                    })?;
+                }
+                let bytes =
+                    fs::read(path).map_err(|e| {
+                        AppError::io("1009", "Could not read the credentials key")
+                            .with_detail(format!("{}: {}", path.display(), e))
+                    })?;
+                if bytes.len() != key.len() {
+                    return Err(
+                        AppError::integrity("1009", "The credentials key is damaged")
+                            .with_detail(path.display()),
+                    );
+                }
+                key.copy_from_slice(&bytes);
+            }
+        }
+        Ok(key)
+    }
+}
+
+impl CredentialStore for EncryptedFileStore {
+    fn load(&self) -> Result<Option<Credentials>, AppError> {
+        if !self.path.exists() {
+            return Ok(None);
+        }
+        let damaged = |e: &dyn std::fmt::Display| {
+            AppError::decrypt("1009", "Stored credentials are damaged").with_detail(e.to_string())
+        };
+        let data = fs::read(&self.path).map_err(|e| damaged(&e))?;
+        let envelope: Envelope = serde_json::from_slice(&data).map_err(|e| damaged(&e))?;
+
+        let salt = decode("salt", &envelope.salt)?;
+        let plain = decrypt_aead(
+            Cipher::aes_256_gcm(),
+            &self.key(&salt)?,
+            Some(&decode("nonce", &envelope.nonce)?),
+            &[],
+            &decode("data", &envelope.data)?,
+            &decode("tag", &envelope.tag)?,
+        )
+        .map_err(|_| {
+            AppError::decrypt("1009", "Stored credentials could not be decrypted")
+                .with_detail("wrong passphrase or key file")
+        })?;
+        serde_json::from_slice(&plain)
+            .map(Some)
+            .map_err(|e| damaged(&e))
+    }
+
+    fn save(&self, credentials: &Credentials) -> Result<(), AppError> {
+        let salt = random::<16>()?;
+        let nonce = random::<12>()?;
+        let plain = serde_json::to_vec(credentials)
+            .map_err(|e| AppError::io("1010", "Could not save the credentials").with_detail(e))?;
+        let mut tag = [0u8; 16];
+        let data = encrypt_aead(
+            Cipher::aes_256_gcm(),
+            &self.key(&salt)?,
+            Some(&nonce),
+            &[],
+            &plain,
+            &mut tag,
+        )
+        .map_err(|e| AppError::io("1010", "Could not encrypt the credentials").with_detail(e))?;
+
+        let envelope = Envelope {
+            version: 1,
+            salt: general_purpose::STANDARD.encode(salt),
+            nonce: general_purpose::STANDARD.encode(nonce),
+            tag: general_purpose::STANDARD.encode(tag),
+            data: general_purpose::STANDARD.encode(data),
+        };
+        let bytes = serde_json::to_vec(&envelope)
+            .map_err(|e| AppError::io("1010", "Could not save the credentials").with_detail(e))?;
+        // Same temp-and-rename dance as the settings, so a crash never
+        // leaves half a file that no key can open.
+        let temp = self.path.with_extension("tmp");
+        write_private(&temp, &bytes)
+            .and_then(|_| fs::rename(&temp, &self.path))
+            .map_err(|e| {
+                AppError::io("1010", "Could not save the credentials").with_detail(format!(
+                    "{}: {}",
+                    self.path.display(),
+                    e
+                ))
+            })
+    }
+
+    fn wipe(&self) -> Result<(), AppError> {
+        shred(&self.path).map_err(|e| {
+            AppError::io("1010", "Could not remove the stored credentials").with_detail(format!(
+                "{}: {}",
+                self.path.display(),
+                e
+            ))
+        })
+    }
+}
+
+/// Credentials in the OS keychain: Keychain on macOS, Credential Manager on
+/// Windows, the kernel keyring on Linux.
+#[cfg(feature = "keyring")]
+pub struct KeyringStore {
+    entry: keyring::Entry,
+}
+
+#[cfg(feature = "keyring")]
+impl KeyringStore {
+    pub fn new(profile: &str) -> Result<Self, AppError> {
+        let user = match profile {
+            crate::profiles::DEFAULT_PROFILE => "credentials".to_string(),
+            _ => format!("credentials-{}", profile),
+        };
+        keyring::Entry::new("jreader", &user)
+            .map(|entry| KeyringStore { entry })
+            .map_err(|e| keyring_error(e, "The system keyring is not available"))
+    }
+}
+
+/// Most keyring errors only show up once an entry is read or written, so
+/// the ones meaning there is no usable keyring get their own code. Reads
+/// and wipes handle `NoEntry` themselves; on a write it means there is no
+/// keyring to write to, as with a Linux session that has none.
+#[cfg(feature = "keyring")]
+fn keyring_error(error: keyring::Error, message: &str) -> AppError {
+    let code = match error {
+        keyring::Error::PlatformFailure(_)
+        | keyring::Error::NoStorageAccess(_)
+        | keyring::Error::NoEntry => KEYRING_UNAVAILABLE,
+        _ => "1011",
+    };
+    AppError::io(code, message).with_detail(error)
+}
+
+#[cfg(feature = "keyring")]
+impl CredentialStore for KeyringStore {
+    fn load(&self) -> Result<Option<Credentials>, AppError> {
+        match self.entry.get_password() {
+            Ok(json) => serde_json::from_str(&json).map(Some).map_err(|e| {
+                AppError::decrypt("1009", "Stored credentials are damaged").with_detail(e)
+            }),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(keyring_error(e, "Could not read the system keyring")),
+        }
+    }
+
+    fn save(&self, credentials: &Credentials) -> Result<(), AppError> {
+        let json = serde_json::to_string(credentials)
+            .map_err(|e| AppError::io("1011", "Could not save the credentials").with_detail(e))?;
+        self.entry
+            .set_password(&json)
+            .map_err(|e| keyring_error(e, "Could not write the system keyring"))
+    }
+
+    fn wipe(&self) -> Result<(), AppError> {
+        match self.entry.delete_credential() {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(keyring_error(e, "Could not clear the system keyring")),
+        }
+    }
+}
+
+/// Uses `primary` while it works and `fallback` whenever it reports the
+/// keyring as unavailable. Saves always go to the fallback too: some
+/// keyrings, like the Linux kernel one, forget everything on logout or
+/// reboot, and the file is what brings the login back then.
+#[cfg(feature = "keyring")]
+struct FallbackStore<P, F> {
+    primary: P,
+    fallback: F,
+}
+
+#[cfg(feature = "keyring")]
+impl<P: CredentialStore, F: CredentialStore> FallbackStore<P, F> {
+    fn unavailable(error: &AppError) -> bool {
+        if error.code() == KEYRING_UNAVAILABLE {
+            tracing::warn!("{}, using the encrypted file", error);
+            return true;
+        }
+        false
+    }
+}
+
+#[cfg(feature = "keyring")]
+impl<P: CredentialStore, F: CredentialStore> CredentialStore for FallbackStore<P, F> {
+    fn load(&self) -> Result<Option<Credentials>, AppError> {
+        match self.primary.load() {
+            Ok(Some(credentials)) => Ok(Some(credentials)),
+            Ok(None) => self.fallback.load(),
+            Err(e) if Self::unavailable(&e) => self.fallback.load(),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn save(&self, credentials: &Credentials) -> Result<(), AppError> {
+        match self.primary.save(credentials) {
+            Err(e) if !Self::unavailable(&e) => return Err(e),
+            _ => {}
+        }
+        self.fallback.save(credentials)
+    }
+
+    fn wipe(&self) -> Result<(), AppError> {
+        match self.primary.wipe() {
+            Err(e) if !Self::unavailable(&e) => return Err(e),
+            _ => {}
+        }
+        self.fallback.wipe()
+    }
+}
+
+/// The store for one profile. Credentials always go to an encrypted file;
+/// builds with the `keyring` feature also keep them in the OS keyring, unless
+/// the data folder is a portable one or was picked with `JREADER_DATA_DIR`.
+/// The file is keyed by `JREADER_PASSPHRASE` when set and by a local key
+/// file shared by all profiles if not.
+pub fn credential_store_for(profile: &str) -> Box<dyn CredentialStore> {
+    let key = match std::env::var("JREADER_PASSPHRASE") {
+        Ok(passphrase) if !passphrase.is_empty() => KeySource::Passphrase(passphrase),
+        _ => KeySource::KeyFile(get_app_data_path(Some("credentials.key"))),
+    };
+    let file = EncryptedFileStore::new(profile_dir(profile).join("credentials.enc"), key);
+
+    #[cfg(feature = "keyring")]
+    if std::env::var_os("JREADER_DATA_DIR").is_none() && portable_data_dir().is_none() {
+        match KeyringStore::new(profile) {
+            Ok(keyring) => {
+                return Box::new(FallbackStore {
+                    primary: keyring,
+                    fallback: file,
+                })
+            }
+            Err(e) => tracing::warn!("{}, using the encrypted file", e),
+        }
+    }
+    Box::new(file)
+}
+
+/// The store of the active profile.
+pub fn credential_store() -> Box<dyn CredentialStore> {
+    credential_store_for(&active_profile())
+}
+
+/// Where versions before the credential store kept the login in
+/// `settings.json`.
+const LEGACY_FIELDS: [&str; 3] = ["email", "password", "auth"];
+
+/// The saved credentials. Older versions kept them in plain `settings.json`;
+/// those are moved into the credential store and removed from the file the
+/// first time they are found.
+pub fn load_credentials() -> Result<Option<Credentials>, AppError> {
+    let store = credential_store();
+    if let Some(credentials) = store.load()? {
+        hide_secrets(&credentials);
+        return Ok(Some(credentials));
+    }
+
+    // Only rewrite the settings when they still hold a login.
+    let settings = Settings::load()?;
+    if !LEGACY_FIELDS
+        .iter()
+        .any(|key| settings.other.contains_key(*key))
+    {
+        return Ok(None);
+    }
+    let field = |key: &str| {
+        settings
+            .other
+            .get(key)
+            .and_then(|v| v.as_str().map(str::to_string))
+    };
+    let (email, password, token) = (field("email"), field("password"), field("auth"));
+    let legacy = (email.is_some() || token.is_some()).then(|| Credentials {
+        email: email.unwrap_or_default(),
+        password: password.unwrap_or_default(),
+        access_token: token,
+    });
+    // The fields go only once the login is safe in the store.
+    if let Some(credentials) = &legacy {
+        hide_secrets(credentials);
+        store.save(credentials)?;
+    }
+    Settings::update(|settings| {
+        for key in LEGACY_FIELDS {
+            settings.other.remove(key);
+        }
+    })?;
+    if legacy.is_some() {
+        tracing::info!("Moved the saved login out of settings.json");
+    }
+    Ok(legacy)
+}
+
+pub fn save_credentials(credentials: &Credentials) -> Result<(), AppError> {
+    hide_secrets(credentials);
+    credential_store().save(credentials)
+}
+
+/// Keeps the password and token out of the logs.
+fn hide_secrets(credentials: &Credentials) {
+    logging::hide(&credentials.password);
+    if let Some(token) = &credentials.access_token {
+        logging::hide(token);
+    }
+}
+
+pub fn access_token() -> Result<Option<String>, AppError> {
+    Ok(load_credentials()?.and_then(|c| c.access_token))
+}
+
+pub fn wipe_credentials() -> Result<(), AppError> {
+    credential_store().wipe()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encrypted_file_store_round_trip() {
+        let dir = std::env::temp_dir().join(format!("jreader-creds-{}", uuid::Uuid::new_v4()));
+        let path = dir.join("credentials.enc");
+        let credentials = Credentials {
+            email: "reader@example.com".to_string(),
+            password: "secret".to_string(),
+            access_token: Some("token".to_string()),
+        };
+
+        let store = EncryptedFileStore::new(path.clone(), KeySource::KeyFile(dir.join("key")));
+        assert_eq!(store.load().unwrap(), None);
+        store.save(&credentials).unwrap();
+        assert_eq!(store.load().unwrap(), Some(credentials.clone()));
+        assert!(!String::from_utf8_lossy(&fs::read(&path).unwrap()).contains("secret"));
+
+        let passphrase =
+            |p: &str| EncryptedFileStore::new(path.clone(), KeySource::Passphrase(p.to_string()));
+        passphrase("correct horse").save(&credentials).unwrap();
+        assert_eq!(
+            passphrase("correct horse").load().unwrap(),
+            Some(credentials)
+        );
+        assert_eq!(passphrase("wrong").load().unwrap_err().code(), "1009");
+
+        store.wipe().unwrap();
+        assert!(!path.exists());
+        assert_eq!(store.load().unwrap(), None);
+
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[cfg(feature = "keyring")]
+    struct BrokenKeyring;
+
+    #[cfg(feature = "keyring")]
+    impl CredentialStore for BrokenKeyring {
+        fn load(&self) -> Result<Option<Credentials>, AppError> {
+            Err(AppError::io(
+                KEYRING_UNAVAILABLE,
+                "Could not read the system keyring",
+            ))
+        }
+
+        fn save(&self, _: &Credentials) -> Result<(), AppError> {
+            Err(AppError::io(
+                KEYRING_UNAVAILABLE,
+                "Could not write the system keyring",
+            ))
+        }
+
+        fn wipe(&self) -> Result<(), AppError> {
+            Err(AppError::io(
+                KEYRING_UNAVAILABLE,
+                "Could not clear the system keyring",
+            ))
+        }
+    }
+
+    #[cfg(feature = "keyring")]
+    #[test]
+    fn test_unavailable_keyring_falls_back_to_the_file() {
+        let dir = std::env::temp_dir().join(format!("jreader-creds-{}", uuid::Uuid::new_v4()));
+        let path = dir.join("credentials.enc");
+        let file = || EncryptedFileStore::new(path.clone(), KeySource::KeyFile(dir.join("key")));
+        let credentials = Credentials {
+            email: "reader@example.com".to_string(),
+            password: "secret".to_string(),
+            access_token: None,
+        };
+
+        let store = FallbackStore {
+            primary: BrokenKeyring,
+            fallback: file(),
+        };
+        assert_eq!(store.load().unwrap(), None);
+        store.save(&credentials).unwrap();
+        assert_eq!(file().load().unwrap(), Some(credentials.clone()));
+        assert_eq!(store.load().unwrap(), Some(credentials));
+        store.wipe().unwrap();
+        assert!(!path.exists());
+
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[cfg(feature = "keyring")]
+    #[derive(Default)]
+    struct SessionKeyring(std::sync::Mutex<Option<Credentials>>);
+
+    #[cfg(feature = "keyring")]
+    impl CredentialStore for SessionKeyring {
+        fn load(&self) -> Result<Option<Credentials>, AppError> {
+            Ok(self.0.lock().unwrap().clone())
+        }
+
+        fn save(&self, credentials: &Credentials) -> Result<(), AppError> {
+            *self.0.lock().unwrap() = Some(credentials.clone());
+            Ok(())
+        }
+
+        fn wipe(&self) -> Result<(), AppError> {
+            *self.0.lock().unwrap() = None;
+            Ok(())
+        }
+    }
+
+    #[cfg(feature = "keyring")]
+    #[test]
+    fn test_file_copy_outlives_a_cleared_keyring() {
+        let dir = std::env::temp_dir().join(format!("jreader-creds-{}", uuid::Uuid::new_v4()));
+        let path = dir.join("credentials.enc");
+        let file = || EncryptedFileStore::new(path.clone(), KeySource::KeyFile(dir.join("key")));
+        let credentials = Credentials {
+            email: "reader@example.com".to_string(),
+            password: "secret".to_string(),
+            access_token: Some("token".to_string()),
+        };
+
+        let store = FallbackStore {
+            primary: SessionKeyring::default(),
+            fallback: file(),
+        };
+        store.save(&credentials).unwrap();
+        assert_eq!(store.primary.load().unwrap(), Some(credentials.clone()));
+
+        // What a reboot does to the kernel keyring.
+        store.primary.wipe().unwrap();
+        assert_eq!(store.load().unwrap(), Some(credentials));
+        store.wipe().unwrap();
+        assert_eq!(store.load().unwrap(), None);
+        assert!(!path.exists());
+
+        let _ = fs::remove_dir_all(dir);
+    }
+}
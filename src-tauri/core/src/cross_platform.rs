@@ -0,0 +1,43 @@
+use app_dirs2::{get_app_root, AppDataType, AppInfo};
+use std::path::PathBuf;
+
+const APP_INFO: AppInfo = AppInfo {
+    name: "jreader",
+    author: "abdumu",
+};
+
+/// A file with this name next to the executable turns on portable mode.
+const PORTABLE_MARKER: &str = "portable";
+
+/// `data` next to the executable when portable mode is on, so the app, its
+/// settings and its books can be carried around together.
+pub fn portable_data_dir() -> Option<PathBuf> {
+    let exe = std::env::current_exe().ok()?;
+    let dir = exe.parent()?;
+    dir.join(PORTABLE_MARKER)
+        .is_file()
+        .then(|| dir.join("data"))
+}
+
+pub fn get_app_data_path(path: Option<&str>) -> PathBuf {
+    // `JREADER_DATA_DIR` keeps everything in one folder, e.g. a test sandbox.
+    let app_data_path = match std::env::var_os("JREADER_DATA_DIR") {
+        Some(dir) => PathBuf::from(dir),
+        None => match portable_data_dir() {
+            Some(dir) => dir,
+            None => get_app_root(AppDataType::UserConfig, &APP_INFO)
+                .map_err(|e| {
+                    tracing::error!("Error getting app data path: {:?}", e);
+                    e
+                })
+                .unwrap(),
+        },
+    };
+
+    //app_data_path is PathBuf
+    if let Some(p) = path {
+        app_data_path.join(p).to_path_buf()
+    } else {
+        app_data_path
+    }
+}
@@ -1,5 +1,6 @@
-use crate::backend::book::Book;
-use crate::backend::cross_platform::get_app_data_path;
+use crate::book::Book;
+use crate::jobs::CancelToken;
+use crate::output::work_dir;
 use base64::{engine::general_purpose, DecodeError, Engine as _};
 use flate2::read::ZlibDecoder;
 use openssl::error::ErrorStack;
@@ -22,6 +23,10 @@ pub enum DecryptError {
     //add errorStack
     #[error("Error Stack: {0}")]
     ErrorStack(#[from] ErrorStack),
+    #[error("Corrupted package: {0}")]
+    CorruptedPackage(String),
+    #[error("Cancelled")]
+    Cancelled,
 }
 
 struct RC4 {
@@ -68,9 +73,10 @@ impl RC4 {
     }
 }
 
-pub async fn read_book_info(book_file: &Path) -> Result<serde_json::Value, DecryptError> {
+pub fn read_book_info(book_file: &Path) -> Result<serde_json::Value, DecryptError> {
     let file = File::open(book_file)?;
-    let mut archive = ZipArchive::new(file).map_err(|e| DecryptError::IoError(e.into()))?;
+    let mut archive =
+        ZipArchive::new(file).map_err(|e| DecryptError::CorruptedPackage(e.to_string()))?;
     let mut info = serde_json::json!({ "formatVersion": 5 });
     for i in 0..archive.len() {
         let mut file = archive
@@ -87,8 +93,10 @@ pub async fn read_book_info(book_file: &Path) -> Result<serde_json::Value, Decry
     Ok(info)
 }
 
-pub async fn unzip_book(book: Book) -> Result<Book, DecryptError> {
-    let output_folder = get_app_data_path(Some("books")).join(book.id.clone());
+/// Extracts and decrypts the package of `book` into its working folder.
+/// Blocking; callers run it on a blocking thread.
+pub fn unzip_book(book: Book, cancel: CancelToken) -> Result<Book, DecryptError> {
+    let output_folder = work_dir().join(book.id.clone());
     if !output_folder.with_extension("zip").exists() {
         return Err(DecryptError::IoError(io::Error::new(
             io::ErrorKind::NotFound,
@@ -99,13 +107,12 @@ pub async fn unzip_book(book: Book) -> Result<Book, DecryptError> {
     let file_stat = fs::metadata(output_folder.with_extension("zip"))?;
     if file_stat.len() == 0 {
         fs::remove_file(output_folder.with_extension("zip"))?;
-        return Err(DecryptError::IoError(io::Error::new(
-            io::ErrorKind::InvalidData,
-            "Downloaded file is corrupted, try again!",
-        )));
+        return Err(DecryptError::CorruptedPackage(
+            "Downloaded file is corrupted, try again!".to_string(),
+        ));
     }
 
-    let book_info = read_book_info(&output_folder.with_extension("zip")).await?;
+    let book_info = read_book_info(&output_folder.with_extension("zip"))?;
     if !output_folder.exists() {
         fs::create_dir_all(&output_folder)?;
     }
@@ -113,6 +120,9 @@ pub async fn unzip_book(book: Book) -> Result<Book, DecryptError> {
     let file = File::open(output_folder.with_extension("zip"))?;
     let mut archive = ZipArchive::new(file).map_err(|e| DecryptError::IoError(e.into()))?;
     for i in 0..archive.len() {
+        if cancel.is_cancelled() {
+            return Err(DecryptError::Cancelled);
+        }
         let mut file = archive
             .by_index(i)
             .map_err(|e| DecryptError::IoError(e.into()))?;
@@ -147,7 +157,7 @@ pub async fn unzip_book(book: Book) -> Result<Book, DecryptError> {
                     && out_path.file_name().unwrap_or_default() != "info.json")
             {
                 decrypt_text(&out_path, &book.key).map_err(|e| {
-                    println!("Decryption failed: {:?}", e);
+                    tracing::error!("Decryption failed: {:?}", e);
                     DecryptError::DecryptionError(format!("Decryption failed: {:?}", e))
                 })?;
             }
@@ -204,14 +214,14 @@ pub async fn combine_zip(
     file_path: &Path,
 ) -> Result<Option<Vec<i32>>, DecryptError> {
     let header = decrypt_header(header_hash, user_access_token).map_err(|e| {
-        println!("Decryption failed: {:?}", e);
+        tracing::error!("Decryption failed: {:?}", e);
         DecryptError::DecryptionError(format!("Decryption failed: {:?}", e))
     })?;
 
     let body = get_bytes(File::open(book_file)?).map_err(DecryptError::IoError)?;
     let header_key = append_files(&header, &body, file_path).map_err(|e| {
-        println!("Error: {:?}", e);
-        DecryptError::IoError(io::Error::new(io::ErrorKind::Other, e.to_string()))
+        tracing::error!("{:?}", e);
+        DecryptError::CorruptedPackage(e.to_string())
     })?;
 
     Ok(header_key)
@@ -240,11 +250,13 @@ fn convert_to_hex(bytes: &[u8]) -> String {
     hex_string
 }
 
-fn decrypt_header(input: &str, user_access_token: &str) -> Result<Vec<u8>, ErrorStack> {
+fn decrypt_header(input: &str, user_access_token: &str) -> Result<Vec<u8>, DecryptError> {
     let sha1 = sha1_hash(&(user_access_token.to_string() + "platform"));
     let key = if sha1.len() > 32 { &sha1[..32] } else { &sha1 };
 
-    let decode = base64_decode(input).expect("Failed to decode base64");
+    let decode = base64_decode(input).map_err(|e| {
+        DecryptError::DecryptionError(format!("Failed to decode base64 header: {}", e))
+    })?;
     let iv = b"1234567812345678";
     let cipher = Cipher::aes_256_cbc();
     let mut decrypter = Crypter::new(cipher, Mode::Decrypt, key.as_bytes(), Some(iv))?;
@@ -0,0 +1,241 @@
+use crate::error::AppError;
+use crate::jobs::CancelToken;
+use crate::progress::{DownloadProgress, ProgressTracker};
+use crate::request::{send, Endpoint};
+use reqwest::header::{HeaderMap, CONTENT_RANGE, ETAG, IF_RANGE, LAST_MODIFIED, RANGE};
+use reqwest::{Client, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tokio::io::AsyncWriteExt;
+
+/// What we know about a partial download, stored next to the `.part` file so
+/// an interrupted transfer can be resumed with a `Range` request.
+#[derive(Debug, Serialize, Deserialize, Default, PartialEq)]
+struct PartMeta {
+    etag: Option<String>,
+    last_modified: Option<String>,
+    expected_size: Option<u64>,
+}
+
+impl PartMeta {
+    fn from_headers(headers: &HeaderMap, expected_size: Option<u64>) -> Self {
+        let header = |name| {
+            headers
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(|v| v.to_string())
+        };
+        PartMeta {
+            etag: header(ETAG),
+            last_modified: header(LAST_MODIFIED),
+            expected_size,
+        }
+    }
+
+    /// The value sent as `If-Range`; a strong ETag is preferred over the date.
+    fn validator(&self) -> Option<&str> {
+        self.etag
+            .as_deref()
+            .filter(|etag| !etag.starts_with("W/"))
+            .or(self.last_modified.as_deref())
+    }
+}
+
+pub fn part_path(dest: &Path) -> PathBuf {
+    append_extension(dest, "part")
+}
+
+fn meta_path(dest: &Path) -> PathBuf {
+    append_extension(dest, "part.json")
+}
+
+fn append_extension(path: &Path, extension: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".");
+    name.push(extension);
+    PathBuf::from(name)
+}
+
+fn read_meta(dest: &Path) -> Option<PartMeta> {
+    let data = fs::read_to_string(meta_path(dest)).ok()?;
+    serde_json::from_str(&data).ok()
+}
+
+fn write_meta(dest: &Path, meta: &PartMeta) -> Result<(), AppError> {
+    let data = serde_json::to_string(meta)
+        .map_err(|e| AppError::io("702-7", "Could not save download state").with_detail(e))?;
+    fs::write(meta_path(dest), data)?;
+    Ok(())
+}
+
+/// Removes the `.part` file of `dest` and its validators, if any.
+pub fn discard_partial(dest: &Path) -> Result<(), std::io::Error> {
+    for path in [part_path(dest), meta_path(dest)] {
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Bytes already on disk for `dest` that can be resumed, together with their
+/// validators. Partial files without a validator are thrown away because
+/// there is no way to tell whether the remote file changed in between.
+fn resumable_state(dest: &Path) -> Option<(u64, PartMeta)> {
+    let meta = read_meta(dest)?;
+    meta.validator()?;
+    let size = fs::metadata(part_path(dest)).ok()?.len();
+    if size == 0 || meta.expected_size.is_some_and(|expected| size > expected) {
+        return None;
+    }
+    Some((size, meta))
+}
+
+/// Start offset of a `Content-Range: bytes <start>-<end>/<total>` header and
+/// the total size when known.
+fn parse_content_range(headers: &HeaderMap) -> Option<(u64, Option<u64>)> {
+    let value = headers.get(CONTENT_RANGE)?.to_str().ok()?;
+    let range = value.trim().strip_prefix("bytes ")?;
+    let (span, total) = range.split_once('/')?;
+    let (start, _) = span.split_once('-')?;
+    Some((start.trim().parse().ok()?, total.trim().parse().ok()))
+}
+
+/// Downloads `url` into `dest`, streaming it through a `.part` file.
+///
+/// When a previous attempt left a partial file with validators behind, only
+/// the missing bytes are requested. Servers that ignore the `Range` header or
+/// report that the file changed get a clean full download instead.
+/// `size_hint` is only used for progress when the server sends no length.
+pub async fn fetch_resumable(
+    client: &Client,
+    url: &str,
+    dest: &Path,
+    book_id: &str,
+    size_hint: Option<u64>,
+    on_progress: &(dyn Fn(DownloadProgress) + Send + Sync),
+    cancel: &CancelToken,
+) -> Result<(), AppError> {
+    let part = part_path(dest);
+    let mut resume = resumable_state(dest);
+    if resume.is_none() {
+        discard_partial(dest)?;
+    }
+
+    if let Some((size, meta)) = &resume {
+        if meta.expected_size == Some(*size) {
+            fs::rename(&part, dest)?;
+            discard_partial(dest)?;
+            return Ok(());
+        }
+    }
+
+    loop {
+        cancel.check()?;
+        let build = || {
+            let mut request = client.get(url);
+            if let Some((offset, meta)) = &resume {
+                request = request.header(RANGE, format!("bytes={}-", offset));
+                if let Some(validator) = meta.validator() {
+                    request = request.header(IF_RANGE, validator);
+                }
+            }
+            request
+        };
+
+        let mut response = tokio::select! {
+            response = send(Endpoint::Download, build) => response
+                .map_err(|e| AppError::network("702-1", "Failed to download book").with_detail(e))?,
+            _ = cancel.cancelled() => return Err(AppError::cancelled()),
+        };
+        let status = response.status();
+
+        if status == StatusCode::RANGE_NOT_SATISFIABLE && resume.is_some() {
+            discard_partial(dest)?;
+            resume = None;
+            continue;
+        }
+        if !status.is_success() {
+            return Err(AppError::network("702-2", "Failed to download book").with_detail(status));
+        }
+
+        let offset = match (&resume, status) {
+            (Some((offset, _)), StatusCode::PARTIAL_CONTENT) => {
+                match parse_content_range(response.headers()) {
+                    Some((start, _)) if start == *offset => Some(*offset),
+                    _ => {
+                        discard_partial(dest)?;
+                        resume = None;
+                        continue;
+                    }
+                }
+            }
+            _ => None,
+        };
+
+        let expected_size = match offset {
+            Some(offset) => parse_content_range(response.headers())
+                .and_then(|(_, total)| total)
+                .or_else(|| response.content_length().map(|len| len + offset)),
+            None => response.content_length(),
+        }
+        .filter(|size| *size > 0);
+
+        write_meta(
+            dest,
+            &PartMeta::from_headers(response.headers(), expected_size),
+        )?;
+
+        let mut file = match offset {
+            Some(_) => tokio::fs::OpenOptions::new().append(true).open(&part).await,
+            None => tokio::fs::File::create(&part).await,
+        }
+        .map_err(|e| AppError::from(e).with_code("702"))?;
+
+        let mut tracker = ProgressTracker::new(book_id, expected_size.or(size_hint));
+        tracker.resume_from(offset.unwrap_or(0));
+        on_progress(tracker.snapshot());
+
+        loop {
+            let chunk = tokio::select! {
+                chunk = response.chunk() => chunk
+                    .map_err(|e| AppError::network("702-3", "Failed to download book").with_detail(e))?,
+                _ = cancel.cancelled() => return Err(AppError::cancelled()),
+            };
+            let Some(chunk) = chunk else {
+                break;
+            };
+            file.write_all(&chunk)
+                .await
+                .map_err(|e| AppError::from(e).with_code("702-4"))?;
+            if let Some(progress) = tracker.advance(chunk.len() as u64) {
+                on_progress(progress);
+            }
+        }
+        file.flush()
+            .await
+            .map_err(|e| AppError::from(e).with_code("702-4"))?;
+        drop(file);
+        on_progress(tracker.snapshot());
+
+        let written = fs::metadata(&part)?.len();
+        if let Some(expected) = expected_size {
+            if written < expected {
+                return Err(AppError::network("702-3", "Download was interrupted")
+                    .with_detail(format!("{} of {} bytes", written, expected)));
+            }
+            if written > expected {
+                discard_partial(dest)?;
+                return Err(
+                    AppError::integrity("702-8", "Downloaded file has the wrong size")
+                        .with_detail(format!("{} of {} bytes", written, expected)),
+                );
+            }
+        }
+
+        fs::rename(&part, dest)?;
+        discard_partial(dest)?;
+        return Ok(());
+    }
+}
@@ -1,36 +1,60 @@
-use crate::backend::book::Book;
-use crate::backend::cross_platform::get_app_data_path;
-use crate::backend::decrypt::{base64_decode, base64_encode};
-use crate::backend::helpers::{clean_filename, nl2br, uuid};
-use crate::backend::transliteration::transliterate;
+use crate::book::Book;
+use crate::cross_platform::get_app_data_path;
+use crate::decrypt::{base64_decode, base64_encode};
+use crate::helpers::{clean_filename, nl2br, uuid};
+use crate::jobs::CancelToken;
+use crate::output::{work_dir, OutputSettings};
+use crate::transliteration::transliterate;
 use epub_builder::{EpubBuilder, EpubContent, ReferenceType, ZipLibrary};
-use reqwest::Url;
 use serde::{Deserialize, Serialize};
+use std::fmt::Display;
 use std::fs::{self, File};
 use std::path::PathBuf;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum EpubError {
+    #[error("IO Error: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("EPUB Builder Error: {0}")]
+    BuilderError(String),
+    #[error("Missing file: {0}")]
+    MissingFile(String),
+    #[error("Encoding Error: {0}")]
+    EncodingError(String),
+    #[error("Cancelled")]
+    Cancelled,
+}
+
+fn builder_error(e: impl Display) -> EpubError {
+    EpubError::BuilderError(e.to_string())
+}
 
 pub async fn book_epub_generator(
     book: Book,
     info: Option<serde_json::Value>,
-) -> Result<PathBuf, String> {
+    cover: Option<&[u8]>,
+    cancel: &CancelToken,
+) -> Result<PathBuf, EpubError> {
     let temp_dir = get_app_data_path(Some("temp"));
     if !temp_dir.exists() {
-        fs::create_dir_all(&temp_dir).map_err(|e| e.to_string())?;
+        fs::create_dir_all(&temp_dir)?;
     }
 
-    let output_path =
-        get_app_data_path(Some("books")).join(format!("{}.epub", clean_filename(&book.title, "-")));
+    let output_dir = OutputSettings::load().book_dir(&book);
+    fs::create_dir_all(&output_dir)?;
+    let output_path = output_dir.join(format!("{}.epub", clean_filename(&book.title, "-")));
 
-    let zip = ZipLibrary::new().map_err(|e| e.to_string())?;
-    let mut builder = EpubBuilder::new(zip).map_err(|e| e.to_string())?;
+    let zip = ZipLibrary::new().map_err(builder_error)?;
+    let mut builder = EpubBuilder::new(zip).map_err(builder_error)?;
 
     builder
         .metadata("title", &book.title)
-        .map_err(|e| e.to_string())?;
+        .map_err(builder_error)?;
 
     builder
         .metadata("author", book.authors.join(", "))
-        .map_err(|e| e.to_string())?;
+        .map_err(builder_error)?;
 
     builder
         .metadata(
@@ -40,31 +64,25 @@ pub async fn book_epub_generator(
                 .and_then(|l| l.as_str())
                 .unwrap_or("ar"),
         )
-        .map_err(|e| e.to_string())?;
+        .map_err(builder_error)?;
 
     builder.epub_version(epub_builder::EpubVersion::V30);
     // builder.inline_toc();
 
-    if let Some(cover) = &book.cover {
-        let cover_data = if Url::parse(cover).is_ok() {
-            let response = reqwest::get(cover).await.map_err(|e| e.to_string())?;
-            response.bytes().await.map_err(|e| e.to_string())?.to_vec()
-        } else {
-            fs::read(cover).map_err(|e| e.to_string())?
-        };
+    if let Some(cover_data) = cover {
         builder
-            .add_cover_image("cover.jpg", &cover_data[..], "image/jpeg")
-            .map_err(|e| e.to_string())?;
+            .add_cover_image("cover.jpg", cover_data, "image/jpeg")
+            .map_err(builder_error)?;
         let cover_page = format!(
             "<html><body><img src='data:image/jpeg;base64, {}' alt='Cover'/></body></html>",
-            base64_encode(&cover_data[..])
+            base64_encode(cover_data)
         );
         builder
             .add_content(
                 EpubContent::new("cover.xhtml", cover_page.as_bytes())
                     .reftype(ReferenceType::Cover),
             )
-            .map_err(|e| e.to_string())?;
+            .map_err(builder_error)?;
     }
 
     let css = r#"
@@ -89,11 +107,9 @@ pub async fn book_epub_generator(
         img { max-width: 100%; height: auto; }
     "#;
 
-    builder
-        .stylesheet(css.as_bytes())
-        .map_err(|e| e.to_string())?;
+    builder.stylesheet(css.as_bytes()).map_err(builder_error)?;
 
-    let content = parse_chapter(&book, &info).await?;
+    let content = parse_chapter(&book, &info, cancel).await?;
 
     for chapter in content.iter() {
         builder
@@ -102,22 +118,20 @@ pub async fn book_epub_generator(
                     .title(&chapter.title)
                     .reftype(ReferenceType::Text),
             )
-            .map_err(|e| e.to_string())?;
+            .map_err(builder_error)?;
     }
 
-    let images_dir = get_app_data_path(Some("books"))
-        .join(&book.id)
-        .join("Images");
+    let images_dir = work_dir().join(&book.id).join("Images");
     if images_dir.exists() {
-        for entry in fs::read_dir(images_dir).map_err(|e| e.to_string())? {
-            let entry = entry.map_err(|e| e.to_string())?;
+        for entry in fs::read_dir(images_dir)? {
+            let entry = entry?;
             let path = entry.path();
             if path.is_file() {
-                let image_data = fs::read(&path).map_err(|e| e.to_string())?;
+                let image_data = fs::read(&path)?;
                 let image_id = format!("Images/{}", path.file_name().unwrap().to_str().unwrap());
                 builder
                     .add_resource(&image_id, &image_data[..], "image/jpeg")
-                    .map_err(|e| e.to_string())?;
+                    .map_err(builder_error)?;
             }
         }
     }
@@ -136,16 +150,14 @@ pub async fn book_epub_generator(
             .collect::<String>()
     );
     builder
-        .add_content(
-            EpubContent::new("nav.xhtml", nav_page.as_bytes())
-                .reftype(ReferenceType::Toc),
-        )
-        .map_err(|e| e.to_string())?;
+        .add_content(EpubContent::new("nav.xhtml", nav_page.as_bytes()).reftype(ReferenceType::Toc))
+        .map_err(builder_error)?;
 
-    let mut output_file = File::create(&output_path).map_err(|e| e.to_string())?;
-    builder
-        .generate(&mut output_file)
-        .map_err(|e| e.to_string())?;
+    if cancel.is_cancelled() {
+        return Err(EpubError::Cancelled);
+    }
+    let mut output_file = File::create(&output_path)?;
+    builder.generate(&mut output_file).map_err(builder_error)?;
 
     Ok(output_path)
 }
@@ -160,7 +172,8 @@ struct Chapter {
 async fn parse_chapter(
     book: &Book,
     info: &Option<serde_json::Value>,
-) -> Result<Vec<Chapter>, String> {
+    cancel: &CancelToken,
+) -> Result<Vec<Chapter>, EpubError> {
     let mut content = Vec::new();
     let mut total_offset = 0;
 
@@ -171,44 +184,49 @@ async fn parse_chapter(
         .unwrap_or(0);
 
     for index in 1..=chapters {
-        let chapter_path = get_app_data_path(Some("books"))
+        if cancel.is_cancelled() {
+            return Err(EpubError::Cancelled);
+        }
+        let chapter_path = work_dir()
             .join(&book.id)
             .join("Text")
             .join(format!("chapter-{:03}.html", index));
-        let spans_path = get_app_data_path(Some("books"))
+        let spans_path = work_dir()
             .join(&book.id)
             .join("Text")
             .join(format!("chapter-{:03}.html.spans", index));
-        let toc_path = get_app_data_path(Some("books"))
-            .join(&book.id)
-            .join("Index")
-            .join("toc.json");
+        let toc_path = work_dir().join(&book.id).join("Index").join("toc.json");
 
         if !chapter_path.exists() {
-            return Err(format!("Chapter file does not exist: {:?}", chapter_path));
+            return Err(EpubError::MissingFile(format!("{:?}", chapter_path)));
         }
 
         if !spans_path.exists() {
-            return Err(format!("Spans file does not exist: {:?}", spans_path));
+            return Err(EpubError::MissingFile(format!("{:?}", spans_path)));
         }
 
         if !toc_path.exists() {
-            return Err(format!("TOC file does not exist: {:?}", toc_path));
+            return Err(EpubError::MissingFile(format!("{:?}", toc_path)));
         }
 
-        let text = fs::read_to_string(&chapter_path).map_err(|e| e.to_string())?;
+        let text = fs::read_to_string(&chapter_path)?;
 
-        let mut spans_bytes = fs::read(&spans_path).map_err(|e| e.to_string())?;
+        let mut spans_bytes = fs::read(&spans_path)?;
         if spans_bytes.starts_with(&[0xEF, 0xBB, 0xBF]) {
             spans_bytes = spans_bytes[3..].to_vec();
         }
-        let spans =
-            String::from_utf8(spans_bytes).map_err(|e| format!("Invalid UTF-8 in spans: {}", e))?;
+        let spans = String::from_utf8(spans_bytes)
+            .map_err(|e| EpubError::EncodingError(format!("Invalid UTF-8 in spans: {}", e)))?;
 
-        let toc_bytes = fs::read(&toc_path).map_err(|e| e.to_string())?;
+        let toc_bytes = fs::read(&toc_path)?;
         let toc = match String::from_utf8(toc_bytes) {
             Ok(toc_str) => serde_json::from_str::<Vec<TocEntry>>(&toc_str).unwrap_or_default(),
-            Err(e) => return Err(format!("Failed to read TOC file as UTF-8: {}", e)),
+            Err(e) => {
+                return Err(EpubError::EncodingError(format!(
+                    "Failed to read TOC file as UTF-8: {}",
+                    e
+                )))
+            }
         };
 
         let spans: Vec<Vec<serde_json::Value>> = serde_json::from_str(&spans).unwrap_or_default();
@@ -263,7 +281,7 @@ async fn parse_chapter(
 
     let last_chapter_text = "KNiq2YXYqikKCi0tLS0tLS0tLS0KCjEtINmH2LDYpyDYp9mE2YPYqtin2Kgg2KrZhSDYp9i12K/Yp9ix2Ycg2YjYp9mG2KrYp9is2Ycg2YTZgtin2LHYpiDYrNix2YrYsS/YsdmB2YjZgSDZiNmK2YXZhti5INmF2YbYudin2Ysg2KjYp9iq2KfZiyDZhti02LHZhyDYqNiv2YjZhiDYp9iw2YYg2K7Yt9mKINmF2YYg2LTYsdmD2Kkg2KzYsdmK2LEv2LHZgdmI2YEuCjItINin2LDYpyDZgtmF2Kog2KjZhti02LEg2KfZhNmD2KrYp9ioINmB2KPZhtmDINiq2YPZiNmGINmC2K8g2KfZgtiq2LHZgdiqINiu2LfYoyDZgtin2YbZiNmG2YrYp9mLINmK2KzYsdmF2Ycg2KfZhNmC2KfZhtmI2YYg2YjZitit2YIg2YTYtNix2YPYqSDYrNix2YrYsS/YsdmB2YjZgSDZhdmC2KfYttin2KrZgyDZiNmF2YTYp9it2YLYqtmDINmC2KfZhtmI2YbZitin2YsuCjMtINmE2Kcg2YrYqtit2YXZhCDZhdi32YjYsSDYo9iv2KfYqSDYp9mE2YXYrdmI2YQg2KfZhNiw2Yog2KrZhSDYqNmH2Kcg2KfYs9iq2K7Ysdin2Kwg2KfZhNmD2KrYp9ioINij2Yog2KrYqNi52KfYqiDZgtin2YbZiNmG2YrYqSDYqtit2K/YqyDZhdmGINij2Yog2YHYsdivINin2Ygg2YXYpNiz2LPYqSDYo9mIINis2YfYqSDYo9mKINmD2KfZhiDZhtmI2LnZh9inINiq2YLZiNmFINio2YHYudmEINi62YrYsSDZgtin2YbZiNmKINio2KfZhNin2K/Yp9ipINmD2YbYtNixINin2YTZg9iq2Kgg2K/ZiNmGINin2LDZhiDZhdmGINi02LHZg9ipINis2LHZitixL9ix2YHZiNmBLgo0LSDYo9mGINmG2LTYsdmDINmE2YfYsNinINin2YTZg9iq2KfYqCDZhNi12YrYutipINin2K7YsdmJINi52KjYsSDYp9mE2KfYr9in2Kkg2YfZiiDZhNin2LLYp9mE2Kkg2KfZhNiv2Yog2KfYsSDYp9mFINmI2KfZhNmC2LHYp9ih2Kkg2KjYsdin2K3YqSDYudmE2Ykg2KfZiiDYudin2LHYtiDYp9iu2LHZiSDZhNmDINi02K7YtdmK2Kcg2YjZhNin2YrYudi32YrZgyDYp9mE2K3ZgiDYqNmG2LTYsSDYp9mE2YPYqtin2Kgg2YjZhNinINiq2YjYstmK2LnZhy4KOTktIERvIG5vdCBzaGFyZSwgc2VsbCwgYW5kL29yIGRpc3RyaWJ1dGUgdGhpcyBjb3B5cmlnaHRlZCBtYXRlcmlhbCEgQnkgdmlvbGF0aW5nIHRoZXNlIHRlcm1zLCB5b3UgYXJlIHN1YmplY3RlZCB0byBsZWdhbCBwcm9jZWVkaW5ncyBhZ2FpbnN0IHlvdSBieSBKYXJpci9SdWZvb2YgY29tcGFueSBhbmQgd2UgKHRvb2wgZGV2ZWxvcGVyKSBhcmUgbm90IHJlc3BvbnNpYmxlIGJ5IGFueSBtZWFucyBieSB5b3VyIGZvdWwgYWN0aW9ucy5vdXIgcGVyc29uYWwgdXNlIG9ubHkgYW5kIHRoYXQgeW8iCgoKLS0tLS0tLS0tLQ==";
     let last_chapter_text = String::from_utf8(base64_decode(last_chapter_text).unwrap())
-        .map_err(|e| format!("Invalid UTF-8 in copyright: {}", e))?;
+        .map_err(|e| EpubError::EncodingError(format!("Invalid UTF-8 in copyright: {}", e)))?;
     let last_chapter_string = last_chapter_text.as_str();
 
     content.push(Chapter {
@@ -367,7 +385,7 @@ fn parse_span(text: &str, spans: &[Vec<serde_json::Value>]) -> String {
                         changed_snippet
                     )
                 }
-                _ => println!("Warning: Unsupported inline type_id {}", inline_type),
+                _ => tracing::warn!("Unsupported inline type_id {}", inline_type),
             }
         }
 
@@ -0,0 +1,316 @@
+#[cfg(feature = "audio")]
+use crate::audio::BookAudioGeneratorError;
+use crate::book_generator::BookGeneratorError;
+use crate::decrypt::DecryptError;
+#[cfg(feature = "epub")]
+use crate::epub::EpubError;
+use serde::ser::SerializeStruct;
+use serde::{Serialize, Serializer};
+use std::fmt::Display;
+use thiserror::Error;
+
+/// Broad category of a failure, used by the frontend to decide how to react
+/// (e.g. go back to the login screen on `auth`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ErrorKind {
+    Network,
+    Auth,
+    Integrity,
+    Decrypt,
+    Conversion,
+    Io,
+    Cancelled,
+}
+
+/// Every error that crosses the Tauri boundary. Serialised as
+/// `{code, kind, message, detail}`.
+///
+/// Codes keep the numbering the app always used: 5xx login, 6xx books list,
+/// 7xx download/convert, 8xx download info and 9xx update check.
+#[derive(Debug, Error)]
+pub enum AppError {
+    #[error("({code}) {message}")]
+    Network {
+        code: &'static str,
+        message: String,
+        detail: Option<String>,
+    },
+    #[error("({code}) {message}")]
+    Auth {
+        code: &'static str,
+        message: String,
+        detail: Option<String>,
+    },
+    #[error("({code}) {message}")]
+    Integrity {
+        code: &'static str,
+        message: String,
+        detail: Option<String>,
+    },
+    #[error("({code}) {message}")]
+    Decrypt {
+        code: &'static str,
+        message: String,
+        detail: Option<String>,
+    },
+    #[error("({code}) {message}")]
+    Conversion {
+        code: &'static str,
+        message: String,
+        detail: Option<String>,
+    },
+    #[error("({code}) {message}")]
+    Io {
+        code: &'static str,
+        message: String,
+        detail: Option<String>,
+    },
+    #[error("({code}) {message}")]
+    Cancelled {
+        code: &'static str,
+        message: String,
+        detail: Option<String>,
+    },
+}
+
+impl AppError {
+    pub fn new(kind: ErrorKind, code: &'static str, message: impl Into<String>) -> Self {
+        let message = message.into();
+        let detail = None;
+        match kind {
+            ErrorKind::Network => AppError::Network {
+                code,
+                message,
+                detail,
+            },
+            ErrorKind::Auth => AppError::Auth {
+                code,
+                message,
+                detail,
+            },
+            ErrorKind::Integrity => AppError::Integrity {
+                code,
+                message,
+                detail,
+            },
+            ErrorKind::Decrypt => AppError::Decrypt {
+                code,
+                message,
+                detail,
+            },
+            ErrorKind::Conversion => AppError::Conversion {
+                code,
+                message,
+                detail,
+            },
+            ErrorKind::Io => AppError::Io {
+                code,
+                message,
+                detail,
+            },
+            ErrorKind::Cancelled => AppError::Cancelled {
+                code,
+                message,
+                detail,
+            },
+        }
+    }
+
+    pub fn network(code: &'static str, message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::Network, code, message)
+    }
+
+    pub fn auth(code: &'static str, message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::Auth, code, message)
+    }
+
+    pub fn integrity(code: &'static str, message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::Integrity, code, message)
+    }
+
+    pub fn decrypt(code: &'static str, message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::Decrypt, code, message)
+    }
+
+    pub fn conversion(code: &'static str, message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::Conversion, code, message)
+    }
+
+    pub fn io(code: &'static str, message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::Io, code, message)
+    }
+
+    /// The job was stopped on the user's request.
+    pub fn cancelled() -> Self {
+        Self::new(ErrorKind::Cancelled, "1100", "The job was cancelled")
+    }
+
+    /// Attaches the underlying error text, shown to the user as extra detail.
+    pub fn with_detail(mut self, value: impl Display) -> Self {
+        *self.detail_mut() = Some(value.to_string());
+        self
+    }
+
+    /// Replaces the code while keeping kind, message and detail.
+    pub fn with_code(mut self, new_code: &'static str) -> Self {
+        *self.code_mut() = new_code;
+        self
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            AppError::Network { .. } => ErrorKind::Network,
+            AppError::Auth { .. } => ErrorKind::Auth,
+            AppError::Integrity { .. } => ErrorKind::Integrity,
+            AppError::Decrypt { .. } => ErrorKind::Decrypt,
+            AppError::Conversion { .. } => ErrorKind::Conversion,
+            AppError::Io { .. } => ErrorKind::Io,
+            AppError::Cancelled { .. } => ErrorKind::Cancelled,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::Network { code, .. }
+            | AppError::Auth { code, .. }
+            | AppError::Integrity { code, .. }
+            | AppError::Decrypt { code, .. }
+            | AppError::Conversion { code, .. }
+            | AppError::Io { code, .. }
+            | AppError::Cancelled { code, .. } => code,
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            AppError::Network { message, .. }
+            | AppError::Auth { message, .. }
+            | AppError::Integrity { message, .. }
+            | AppError::Decrypt { message, .. }
+            | AppError::Conversion { message, .. }
+            | AppError::Io { message, .. }
+            | AppError::Cancelled { message, .. } => message,
+        }
+    }
+
+    pub fn detail(&self) -> Option<&str> {
+        match self {
+            AppError::Network { detail, .. }
+            | AppError::Auth { detail, .. }
+            | AppError::Integrity { detail, .. }
+            | AppError::Decrypt { detail, .. }
+            | AppError::Conversion { detail, .. }
+            | AppError::Io { detail, .. }
+            | AppError::Cancelled { detail, .. } => detail.as_deref(),
+        }
+    }
+
+    fn code_mut(&mut self) -> &mut &'static str {
+        match self {
+            AppError::Network { code, .. }
+            | AppError::Auth { code, .. }
+            | AppError::Integrity { code, .. }
+            | AppError::Decrypt { code, .. }
+            | AppError::Conversion { code, .. }
+            | AppError::Io { code, .. }
+            | AppError::Cancelled { code, .. } => code,
+        }
+    }
+
+    fn detail_mut(&mut self) -> &mut Option<String> {
+        match self {
+            AppError::Network { detail, .. }
+            | AppError::Auth { detail, .. }
+            | AppError::Integrity { detail, .. }
+            | AppError::Decrypt { detail, .. }
+            | AppError::Conversion { detail, .. }
+            | AppError::Io { detail, .. }
+            | AppError::Cancelled { detail, .. } => detail,
+        }
+    }
+}
+
+impl Serialize for AppError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("AppError", 4)?;
+        state.serialize_field("code", self.code())?;
+        state.serialize_field("kind", &self.kind())?;
+        state.serialize_field("message", self.message())?;
+        state.serialize_field("detail", &self.detail())?;
+        state.end()
+    }
+}
+
+impl From<std::io::Error> for AppError {
+    fn from(e: std::io::Error) -> Self {
+        AppError::io("100", "File system error").with_detail(e)
+    }
+}
+
+#[cfg(feature = "network")]
+impl From<reqwest::Error> for AppError {
+    fn from(e: reqwest::Error) -> Self {
+        AppError::network("200", "Network error").with_detail(e)
+    }
+}
+
+impl From<DecryptError> for AppError {
+    fn from(e: DecryptError) -> Self {
+        match e {
+            DecryptError::IoError(e) => AppError::from(e),
+            DecryptError::Cancelled => AppError::cancelled(),
+            DecryptError::CorruptedPackage(msg) => {
+                AppError::integrity("710", "Downloaded file is corrupted, try again!")
+                    .with_detail(msg)
+            }
+            e => AppError::decrypt("720", "Could not decrypt the book").with_detail(e),
+        }
+    }
+}
+
+impl From<BookGeneratorError> for AppError {
+    fn from(e: BookGeneratorError) -> Self {
+        match e {
+            BookGeneratorError::IoError(e) => AppError::from(e),
+            BookGeneratorError::Cancelled => AppError::cancelled(),
+            #[cfg(feature = "epub")]
+            BookGeneratorError::EpubError(EpubError::Cancelled) => AppError::cancelled(),
+            #[cfg(feature = "audio")]
+            BookGeneratorError::AudioError(BookAudioGeneratorError::Cancelled) => {
+                AppError::cancelled()
+            }
+            e => AppError::conversion("730", "Could not convert the book").with_detail(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_serialize_shape() {
+        let error = AppError::auth("505", "Can not login!").with_detail("bad password");
+        let value = serde_json::to_value(&error).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "code": "505",
+                "kind": "auth",
+                "message": "Can not login!",
+                "detail": "bad password",
+            })
+        );
+        assert_eq!(error.to_string(), "(505) Can not login!");
+    }
+
+    #[test]
+    fn test_decrypt_io_is_io_kind() {
+        let error: AppError =
+            DecryptError::IoError(std::io::Error::new(std::io::ErrorKind::NotFound, "x")).into();
+        assert_eq!(error.kind(), ErrorKind::Io);
+        let error = error.with_code("701-2");
+        assert_eq!(error.code(), "701-2");
+    }
+}
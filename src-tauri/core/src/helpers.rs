@@ -1,4 +1,7 @@
-use crate::backend::cross_platform::get_app_data_path;
+use crate::error::AppError;
+use crate::journal::Journal;
+use crate::output::work_dir;
+use crate::settings::Settings;
 use rand::Rng;
 use regex::Regex;
 use serde_json::Value;
@@ -39,75 +42,21 @@ pub fn random_company() -> &'static str {
     items[rand::thread_rng().gen_range(0..items.len())]
 }
 
-pub fn logout_from_app() -> Result<bool, std::io::Error> {
-    let settings_path = get_app_data_path(Some("settings.json"));
-    let books_path = get_app_data_path(Some("books"));
-
-    if settings_path.exists() {
-        fs::remove_file(&settings_path)?;
-    }
-
-    if books_path.exists() {
-        fs::remove_dir_all(&books_path)?;
-    }
-
-    Ok(!settings_path.exists() && !books_path.exists())
-}
-
-pub fn get_settings(item: Option<&str>) -> Option<Value> {
-    let settings_path = get_app_data_path(Some("settings.json"));
-
-    if settings_path.exists() {
-        let data = fs::read_to_string(settings_path).ok()?;
-        if let Ok(json) = serde_json::from_str::<Value>(&data) {
-            return item.map_or(Some(json.clone()), |key| json.get(key).cloned());
-        }
-    }
-    Some(Value::Null)
-}
-
-pub fn set_settings(new_settings: Value) -> Result<(), std::io::Error> {
-    let settings_path = get_app_data_path(Some("settings.json"));
-    // println!("jrr| Settings path: {:?}", settings_path);
-
-    let mut data = get_settings(None).unwrap_or_else(|| serde_json::json!({}));
-    // println!("jrr| Current settings data: {:?}", data);
-
-    if data.is_null() {
-        data = serde_json::json!({});
-    }
-
-    if let Some(new_obj) = new_settings.as_object() {
-        if let Some(obj) = data.as_object_mut() {
-            for (key, value) in new_obj {
-                obj.insert(key.clone(), value.clone());
-            }
-        }
-    } else {
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::InvalidInput,
-            "jrr| new_settings must be a JSON object",
-        ));
-    }
-
-    if let Some(parent) = settings_path.parent() {
-        if !parent.exists() {
-            // println!("jrr| Creating parent directory: {:?}", parent);
-            fs::create_dir_all(parent)?;
-        }
-    }
-
-    let serialized_data = serde_json::to_string(&data)?;
-    // println!("jrr| Serialized settings data: {}", serialized_data);
-
-    fs::write(&settings_path, serialized_data)?;
-    // println!("jrr| Settings saved successfully to {:?}", settings_path);
-
-    Ok(())
+/// Forgets the session of the active profile. Converted books, the catalog
+/// and the working files stay; `output::purge` is the way to remove those.
+pub fn logout_from_app() -> Result<(), AppError> {
+    Settings::update(|settings| {
+        settings.app = None;
+        settings.initial_token = None;
+        settings.expires = None;
+        settings.username = None;
+        settings.device_name = None;
+        settings.device_uid = None;
+    })
 }
 
 pub fn clear_residue(book_id: &str) -> Result<(), std::io::Error> {
-    let path = get_app_data_path(Some("books")).join(book_id);
+    let path = work_dir().join(book_id);
     if path.with_extension("zip").exists() {
         fs::remove_file(path.with_extension("zip"))?;
     }
@@ -117,7 +66,7 @@ pub fn clear_residue(book_id: &str) -> Result<(), std::io::Error> {
     if path.exists() {
         fs::remove_dir_all(path)?;
     }
-    Ok(())
+    Journal::remove(book_id)
 }
 
 pub fn nl2br(str: &str, rtl: bool) -> String {
@@ -138,7 +87,7 @@ pub fn nl2br(str: &str, rtl: bool) -> String {
 }
 
 pub fn get_book_index(book_id: &str, item: &str) -> Option<Value> {
-    let path = get_app_data_path(Some("books"))
+    let path = work_dir()
         .join(book_id)
         .join("Index")
         .join(format!("{}.json", item));
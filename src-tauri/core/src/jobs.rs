@@ -0,0 +1,222 @@
+#[cfg(feature = "network")]
+use crate::download::discard_partial;
+use crate::error::AppError;
+use crate::helpers::clear_residue;
+use crate::journal::Journal;
+#[cfg(feature = "network")]
+use crate::output::work_dir;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
+use uuid::Uuid;
+
+#[derive(Default)]
+struct CancelState {
+    cancelled: AtomicBool,
+    notify: Notify,
+}
+
+/// Shared flag telling every stage of a job to stop. Cheap to clone.
+#[derive(Clone, Default)]
+pub struct CancelToken(Arc<CancelState>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.cancelled.store(true, Ordering::SeqCst);
+        self.0.notify.notify_waiters();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Errors out if the job was cancelled. Called between units of work.
+    pub fn check(&self) -> Result<(), AppError> {
+        if self.is_cancelled() {
+            return Err(AppError::cancelled());
+        }
+        Ok(())
+    }
+
+    /// Resolves once the token is cancelled.
+    pub async fn cancelled(&self) {
+        let notified = self.0.notify.notified();
+        if self.is_cancelled() {
+            return;
+        }
+        notified.await;
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct JobInfo {
+    pub job_id: String,
+    pub book_id: String,
+}
+
+/// Download-and-convert jobs currently running, by job id.
+#[derive(Default)]
+pub struct JobRegistry {
+    jobs: Mutex<HashMap<String, (String, CancelToken)>>,
+}
+
+impl JobRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a job for `book_id`. The job is forgotten when the returned
+    /// handle is dropped.
+    pub fn start(self: &Arc<Self>, book_id: &str) -> JobHandle {
+        let mut jobs = self.jobs.lock().unwrap();
+        self.register(&mut jobs, book_id)
+    }
+
+    /// Like `start`, but returns `None` instead when `book_id` already has a
+    /// job. The check and the registration happen under one lock, so two
+    /// callers can not both get a job for the same book.
+    pub fn start_exclusive(self: &Arc<Self>, book_id: &str) -> Option<JobHandle> {
+        let mut jobs = self.jobs.lock().unwrap();
+        if jobs.values().any(|(job_book_id, _)| job_book_id == book_id) {
+            return None;
+        }
+        Some(self.register(&mut jobs, book_id))
+    }
+
+    fn register(
+        self: &Arc<Self>,
+        jobs: &mut HashMap<String, (String, CancelToken)>,
+        book_id: &str,
+    ) -> JobHandle {
+        let info = JobInfo {
+            job_id: Uuid::new_v4().to_string(),
+            book_id: book_id.to_string(),
+        };
+        let token = CancelToken::new();
+        jobs.insert(info.job_id.clone(), (info.book_id.clone(), token.clone()));
+        JobHandle {
+            info,
+            token,
+            registry: self.clone(),
+        }
+    }
+
+    /// Cancels a job by id. Returns false if it is not running.
+    pub fn cancel(&self, job_id: &str) -> bool {
+        match self.jobs.lock().unwrap().get(job_id) {
+            Some((_, token)) => {
+                token.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Cancels every running job for `book_id`.
+    pub fn cancel_book(&self, book_id: &str) -> bool {
+        let jobs = self.jobs.lock().unwrap();
+        let mut found = false;
+        for (job_book_id, token) in jobs.values() {
+            if job_book_id == book_id {
+                token.cancel();
+                found = true;
+            }
+        }
+        found
+    }
+
+    pub fn list(&self) -> Vec<JobInfo> {
+        self.jobs
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(job_id, (book_id, _))| JobInfo {
+                job_id: job_id.clone(),
+                book_id: book_id.clone(),
+            })
+            .collect()
+    }
+}
+
+pub struct JobHandle {
+    pub info: JobInfo,
+    pub token: CancelToken,
+    registry: Arc<JobRegistry>,
+}
+
+impl Drop for JobHandle {
+    fn drop(&mut self) {
+        self.registry.jobs.lock().unwrap().remove(&self.info.job_id);
+    }
+}
+
+/// Removes a book's working files, journal and partial downloads when the
+/// user cancels its job.
+///
+/// After a plain error or a panic the working files stay only while the
+/// journal has an intact stage for the next attempt to resume from; without
+/// one they could not be reused and are removed as well. Partial downloads
+/// are kept in that case, since they resume on their own. The trade-off is
+/// that a job failing before its first stage finishes leaves nothing behind
+/// to inspect, only the error in its conversion history.
+pub struct ResidueGuard {
+    book_id: String,
+    token: CancelToken,
+    armed: bool,
+}
+
+impl ResidueGuard {
+    pub fn new(book_id: &str, token: &CancelToken) -> Self {
+        ResidueGuard {
+            book_id: book_id.to_string(),
+            token: token.clone(),
+            armed: true,
+        }
+    }
+
+    /// Called once the job succeeded and cleaned up after itself.
+    pub fn disarm(&mut self) {
+        self.armed = false;
+    }
+
+    /// Drops what the cancelled download left behind.
+    #[cfg(feature = "network")]
+    fn discard_partial_downloads(&self) {
+        let package = work_dir().join(&self.book_id);
+        for dest in [
+            package.with_extension("zip"),
+            package.with_extension("zip.body"),
+        ] {
+            if let Err(e) = discard_partial(&dest) {
+                tracing::warn!("Could not remove partial download: {}", e);
+            }
+        }
+    }
+}
+
+impl Drop for ResidueGuard {
+    fn drop(&mut self) {
+        if !self.armed {
+            return;
+        }
+        let cancelled = self.token.is_cancelled();
+        let resumable =
+            Journal::load(&self.book_id).is_some_and(|journal| journal.resume_point().is_some());
+        if !cancelled && resumable {
+            return;
+        }
+        if let Err(e) = clear_residue(&self.book_id) {
+            tracing::warn!("Could not clear residue of {}: {}", self.book_id, e);
+        }
+        #[cfg(feature = "network")]
+        if cancelled {
+            self.discard_partial_downloads();
+        }
+    }
+}
@@ -0,0 +1,288 @@
+use crate::book::Book;
+use crate::error::AppError;
+use crate::helpers::clear_residue;
+use crate::output::work_dir;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// Failed jobs keep their working files this long for a retry.
+const STALE_FAILURE_SECS: u64 = 7 * 24 * 60 * 60;
+
+/// Steps from a store package to a finished book, in order.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Stage {
+    /// The package as the store sent it.
+    Downloaded,
+    /// A `.zip.body` joined with its header into a readable `.zip`. Plain
+    /// packages reach this stage as soon as they are downloaded.
+    Combined,
+    /// The package unpacked and decrypted into its folder.
+    Extracted,
+    /// The book in the output folder.
+    Generated,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct StageRecord {
+    pub stage: Stage,
+    /// What the stage produced.
+    pub path: String,
+    /// Size of the artifact, in bytes; of all its files for a folder.
+    #[serde(default)]
+    pub size: u64,
+    /// Newest modification time in the artifact, in nanoseconds since the
+    /// epoch.
+    #[serde(default)]
+    pub modified: u64,
+    pub finished_at: u64,
+}
+
+impl StageRecord {
+    /// Whether the artifact is still on disk as the stage left it. Only its
+    /// metadata is read, so this stays cheap for large packages; records of
+    /// older versions carry none and never match.
+    pub fn verify(&self) -> bool {
+        fingerprint(Path::new(&self.path)).is_ok_and(|f| f == (self.size, self.modified))
+    }
+}
+
+/// Progress of one book through the pipeline, kept next to its working
+/// files so an attempt after a failure or a crash can pick up where the
+/// last one stopped.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct Journal {
+    pub book_id: String,
+    /// The store file the stages belong to; a newer file starts over.
+    pub file_id: String,
+    pub url: String,
+    pub header: String,
+    /// Key from the package header, needed to extract it.
+    pub key: Vec<i32>,
+    pub stages: Vec<StageRecord>,
+    /// Why the last attempt failed. Unset while an attempt is running, so a
+    /// journal without it after a restart belongs to an interrupted job.
+    pub error: Option<String>,
+    pub updated_at: u64,
+}
+
+impl Journal {
+    pub fn new(book_id: &str, file_id: &str) -> Self {
+        Journal {
+            book_id: book_id.to_string(),
+            file_id: file_id.to_string(),
+            ..Journal::default()
+        }
+    }
+
+    pub fn path(book_id: &str) -> PathBuf {
+        work_dir().join(format!("{}.journal.json", book_id))
+    }
+
+    /// The journal of `book_id`, if there is a readable one.
+    pub fn load(book_id: &str) -> Option<Journal> {
+        read(&Self::path(book_id))
+    }
+
+    /// Writes the journal through a temporary file, so a crash leaves the
+    /// old or the new one.
+    pub fn save(&mut self) -> Result<(), AppError> {
+        self.updated_at = Utc::now().timestamp() as u64;
+        let path = Self::path(&self.book_id);
+        let write = || -> io::Result<()> {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let temp = path.with_extension("json.tmp");
+            fs::write(&temp, serde_json::to_vec(self)?)?;
+            fs::rename(&temp, &path)
+        };
+        write().map_err(|e| {
+            AppError::io("1019", "Could not save the conversion journal").with_detail(format!(
+                "{}: {}",
+                path.display(),
+                e
+            ))
+        })
+    }
+
+    /// Records that `stage` finished with `artifact`, forgetting any later
+    /// stage, and saves the journal.
+    pub fn record(&mut self, stage: Stage, artifact: &Path) -> Result<(), AppError> {
+        let (size, modified) = fingerprint(artifact).map_err(|e| {
+            AppError::io("1019", "Could not read a conversion step").with_detail(format!(
+                "{}: {}",
+                artifact.display(),
+                e
+            ))
+        })?;
+        self.stages.retain(|s| s.stage < stage);
+        self.stages.push(StageRecord {
+            stage,
+            path: artifact.display().to_string(),
+            size,
+            modified,
+            finished_at: Utc::now().timestamp() as u64,
+        });
+        tracing::info!("{:?} stage finished", stage);
+        self.save()
+    }
+
+    /// The latest stage whose artifact is intact, where a retry resumes.
+    pub fn resume_point(&self) -> Option<&StageRecord> {
+        self.stages.iter().rev().find(|s| s.verify())
+    }
+
+    pub fn artifact(&self, stage: Stage) -> Option<PathBuf> {
+        self.stages
+            .iter()
+            .find(|s| s.stage == stage)
+            .map(|s| PathBuf::from(&s.path))
+    }
+
+    pub fn remove(book_id: &str) -> io::Result<()> {
+        let path = Self::path(book_id);
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+}
+
+/// Notes why an attempt failed. Its working files stay for the next one.
+pub fn mark_failed(book_id: &str, error: &AppError) {
+    let Some(mut journal) = Journal::load(book_id) else {
+        return;
+    };
+    journal.error = Some(error.to_string());
+    if let Err(e) = journal.save() {
+        tracing::warn!("{}", e);
+    }
+}
+
+fn read(path: &Path) -> Option<Journal> {
+    let data = fs::read_to_string(path).ok()?;
+    serde_json::from_str(&data)
+        .map_err(|e| tracing::warn!("Ignoring damaged journal {}: {}", path.display(), e))
+        .ok()
+}
+
+/// Every journal in the working folder of the active profile.
+pub fn journals() -> Vec<Journal> {
+    let Ok(entries) = fs::read_dir(work_dir()) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.to_string_lossy().ends_with(".journal.json"))
+        .filter_map(|path| read(&path))
+        .collect()
+}
+
+/// Goes through the journals the last run left behind. Jobs that were
+/// interrupted and still have an intact stage are returned so they can be
+/// queued again; the rest, and failures older than `STALE_FAILURE_SECS`, are
+/// removed with their working files.
+pub fn recover(known: &[Book]) -> Vec<Book> {
+    let now = Utc::now().timestamp() as u64;
+    let mut resume = Vec::new();
+    for journal in journals() {
+        let book = known.iter().find(|b| b.id == journal.book_id);
+        match (book, &journal.error) {
+            (Some(book), None) if journal.resume_point().is_some() => resume.push(book.clone()),
+            (Some(_), Some(_)) if now.saturating_sub(journal.updated_at) < STALE_FAILURE_SECS => {}
+            _ => {
+                tracing::info!("Cleaning up the unfinished job of {}", journal.book_id);
+                if let Err(e) = clear_residue(&journal.book_id) {
+                    tracing::warn!("Could not clear residue of {}: {}", journal.book_id, e);
+                }
+            }
+        }
+    }
+    resume
+}
+
+/// Size and newest modification time of a file, or of everything in a
+/// folder, which is enough to notice a half-written or half-moved artifact
+/// without reading it again.
+pub fn fingerprint(path: &Path) -> io::Result<(u64, u64)> {
+    let metadata = fs::metadata(path)?;
+    let mut size = 0;
+    let mut modified = modified_nanos(&metadata)?;
+    if metadata.is_dir() {
+        add_files(path, &mut size, &mut modified)?;
+    } else {
+        size = metadata.len();
+    }
+    Ok((size, modified))
+}
+
+fn add_files(dir: &Path, size: &mut u64, modified: &mut u64) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        *modified = (*modified).max(modified_nanos(&metadata)?);
+        if metadata.is_dir() {
+            add_files(&entry.path(), size, modified)?;
+        } else {
+            *size += metadata.len();
+        }
+    }
+    Ok(())
+}
+
+fn modified_nanos(metadata: &fs::Metadata) -> io::Result<u64> {
+    let since_epoch = metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    Ok(since_epoch.as_nanos() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resume_point_skips_damaged_artifacts() {
+        let dir = std::env::temp_dir().join(format!("jreader-journal-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(dir.join("book")).unwrap();
+        let package = dir.join("book.zip");
+        fs::write(&package, b"package").unwrap();
+        fs::write(dir.join("book").join("info.json"), b"{}").unwrap();
+
+        let record = |stage, path: &Path| {
+            let (size, modified) = fingerprint(path).unwrap();
+            StageRecord {
+                stage,
+                path: path.display().to_string(),
+                size,
+                modified,
+                finished_at: 0,
+            }
+        };
+        let journal = Journal {
+            stages: vec![
+                record(Stage::Combined, &package),
+                record(Stage::Extracted, &dir.join("book")),
+            ],
+            ..Journal::new("book", "file")
+        };
+        assert_eq!(journal.resume_point().unwrap().stage, Stage::Extracted);
+
+        // A chapter half moved out of the folder invalidates the extraction.
+        fs::write(dir.join("book").join("chapter.html"), b"<p>").unwrap();
+        assert_eq!(journal.resume_point().unwrap().stage, Stage::Combined);
+
+        fs::write(&package, b"truncated").unwrap();
+        assert_eq!(journal.resume_point(), None);
+
+        let _ = fs::remove_dir_all(dir);
+    }
+}
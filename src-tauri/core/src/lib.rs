@@ -0,0 +1,43 @@
+//! Download, decryption and conversion of Jarir and Rufoof books, with no
+//! UI attached. The Tauri app and `jarir-reader-cli` are both built on it.
+//!
+//! Features: `network` talks to the stores, `epub`, `audio` and `pdf` pick
+//! the output formats, and `cli` builds the command line tool.
+
+#[cfg(feature = "network")]
+pub mod api_calls;
+#[cfg(feature = "audio")]
+pub mod audio;
+pub mod book;
+pub mod book_generator;
+pub mod calibre;
+pub mod catalog;
+pub mod credentials;
+pub mod cross_platform;
+pub mod decrypt;
+#[cfg(feature = "network")]
+pub mod download;
+#[cfg(feature = "epub")]
+pub mod epub;
+pub mod error;
+pub mod helpers;
+pub mod jobs;
+pub mod journal;
+pub mod library;
+pub mod logging;
+pub mod network;
+pub mod output;
+pub mod profiles;
+pub mod progress;
+pub mod queue;
+#[cfg(feature = "network")]
+pub mod request;
+#[cfg(feature = "network")]
+pub mod session;
+pub mod settings;
+pub mod sources;
+pub mod storage;
+#[cfg(feature = "network")]
+pub mod store;
+#[cfg(feature = "epub")]
+pub mod transliteration;
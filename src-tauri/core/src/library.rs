@@ -0,0 +1,351 @@
+use crate::book::Book;
+use crate::catalog::{BookPage, BookQuery, Catalog, Conversion};
+use crate::error::{AppError, ErrorKind};
+use crate::output::{output_dir, work_dir};
+use crate::profiles::list_profiles;
+use crate::sources::sources_dir;
+use chrono::Utc;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// How long the cached library is used before `get_user_books` syncs again.
+const CACHE_TTL_SECS: u64 = 5 * 60 * 60;
+
+/// What changed in the store library since the last sync, by book id.
+#[derive(Debug, Serialize, Default, Clone, PartialEq)]
+pub struct LibraryChanges {
+    /// New purchases.
+    pub added: Vec<String>,
+    /// Books still listed whose `book_access` was taken away.
+    pub revoked: Vec<String>,
+    /// Downloaded books with a newer file on the store.
+    pub updated: Vec<String>,
+    /// Books no longer listed at all. They stay in the catalog without
+    /// access.
+    pub removed: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LibrarySync {
+    pub books: Vec<Book>,
+    pub changes: LibraryChanges,
+}
+
+/// A catalog entry tagged with its profile, for the view of all profiles.
+#[derive(Debug, Serialize)]
+pub struct ProfileBook {
+    pub profile: String,
+    pub profile_name: String,
+    #[serde(flatten)]
+    pub book: Book,
+}
+
+/// The local catalog, however old.
+pub fn cached_books() -> Result<Vec<Book>, AppError> {
+    Catalog::open()?.books()
+}
+
+/// The local catalog if it was synced recently enough to skip the store.
+pub fn fresh_cached_books() -> Result<Option<Vec<Book>>, AppError> {
+    let catalog = Catalog::open()?;
+    let Some(synced_at) = catalog.synced_at()? else {
+        return Ok(None);
+    };
+    let age = (Utc::now().timestamp_millis() as u64).saturating_sub(synced_at) / 1000;
+    if age >= CACHE_TTL_SECS {
+        return Ok(None);
+    }
+    let books = catalog.books()?;
+    Ok((!books.is_empty()).then_some(books))
+}
+
+/// The catalogs of every profile in one list. A profile whose catalog can
+/// not be read is left out rather than hiding all the others.
+pub fn all_profile_books() -> Result<Vec<ProfileBook>, AppError> {
+    let mut books = Vec::new();
+    for profile in list_profiles()?.profiles {
+        let items = match Catalog::open_for(&profile.id).and_then(|c| c.books()) {
+            Ok(items) => items,
+            Err(e) => {
+                tracing::warn!("Skipping profile {}: {}", profile.id, e);
+                continue;
+            }
+        };
+        books.extend(items.into_iter().map(|book| ProfileBook {
+            profile: profile.id.clone(),
+            profile_name: profile.name.clone(),
+            book,
+        }));
+    }
+    Ok(books)
+}
+
+/// Merges a freshly fetched store list into the catalog and saves it.
+pub fn apply_sync(remote: Vec<Book>) -> Result<LibrarySync, AppError> {
+    Catalog::open()?.replace_all(
+        |local| {
+            let sync = merge_library(&local, remote);
+            (sync.books.clone(), sync)
+        },
+        Utc::now().timestamp_millis() as u64,
+    )
+}
+
+/// Changes one catalog entry in place, keeping the sync time.
+pub fn update_cached_book(book_id: &str, update: impl FnOnce(&mut Book)) -> Result<(), AppError> {
+    Catalog::open()?.update_book(book_id, update).map(|_| ())
+}
+
+/// Searches the local catalog, see `BookQuery`.
+pub fn query_books(query: &BookQuery) -> Result<BookPage, AppError> {
+    Catalog::open()?.query(query)
+}
+
+pub fn record_conversion(conversion: &Conversion) -> Result<(), AppError> {
+    Catalog::open()?.record_conversion(conversion)
+}
+
+/// Adds an attempt to convert a book to its history, and returns its
+/// status. A history that can not be written is only logged.
+pub fn record_attempt(
+    book_id: &str,
+    file_id: Option<String>,
+    started_at: u64,
+    result: &Result<String, AppError>,
+) -> &'static str {
+    let status = match result {
+        Ok(_) => "done",
+        Err(e) if e.kind() == ErrorKind::Cancelled => "cancelled",
+        Err(_) => "failed",
+    };
+    let conversion = Conversion {
+        book_id: book_id.to_string(),
+        file_id,
+        started_at,
+        finished_at: Utc::now().timestamp() as u64,
+        status: status.to_string(),
+        output: result.as_ref().ok().cloned(),
+        error: result.as_ref().err().map(|e| e.to_string()),
+    };
+    if let Err(e) = record_conversion(&conversion) {
+        tracing::warn!("Could not record the conversion: {}", e);
+    }
+    status
+}
+
+/// Every conversion of a book, newest first.
+pub fn conversion_history(book_id: &str) -> Result<Vec<Conversion>, AppError> {
+    Catalog::open()?.conversions(book_id)
+}
+
+/// Every converted book that can be read without the store: catalog entries
+/// whose file is still on disk, then files the catalog does not know about.
+pub fn offline_books() -> Result<Vec<Book>, AppError> {
+    let mut books: Vec<Book> = cached_books()?
+        .into_iter()
+        .filter(|b| {
+            b.book_path
+                .as_deref()
+                .is_some_and(|p| Path::new(p).exists())
+        })
+        .collect();
+    let known: HashSet<String> = books.iter().filter_map(|b| b.book_path.clone()).collect();
+
+    let mut files = Vec::new();
+    output_files(&output_dir(), OUTPUT_DEPTH, &known, &mut files);
+    let mut found: Vec<Book> = files
+        .into_iter()
+        .filter_map(|path| local_book(&path))
+        .collect();
+    found.sort_by(|a, b| a.title.cmp(&b.title));
+    books.extend(found);
+    Ok(books)
+}
+
+/// How deep books can sit in the output folder: author, title and the
+/// folder of an audio book.
+const OUTPUT_DEPTH: usize = 4;
+
+/// Files under `dir` down to `depth` folders, whatever the layout put them
+/// in, leaving out the `known` books with their folders. The working and
+/// sources folders are skipped should the output folder hold them.
+fn output_files(dir: &Path, depth: usize, known: &HashSet<String>, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for path in entries.flatten().map(|entry| entry.path()) {
+        if known.contains(&path.display().to_string()) {
+            continue;
+        }
+        if !path.is_dir() {
+            files.push(path);
+        } else if depth > 0 && path != work_dir() && path != sources_dir() {
+            output_files(&path, depth - 1, known, files);
+        }
+    }
+}
+
+/// A book for an output file with no catalog entry, titled after the file.
+fn local_book(path: &Path) -> Option<Book> {
+    let book_type = match path.extension()?.to_str()?.to_lowercase().as_str() {
+        "epub" => "epub",
+        "m3u8" => "mp3",
+        "pdf" => "pdf",
+        _ => return None,
+    };
+    let file_name = path.file_name()?.to_string_lossy().to_string();
+    let title = path.file_stem()?.to_string_lossy().to_string();
+    let downloaded_at = fs::metadata(path)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs());
+
+    Some(Book {
+        id: format!("file:{}", file_name),
+        name: title.clone(),
+        title,
+        book_type: book_type.to_string(),
+        book_path: Some(path.display().to_string()),
+        access: true,
+        downloaded_at,
+        ..Book::default()
+    })
+}
+
+/// Merges the store list into the local catalog: downloads are carried over
+/// and every difference is reported. Books the store no longer lists stay at
+/// the end without access, so their downloads and history are kept.
+pub fn merge_library(local: &[Book], remote: Vec<Book>) -> LibrarySync {
+    let known: HashMap<&str, &Book> = local.iter().map(|b| (b.id.as_str(), b)).collect();
+    let listed: HashSet<String> = remote.iter().map(|b| b.id.clone()).collect();
+    let mut changes = LibraryChanges::default();
+
+    let mut books: Vec<Book> = remote
+        .into_iter()
+        .map(|mut book| {
+            let Some(previous) = known.get(book.id.as_str()) else {
+                // Everything is new on the first sync, that is not news.
+                if !local.is_empty() {
+                    changes.added.push(book.id.clone());
+                }
+                return book;
+            };
+
+            if previous.access && !book.access {
+                changes.revoked.push(book.id.clone());
+            }
+
+            book.book_path = previous.book_path.clone();
+            book.downloaded_at = previous.downloaded_at;
+            // Downloads from before file ids were recorded used `file_id`.
+            book.downloaded_file_id = previous.downloaded_file_id.clone().or_else(|| {
+                previous
+                    .book_path
+                    .as_ref()
+                    .map(|_| previous.file_id.clone())
+            });
+
+            if let Some(downloaded) = &book.downloaded_file_id {
+                book.update_available = downloaded != book.current_file_id();
+                if book.update_available && !previous.update_available {
+                    changes.updated.push(book.id.clone());
+                }
+            }
+            book
+        })
+        .collect();
+
+    for book in local.iter().filter(|b| !listed.contains(&b.id)) {
+        // Reported once, when the store stops listing it.
+        if book.access {
+            changes.removed.push(book.id.clone());
+        }
+        books.push(Book {
+            access: false,
+            update_available: false,
+            ..book.clone()
+        });
+    }
+
+    LibrarySync { books, changes }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn book(id: &str, file_id: &str, latest_file_id: &str) -> Book {
+        Book {
+            id: id.to_string(),
+            access: true,
+            file_id: file_id.to_string(),
+            latest_file_id: latest_file_id.to_string(),
+            ..Book::default()
+        }
+    }
+
+    #[test]
+    fn test_merge_library_reports_changes() {
+        let downloaded = Book {
+            book_path: Some("/books/a.epub".to_string()),
+            downloaded_at: Some(1),
+            ..book("a", "10", "10")
+        };
+        let local = vec![downloaded, book("b", "20", "20"), book("c", "30", "30")];
+        let remote = vec![
+            book("a", "10", "11"),
+            Book {
+                access: false,
+                ..book("b", "20", "20")
+            },
+            book("d", "40", "40"),
+        ];
+
+        let sync = merge_library(&local, remote);
+
+        assert_eq!(
+            sync.changes,
+            LibraryChanges {
+                added: vec!["d".to_string()],
+                revoked: vec!["b".to_string()],
+                updated: vec!["a".to_string()],
+                removed: vec!["c".to_string()],
+            }
+        );
+        let a = &sync.books[0];
+        assert_eq!(a.book_path.as_deref(), Some("/books/a.epub"));
+        let c = &sync.books[3];
+        assert_eq!(c.id, "c");
+        assert!(!c.access);
+        assert_eq!(a.downloaded_file_id.as_deref(), Some("10"));
+        assert!(a.update_available);
+
+        // A second sync against the same list has nothing new to report.
+        let listed = sync.books[..3].to_vec();
+        let again = merge_library(&sync.books, listed);
+        assert_eq!(again.changes, LibraryChanges::default());
+        assert!(again.books[0].update_available);
+        assert_eq!(again.books.len(), 4);
+    }
+
+    #[test]
+    fn test_local_book_from_output_file() {
+        let dir = std::env::temp_dir().join(format!("jreader-offline-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let epub = dir.join("عنوان الكتاب.epub");
+        fs::write(&epub, b"epub").unwrap();
+
+        let book = local_book(&epub).unwrap();
+        assert_eq!(book.id, "file:عنوان الكتاب.epub");
+        assert_eq!(book.title, "عنوان الكتاب");
+        assert_eq!(book.book_type, "epub");
+        assert!(book.downloaded_at.is_some());
+        assert!(local_book(&dir.join("1234.zip")).is_none());
+
+        let _ = fs::remove_dir_all(dir);
+    }
+}
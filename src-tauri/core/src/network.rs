@@ -0,0 +1,125 @@
+use crate::error::AppError;
+use crate::settings::Settings;
+#[cfg(feature = "network")]
+use reqwest::header::{HeaderMap, HeaderValue, CONTENT_TYPE, USER_AGENT};
+#[cfg(feature = "network")]
+use reqwest::{Certificate, Client, ClientBuilder, Proxy};
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "network")]
+use std::fs;
+#[cfg(feature = "network")]
+use std::time::Duration;
+
+const DEFAULT_CONNECT_TIMEOUT: u64 = 15;
+const DEFAULT_READ_TIMEOUT: u64 = 60;
+
+/// Connection options for the current profile, stored under `network` in
+/// `settings.json`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct NetworkSettings {
+    /// Skip certificate checks. Only for networks that intercept TLS and
+    /// cannot be fixed with `ca_bundle`.
+    pub accept_invalid_certs: bool,
+    /// Path to a PEM file with extra root certificates.
+    pub ca_bundle: Option<String>,
+    /// `http://`, `https://` or `socks5://` proxy URL, credentials included.
+    pub proxy: Option<String>,
+    pub connect_timeout_secs: u64,
+    pub read_timeout_secs: u64,
+}
+
+impl Default for NetworkSettings {
+    fn default() -> Self {
+        NetworkSettings {
+            accept_invalid_certs: false,
+            ca_bundle: None,
+            proxy: None,
+            connect_timeout_secs: DEFAULT_CONNECT_TIMEOUT,
+            read_timeout_secs: DEFAULT_READ_TIMEOUT,
+        }
+    }
+}
+
+impl NetworkSettings {
+    /// The saved settings, or the defaults when there are none or the
+    /// settings file cannot be read.
+    pub fn load() -> Self {
+        match Settings::load() {
+            Ok(settings) => settings.network.unwrap_or_default(),
+            Err(e) => {
+                tracing::warn!("Using default network settings: {}", e);
+                NetworkSettings::default()
+            }
+        }
+    }
+
+    pub fn save(&self) -> Result<(), AppError> {
+        Settings::update(|settings| settings.network = Some(self.clone()))
+            .map_err(|e| e.with_code("1002"))
+    }
+}
+
+/// Builds the client shared by every request, store calls and downloads alike.
+#[cfg(feature = "network")]
+pub fn build_client(settings: &NetworkSettings) -> Result<Client, AppError> {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("application/x-www-form-urlencoded"),
+    );
+    headers.insert(USER_AGENT, HeaderValue::from_static("okhttp/4.3.1"));
+
+    let mut builder = ClientBuilder::new()
+        .default_headers(headers)
+        .connect_timeout(Duration::from_secs(settings.connect_timeout_secs.max(1)))
+        .read_timeout(Duration::from_secs(settings.read_timeout_secs.max(1)));
+
+    if let Some(path) = settings.ca_bundle.as_deref().filter(|p| !p.is_empty()) {
+        let pem = fs::read(path).map_err(|e| {
+            AppError::io("1003", "Could not read the CA bundle")
+                .with_detail(format!("{}: {}", path, e))
+        })?;
+        let certificates = Certificate::from_pem_bundle(&pem)
+            .map_err(|e| AppError::network("1003", "Invalid CA bundle").with_detail(e))?;
+        for certificate in certificates {
+            builder = builder.add_root_certificate(certificate);
+        }
+    }
+
+    if let Some(url) = settings.proxy.as_deref().filter(|p| !p.is_empty()) {
+        let proxy = Proxy::all(url)
+            .map_err(|e| AppError::network("1004", "Invalid proxy address").with_detail(e))?;
+        builder = builder.proxy(proxy);
+    }
+
+    if settings.accept_invalid_certs {
+        tracing::warn!("TLS certificate verification is disabled for this profile");
+        builder = builder.danger_accept_invalid_certs(true);
+    }
+
+    builder
+        .build()
+        .map_err(|e| AppError::network("1005", "Could not create the HTTP client").with_detail(e))
+}
+
+#[cfg(all(test, feature = "network"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_network_settings_defaults_and_validation() {
+        let settings: NetworkSettings =
+            serde_json::from_value(serde_json::json!({ "proxy": "socks5://127.0.0.1:9050" }))
+                .unwrap();
+        assert!(!settings.accept_invalid_certs);
+        assert_eq!(settings.read_timeout_secs, DEFAULT_READ_TIMEOUT);
+        assert!(build_client(&settings).is_ok());
+
+        let broken = NetworkSettings {
+            ca_bundle: Some("/nonexistent/ca.pem".to_string()),
+            ..NetworkSettings::default()
+        };
+        assert_eq!(build_client(&broken).unwrap_err().code(), "1003");
+    }
+}
@@ -0,0 +1,344 @@
+use crate::book::Book;
+use crate::calibre;
+use crate::catalog::Catalog;
+use crate::cross_platform::get_app_data_path;
+use crate::error::AppError;
+use crate::helpers::clean_filename;
+use crate::profiles::profile_path;
+use crate::settings::Settings;
+use crate::sources::sources_dir;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The output folder inside a profile, used unless another one is picked.
+pub const OUTPUT_DIR: &str = "books";
+
+/// How converted books are arranged inside the output folder.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputLayout {
+    /// Every book directly in the output folder.
+    #[default]
+    Flat,
+    /// `Author/book.epub`
+    Author,
+    /// `Author/Title/book.epub`
+    AuthorTitle,
+    /// `Author/Title (id)/book.epub` with a `metadata.opf` and `cover.jpg`,
+    /// ready for Calibre's "add books from folders".
+    Calibre,
+}
+
+/// Where converted books go for the current profile, stored under `output`
+/// in `settings.json`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+#[serde(default)]
+pub struct OutputSettings {
+    /// Output folder; `books` in the profile folder when unset. Relative
+    /// paths are taken from the app data dir, so a portable install can
+    /// keep its books with it.
+    pub dir: Option<String>,
+    pub layout: OutputLayout,
+    /// Keep the decrypted source of each book, so `regenerate_book` can
+    /// build it again without the store.
+    pub keep_sources: bool,
+}
+
+impl OutputSettings {
+    /// The saved settings, or the defaults when there are none or the
+    /// settings file cannot be read.
+    pub fn load() -> Self {
+        match Settings::load() {
+            Ok(settings) => settings.output.unwrap_or_default(),
+            Err(e) => {
+                tracing::warn!("Using default output settings: {}", e);
+                OutputSettings::default()
+            }
+        }
+    }
+
+    pub fn root(&self) -> PathBuf {
+        match self.dir.as_deref().map(str::trim) {
+            Some(dir) if !dir.is_empty() => get_app_data_path(None).join(dir),
+            _ => profile_path(OUTPUT_DIR),
+        }
+    }
+
+    /// The folder a book's files are written to.
+    pub fn book_dir(&self, book: &Book) -> PathBuf {
+        let author = book
+            .authors
+            .first()
+            .map(|a| clean_filename(a, " "))
+            .filter(|a| !a.is_empty())
+            .unwrap_or_else(|| "Unknown".to_string());
+        match self.layout {
+            OutputLayout::Flat => self.root(),
+            OutputLayout::Author => self.root().join(author),
+            OutputLayout::AuthorTitle => self.root().join(author).join(title_name(book)),
+            OutputLayout::Calibre => {
+                self.root()
+                    .join(author)
+                    .join(format!("{} ({})", title_name(book), book.id))
+            }
+        }
+    }
+
+    /// The folder of an audio book, which holds its chapters and playlist.
+    pub fn audio_dir(&self, book: &Book) -> PathBuf {
+        match self.layout {
+            OutputLayout::AuthorTitle | OutputLayout::Calibre => self.book_dir(book),
+            _ => self.book_dir(book).join(title_name(book)),
+        }
+    }
+
+    /// Where the converted file or folder at `current` belongs.
+    fn target(&self, book: &Book, current: &Path) -> Option<PathBuf> {
+        if current.is_dir() {
+            return Some(self.audio_dir(book));
+        }
+        Some(self.book_dir(book).join(current.file_name()?))
+    }
+}
+
+fn title_name(book: &Book) -> String {
+    clean_filename(&book.title, " ")
+}
+
+/// Downloaded packages and extraction folders. Never shown to the user and
+/// never inside the output folder.
+pub fn work_dir() -> PathBuf {
+    profile_path("work")
+}
+
+pub fn output_dir() -> PathBuf {
+    OutputSettings::load().root()
+}
+
+/// Removes the working files, kept sources and the catalog of the active
+/// profile. The converted books go too only with `delete_converted`, and
+/// only when `confirmed`, as they can not be had back without downloading
+/// them again.
+pub fn purge(delete_converted: bool, confirmed: bool) -> Result<(), AppError> {
+    if delete_converted && !confirmed {
+        return Err(AppError::integrity(
+            "1018",
+            "Deleting the converted books needs confirmation",
+        ));
+    }
+
+    let mut catalog = Catalog::open()?;
+    if delete_converted {
+        let root = output_dir();
+        for book in catalog.books()? {
+            if let Some(path) = book.book_path.as_deref() {
+                remove_converted(Path::new(path), &root)?;
+            }
+        }
+    }
+    catalog.clear()?;
+
+    for dir in [work_dir(), sources_dir()] {
+        if dir.exists() {
+            fs::remove_dir_all(&dir)?;
+        }
+    }
+    Ok(())
+}
+
+/// Deletes one converted book. Paths outside the output folder, or inside
+/// the working folder, are left alone so a stale catalog entry can not take
+/// other files with it.
+fn remove_converted(path: &Path, root: &Path) -> Result<(), AppError> {
+    if !path.starts_with(root) || path == root || path.starts_with(work_dir()) {
+        tracing::warn!(
+            "Not deleting {}, it is outside the output folder",
+            path.display()
+        );
+        return Ok(());
+    }
+    if path.is_dir() {
+        fs::remove_dir_all(path)?;
+    } else if path.exists() {
+        fs::remove_file(path)?;
+        if OutputSettings::load().layout == OutputLayout::Calibre {
+            calibre::remove_sidecars(path)?;
+        }
+    }
+    remove_empty_parents(path, root);
+    Ok(())
+}
+
+/// Saves new output settings and moves every converted book the catalog
+/// knows into place. The settings stay saved when a book can not be moved;
+/// the error lists those books, which keep their old path.
+pub fn change_output_settings(settings: OutputSettings) -> Result<OutputSettings, AppError> {
+    let root = settings.root();
+    if root.starts_with(work_dir()) {
+        return Err(AppError::io(
+            "1017",
+            "The output folder can not be inside the working folder",
+        )
+        .with_detail(root.display().to_string()));
+    }
+    fs::create_dir_all(&root).map_err(|e| {
+        AppError::io("1017", "Could not create the output folder").with_detail(format!(
+            "{}: {}",
+            root.display(),
+            e
+        ))
+    })?;
+
+    let old = OutputSettings::load();
+    Settings::update(|s| s.output = Some(settings.clone()))?;
+    if old == settings {
+        return Ok(settings);
+    }
+
+    let mut catalog = Catalog::open()?;
+    let mut failed = Vec::new();
+    for book in catalog.books()? {
+        let Some(current) = book.book_path.as_deref().map(PathBuf::from) else {
+            continue;
+        };
+        let Some(target) = settings.target(&book, &current) else {
+            continue;
+        };
+        if !current.exists() || current == target {
+            continue;
+        }
+        match move_path(&current, &target) {
+            Ok(()) => {
+                let path = target.display().to_string();
+                catalog.update_book(&book.id, |b| b.book_path = Some(path))?;
+                move_sidecars(&book, &current, &target, &old, &settings);
+                remove_empty_parents(&current, &old.root());
+            }
+            Err(e) => {
+                tracing::warn!("Could not move {}: {}", current.display(), e);
+                failed.push(format!("{}: {}", current.display(), e));
+            }
+        }
+    }
+
+    if failed.is_empty() {
+        Ok(settings)
+    } else {
+        Err(AppError::io("1017", "Some books could not be moved").with_detail(failed.join("\n")))
+    }
+}
+
+/// Takes the Calibre metadata along with a moved book: the cover is moved
+/// and the OPF written anew, or both dropped when leaving that layout.
+fn move_sidecars(book: &Book, from: &Path, to: &Path, old: &OutputSettings, new: &OutputSettings) {
+    // An audio book's folder was moved with its sidecars in it.
+    let sidecars_at = if to.is_dir() { to } else { from };
+    let result = (|| -> io::Result<()> {
+        if new.layout == OutputLayout::Calibre {
+            let cover = calibre::read_cover(sidecars_at);
+            let store = calibre::recorded_store(sidecars_at);
+            calibre::write_sidecars(book, to, &store, cover.as_deref())?;
+        }
+        if old.layout == OutputLayout::Calibre
+            && (new.layout != OutputLayout::Calibre || !to.is_dir())
+        {
+            calibre::remove_sidecars(sidecars_at)?;
+        }
+        Ok(())
+    })();
+    if let Err(e) = result {
+        tracing::warn!("Could not move the Calibre metadata of {}: {}", book.id, e);
+    }
+}
+
+/// Renames `from` to `to`, copying when they are on different drives.
+pub(crate) fn move_path(from: &Path, to: &Path) -> io::Result<()> {
+    if to.exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} exists", to.display()),
+        ));
+    }
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    copy_all(from, to)?;
+    if from.is_dir() {
+        fs::remove_dir_all(from)
+    } else {
+        fs::remove_file(from)
+    }
+}
+
+fn copy_all(from: &Path, to: &Path) -> io::Result<()> {
+    if !from.is_dir() {
+        return fs::copy(from, to).map(|_| ());
+    }
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        copy_all(&entry.path(), &to.join(entry.file_name()))?;
+    }
+    Ok(())
+}
+
+/// Drops the author and title folders a moved book leaves empty, up to the
+/// old output folder.
+fn remove_empty_parents(moved: &Path, root: &Path) {
+    let mut dir = moved.parent();
+    while let Some(path) = dir {
+        if !path.starts_with(root) || path == root || fs::remove_dir(path).is_err() {
+            break;
+        }
+        dir = path.parent();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn book(title: &str, authors: &[&str]) -> Book {
+        Book {
+            title: title.to_string(),
+            authors: authors.iter().map(|a| a.to_string()).collect(),
+            ..Book::default()
+        }
+    }
+
+    #[test]
+    fn test_layouts_place_books_under_the_root() {
+        let settings = |layout| OutputSettings {
+            dir: Some("/library".to_string()),
+            layout,
+            ..OutputSettings::default()
+        };
+        let novel = book("A: Book", &["Some Author", "Other"]);
+
+        assert_eq!(
+            settings(OutputLayout::Flat).book_dir(&novel),
+            PathBuf::from("/library")
+        );
+        assert_eq!(
+            settings(OutputLayout::Author).book_dir(&novel),
+            PathBuf::from("/library/Some Author")
+        );
+        assert_eq!(
+            settings(OutputLayout::AuthorTitle).book_dir(&novel),
+            PathBuf::from("/library/Some Author/A Book")
+        );
+        assert_eq!(
+            settings(OutputLayout::AuthorTitle).audio_dir(&novel),
+            settings(OutputLayout::AuthorTitle).book_dir(&novel)
+        );
+        assert_eq!(
+            settings(OutputLayout::Author).book_dir(&book("Book", &[])),
+            PathBuf::from("/library/Unknown")
+        );
+    }
+}
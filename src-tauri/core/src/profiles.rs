@@ -0,0 +1,214 @@
+use crate::credentials::credential_store_for;
+use crate::cross_platform::get_app_data_path;
+use crate::error::AppError;
+use crate::helpers::uuid;
+use crate::output::OUTPUT_DIR;
+use serde::{Deserialize, Serialize};
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+
+/// The profile every install starts with. Its files stay at the top of the
+/// app data dir, where versions with a single account kept them.
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// One account: its own store, credentials, catalog and books folder, all
+/// inside `profile_dir(id)`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Profile {
+    pub id: String,
+    pub name: String,
+}
+
+/// Contents of `profiles.json`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Profiles {
+    pub active: String,
+    pub profiles: Vec<Profile>,
+}
+
+impl Default for Profiles {
+    fn default() -> Self {
+        Profiles {
+            active: DEFAULT_PROFILE.to_string(),
+            profiles: vec![Profile {
+                id: DEFAULT_PROFILE.to_string(),
+                name: "Default".to_string(),
+            }],
+        }
+    }
+}
+
+/// Id of the active profile once `profiles.json` has been read. Also
+/// serialises every change to that file.
+static ACTIVE: Mutex<Option<String>> = Mutex::new(None);
+
+fn lock() -> MutexGuard<'static, Option<String>> {
+    ACTIVE.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn profiles_path() -> PathBuf {
+    get_app_data_path(Some("profiles.json"))
+}
+
+fn read_profiles(path: &Path) -> Result<Profiles, AppError> {
+    if !path.exists() {
+        return Ok(Profiles::default());
+    }
+    let data = fs::read_to_string(path).map_err(|e| {
+        AppError::io("1012", "Could not read the profiles").with_detail(format!(
+            "{}: {}",
+            path.display(),
+            e
+        ))
+    })?;
+    serde_json::from_str(&data).map_err(|e| {
+        AppError::integrity("1012", "The profiles file is damaged").with_detail(format!(
+            "{}: {}",
+            path.display(),
+            e
+        ))
+    })
+}
+
+fn write_profiles(path: &Path, profiles: &Profiles) -> Result<(), AppError> {
+    let write = || -> std::io::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let temp = path.with_extension("json.tmp");
+        fs::write(&temp, serde_json::to_vec(profiles)?)?;
+        fs::rename(&temp, path)
+    };
+    write().map_err(|e| {
+        AppError::io("1012", "Could not save the profiles").with_detail(format!(
+            "{}: {}",
+            path.display(),
+            e
+        ))
+    })
+}
+
+fn not_found(id: &str) -> AppError {
+    AppError::integrity("1013", "No such profile").with_detail(id.to_string())
+}
+
+/// Where a profile keeps its files.
+pub fn profile_dir(id: &str) -> PathBuf {
+    if id == DEFAULT_PROFILE {
+        get_app_data_path(None)
+    } else {
+        get_app_data_path(Some("profiles")).join(id)
+    }
+}
+
+pub fn active_profile() -> String {
+    let mut active = lock();
+    if let Some(id) = active.as_ref() {
+        return id.clone();
+    }
+    let id = read_profiles(&profiles_path())
+        .map(|p| p.active)
+        .unwrap_or_else(|e| {
+            tracing::warn!("{}, using the default profile", e);
+            DEFAULT_PROFILE.to_string()
+        });
+    *active = Some(id.clone());
+    id
+}
+
+/// `name` inside the active profile's folder, e.g. `settings.json`.
+pub fn profile_path(name: &str) -> PathBuf {
+    profile_dir(&active_profile()).join(name)
+}
+
+pub fn list_profiles() -> Result<Profiles, AppError> {
+    let _guard = lock();
+    read_profiles(&profiles_path())
+}
+
+/// Creates an empty profile; its store is chosen on the first login.
+pub fn add_profile(name: &str) -> Result<Profile, AppError> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(AppError::integrity("1013", "The profile needs a name"));
+    }
+    let _guard = lock();
+    let path = profiles_path();
+    let mut profiles = read_profiles(&path)?;
+    if profiles.profiles.iter().any(|p| p.name == name) {
+        return Err(
+            AppError::integrity("1013", "A profile with this name exists").with_detail(name),
+        );
+    }
+
+    let profile = Profile {
+        // Lowercase so ids stay distinct on case-insensitive file systems.
+        id: uuid().to_lowercase(),
+        name: name.to_string(),
+    };
+    fs::create_dir_all(profile_dir(&profile.id))?;
+    profiles.profiles.push(profile.clone());
+    write_profiles(&path, &profiles)?;
+    Ok(profile)
+}
+
+/// Makes `id` the active profile. Callers are expected to drop anything
+/// they hold for the previous one, such as the cached session.
+pub fn switch_profile(id: &str) -> Result<Profile, AppError> {
+    let mut active = lock();
+    let path = profiles_path();
+    let mut profiles = read_profiles(&path)?;
+    let profile = profiles
+        .profiles
+        .iter()
+        .find(|p| p.id == id)
+        .cloned()
+        .ok_or_else(|| not_found(id))?;
+    profiles.active = profile.id.clone();
+    write_profiles(&path, &profiles)?;
+    *active = Some(profile.id.clone());
+    Ok(profile)
+}
+
+/// Deletes a profile with its credentials, catalog, working files and kept
+/// sources. Books converted into its default output folder stay where they
+/// are; `output::purge` is the one way to delete those. The default and the
+/// active profile can not be removed.
+pub fn remove_profile(id: &str) -> Result<(), AppError> {
+    let _guard = lock();
+    let path = profiles_path();
+    let mut profiles = read_profiles(&path)?;
+    if id == DEFAULT_PROFILE || id == profiles.active {
+        return Err(AppError::integrity(
+            "1013",
+            "The default and the active profile can not be removed",
+        ));
+    }
+    if !profiles.profiles.iter().any(|p| p.id == id) {
+        return Err(not_found(id));
+    }
+
+    credential_store_for(id).wipe()?;
+    let dir = profile_dir(id);
+    if dir.exists() {
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.file_name() == Some(OsStr::new(OUTPUT_DIR)) {
+                continue;
+            }
+            if path.is_dir() {
+                fs::remove_dir_all(&path)?;
+            } else {
+                fs::remove_file(&path)?;
+            }
+        }
+        // Only goes when no books were left in it.
+        let _ = fs::remove_dir(dir.join(OUTPUT_DIR));
+        let _ = fs::remove_dir(&dir);
+    }
+    profiles.profiles.retain(|p| p.id != id);
+    write_profiles(&path, &profiles)
+}
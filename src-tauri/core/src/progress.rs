@@ -0,0 +1,76 @@
+use serde::Serialize;
+use std::time::{Duration, Instant};
+
+/// Minimum time between two progress reports, so the webview isn't flooded.
+const REPORT_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Serialize, Clone)]
+pub struct DownloadProgress {
+    pub book_id: String,
+    pub downloaded: u64,
+    pub total: Option<u64>,
+    /// Bytes per second, averaged since the transfer started.
+    pub rate: u64,
+}
+
+/// Keeps the running byte count of a transfer and decides when it is worth
+/// reporting it.
+pub struct ProgressTracker {
+    book_id: String,
+    total: Option<u64>,
+    downloaded: u64,
+    resumed_from: u64,
+    started_at: Instant,
+    last_report: Option<Instant>,
+}
+
+impl ProgressTracker {
+    pub fn new(book_id: &str, total: Option<u64>) -> Self {
+        ProgressTracker {
+            book_id: book_id.to_string(),
+            total: total.filter(|t| *t > 0),
+            downloaded: 0,
+            resumed_from: 0,
+            started_at: Instant::now(),
+            last_report: None,
+        }
+    }
+
+    /// Counts `bytes` already on disk from an earlier attempt. They are part of
+    /// the progress but not of the transfer rate.
+    pub fn resume_from(&mut self, bytes: u64) {
+        self.downloaded = bytes;
+        self.resumed_from = bytes;
+    }
+
+    /// Records `bytes` more and returns a progress value if the throttle allows one.
+    pub fn advance(&mut self, bytes: u64) -> Option<DownloadProgress> {
+        self.downloaded += bytes;
+        let now = Instant::now();
+        let due = match self.last_report {
+            Some(last) => now.duration_since(last) >= REPORT_INTERVAL,
+            None => true,
+        };
+        if !due {
+            return None;
+        }
+        self.last_report = Some(now);
+        Some(self.snapshot())
+    }
+
+    /// Current progress, regardless of the throttle. Used for the final report.
+    pub fn snapshot(&self) -> DownloadProgress {
+        let elapsed = self.started_at.elapsed().as_secs_f64();
+        let rate = if elapsed > 0.0 {
+            ((self.downloaded - self.resumed_from) as f64 / elapsed) as u64
+        } else {
+            0
+        };
+        DownloadProgress {
+            book_id: self.book_id.clone(),
+            downloaded: self.downloaded,
+            total: self.total,
+            rate,
+        }
+    }
+}
@@ -0,0 +1,333 @@
+use crate::book::Book;
+use crate::error::AppError;
+use crate::profiles::profile_path;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::future::Future;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
+
+const DEFAULT_PARALLELISM: usize = 2;
+const MAX_PARALLELISM: usize = 8;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum QueueStatus {
+    Queued,
+    Running,
+    Done,
+    Failed,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct QueueItem {
+    pub book_id: String,
+    pub title: String,
+    pub status: QueueStatus,
+    pub book_path: Option<String>,
+    pub error: Option<String>,
+}
+
+/// The persisted part of the queue, also what the frontend receives.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct QueueState {
+    pub items: Vec<QueueItem>,
+    pub paused: bool,
+    pub parallelism: usize,
+}
+
+impl Default for QueueState {
+    fn default() -> Self {
+        QueueState {
+            items: Vec::new(),
+            paused: false,
+            parallelism: DEFAULT_PARALLELISM,
+        }
+    }
+}
+
+pub type JobFuture = Pin<Box<dyn Future<Output = Result<String, AppError>> + Send>>;
+/// Runs one book through download and conversion, returning the output path.
+pub type Job = Arc<dyn Fn(String) -> JobFuture + Send + Sync>;
+/// Called with a copy of the state every time it changes.
+pub type OnChange = Arc<dyn Fn(QueueState) + Send + Sync>;
+
+/// Download queue shared between the commands and the background scheduler.
+/// Its state is written to the profile's `queue.json` after every change so
+/// an unattended batch survives an app restart.
+pub struct DownloadQueue {
+    state: Mutex<QueueState>,
+    wake: Notify,
+    /// Locked after `state` when both are needed.
+    path: Mutex<PathBuf>,
+}
+
+fn queue_path() -> PathBuf {
+    profile_path("queue.json")
+}
+
+fn read_state(path: &Path) -> QueueState {
+    let mut state: QueueState = fs::read_to_string(path)
+        .ok()
+        .and_then(|data| serde_json::from_str(&data).ok())
+        .unwrap_or_default();
+    // Jobs that were running when the app stopped start over.
+    for item in state.items.iter_mut() {
+        if item.status == QueueStatus::Running {
+            item.status = QueueStatus::Queued;
+        }
+    }
+    state
+}
+
+impl DownloadQueue {
+    /// The queue of the active profile.
+    pub fn load() -> Arc<Self> {
+        Self::load_from(queue_path())
+    }
+
+    pub fn load_from(path: PathBuf) -> Arc<Self> {
+        Arc::new(DownloadQueue {
+            state: Mutex::new(read_state(&path)),
+            wake: Notify::new(),
+            path: Mutex::new(path),
+        })
+    }
+
+    /// Swaps in the queue of the profile that just became active. Must not
+    /// run while jobs of the previous profile are running.
+    pub fn reload(&self) {
+        let path = queue_path();
+        {
+            let mut state = self.state.lock().unwrap();
+            *state = read_state(&path);
+            *self.path.lock().unwrap() = path;
+        }
+        self.wake.notify_one();
+    }
+
+    pub fn snapshot(&self) -> QueueState {
+        self.state.lock().unwrap().clone()
+    }
+
+    /// Applies `change` to the state, persists it and wakes the scheduler.
+    fn update<T>(&self, change: impl FnOnce(&mut QueueState) -> T) -> Result<T, AppError> {
+        let result = {
+            let mut state = self.state.lock().unwrap();
+            let result = change(&mut state);
+            self.persist(&state)?;
+            result
+        };
+        self.wake.notify_one();
+        Ok(result)
+    }
+
+    /// Written while the state lock is held so saves never go out of order,
+    /// and through a synced temporary file so a crash leaves the old or the
+    /// new queue.
+    fn persist(&self, state: &QueueState) -> Result<(), AppError> {
+        let path = self.path.lock().unwrap().clone();
+        let write = || -> std::io::Result<()> {
+            let parent = path.parent().unwrap_or(Path::new("."));
+            fs::create_dir_all(parent)?;
+            let data = serde_json::to_vec(state)?;
+            let temp = path.with_extension("json.tmp");
+            let mut file = File::create(&temp)?;
+            file.write_all(&data)?;
+            file.sync_all()?;
+            fs::rename(&temp, &path)?;
+            if let Ok(dir) = File::open(parent) {
+                let _ = dir.sync_all();
+            }
+            Ok(())
+        };
+        write().map_err(|e| {
+            AppError::io("1001", "Could not save the download queue").with_detail(format!(
+                "{}: {}",
+                path.display(),
+                e
+            ))
+        })
+    }
+
+    /// Adds books to the end of the queue. Books already waiting or running
+    /// are skipped; finished or failed entries are queued again.
+    pub fn enqueue(&self, books: &[Book]) -> Result<usize, AppError> {
+        self.update(|state| {
+            let mut added = 0;
+            for book in books {
+                if let Some(index) = state.items.iter().position(|i| i.book_id == book.id) {
+                    match state.items[index].status {
+                        QueueStatus::Queued | QueueStatus::Running => continue,
+                        _ => {
+                            state.items.remove(index);
+                        }
+                    }
+                }
+                state.items.push(QueueItem {
+                    book_id: book.id.clone(),
+                    title: book.title.clone(),
+                    status: QueueStatus::Queued,
+                    book_path: None,
+                    error: None,
+                });
+                added += 1;
+            }
+            added
+        })
+    }
+
+    /// Queues every book the user can access that has not been converted yet.
+    pub fn enqueue_missing(&self, books: &[Book]) -> Result<usize, AppError> {
+        let missing: Vec<Book> = books
+            .iter()
+            .filter(|b| b.access && b.book_path.is_none())
+            .cloned()
+            .collect();
+        self.enqueue(&missing)
+    }
+
+    pub fn remove(&self, book_id: &str) -> Result<(), AppError> {
+        self.update(|state| {
+            state
+                .items
+                .retain(|i| i.book_id != book_id || i.status == QueueStatus::Running);
+        })
+    }
+
+    /// Drops finished entries from the list.
+    pub fn clear_finished(&self) -> Result<(), AppError> {
+        self.update(|state| {
+            state.items.retain(|i| i.status != QueueStatus::Done);
+        })
+    }
+
+    /// Moves a waiting book to `position` in the queue.
+    pub fn reorder(&self, book_id: &str, position: usize) -> Result<(), AppError> {
+        self.update(|state| {
+            if let Some(index) = state.items.iter().position(|i| i.book_id == book_id) {
+                let item = state.items.remove(index);
+                let position = position.min(state.items.len());
+                state.items.insert(position, item);
+            }
+        })
+    }
+
+    /// Paused queues let running jobs finish but start no new ones.
+    pub fn set_paused(&self, paused: bool) -> Result<(), AppError> {
+        self.update(|state| state.paused = paused)
+    }
+
+    pub fn set_parallelism(&self, parallelism: usize) -> Result<(), AppError> {
+        self.update(|state| state.parallelism = parallelism.clamp(1, MAX_PARALLELISM))
+    }
+
+    /// Marks the next waiting book as running, if a slot is free. Does not
+    /// wake the scheduler, which is the only caller.
+    fn take_next(&self) -> Result<Option<String>, AppError> {
+        let mut state = self.state.lock().unwrap();
+        let running = state
+            .items
+            .iter()
+            .filter(|i| i.status == QueueStatus::Running)
+            .count();
+        if state.paused || running >= state.parallelism {
+            return Ok(None);
+        }
+        let Some(item) = state
+            .items
+            .iter_mut()
+            .find(|i| i.status == QueueStatus::Queued)
+        else {
+            return Ok(None);
+        };
+        item.status = QueueStatus::Running;
+        item.error = None;
+        let book_id = item.book_id.clone();
+        self.persist(&state)?;
+        Ok(Some(book_id))
+    }
+
+    fn finish(&self, book_id: &str, result: Result<String, AppError>) -> Result<(), AppError> {
+        self.update(|state| {
+            if let Some(item) = state.items.iter_mut().find(|i| i.book_id == book_id) {
+                match result {
+                    Ok(path) => {
+                        item.status = QueueStatus::Done;
+                        item.book_path = Some(path);
+                    }
+                    Err(e) => {
+                        item.status = QueueStatus::Failed;
+                        item.error = Some(e.to_string());
+                    }
+                }
+            }
+        })
+    }
+
+    /// Scheduler loop: starts jobs whenever a slot is free and the queue is
+    /// not paused. Runs for the lifetime of the app.
+    pub async fn run(self: Arc<Self>, job: Job, on_change: OnChange) {
+        loop {
+            match self.take_next() {
+                Ok(Some(book_id)) => {
+                    on_change(self.snapshot());
+                    let queue = self.clone();
+                    let job = job.clone();
+                    let on_change = on_change.clone();
+                    tokio::spawn(async move {
+                        let result = job(book_id.clone()).await;
+                        if let Err(e) = queue.finish(&book_id, result) {
+                            tracing::warn!("Could not update download queue: {}", e);
+                        }
+                        on_change(queue.snapshot());
+                    });
+                }
+                Ok(None) => {
+                    on_change(self.snapshot());
+                    self.wake.notified().await;
+                }
+                Err(e) => {
+                    tracing::warn!("Could not update download queue: {}", e);
+                    self.wake.notified().await;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn book(id: &str) -> Book {
+        Book {
+            id: id.to_string(),
+            title: format!("Book {}", id),
+            access: true,
+            ..Book::default()
+        }
+    }
+
+    #[test]
+    fn test_queue_persists_and_requeues_running() {
+        let path =
+            std::env::temp_dir().join(format!("jreader-queue-{}.json", uuid::Uuid::new_v4()));
+        let queue = DownloadQueue::load_from(path.clone());
+        queue.enqueue(&[book("1"), book("2"), book("3")]).unwrap();
+        queue.enqueue(&[book("2")]).unwrap();
+        queue.reorder("3", 0).unwrap();
+        assert_eq!(queue.take_next().unwrap().as_deref(), Some("3"));
+
+        let reloaded = DownloadQueue::load_from(path.clone());
+        let state = reloaded.snapshot();
+        let ids: Vec<_> = state.items.iter().map(|i| i.book_id.as_str()).collect();
+        assert_eq!(ids, ["3", "1", "2"]);
+        assert!(state.items.iter().all(|i| i.status == QueueStatus::Queued));
+
+        let _ = fs::remove_file(path);
+    }
+}
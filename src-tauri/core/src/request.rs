@@ -0,0 +1,202 @@
+use crate::error::AppError;
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use reqwest::header::RETRY_AFTER;
+use reqwest::{RequestBuilder, Response, StatusCode};
+use std::time::Duration;
+
+/// The store and update endpoints the app talks to, each with its own
+/// timeout and retry budget.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Endpoint {
+    Token,
+    Login,
+    UserBooks,
+    DownloadInfo,
+    Download,
+    Logout,
+    VersionCheck,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    /// Whole-request timeout. `None` for transfers that may take long.
+    pub timeout: Option<Duration>,
+}
+
+impl Endpoint {
+    pub fn name(self) -> &'static str {
+        match self {
+            Endpoint::Token => "login/token",
+            Endpoint::Login => "login/login",
+            Endpoint::UserBooks => "books/get-user-books",
+            Endpoint::DownloadInfo => "books/file/download",
+            Endpoint::Download => "book package",
+            Endpoint::Logout => "logout",
+            Endpoint::VersionCheck => "github release",
+        }
+    }
+
+    pub fn policy(self) -> RetryPolicy {
+        let policy = RetryPolicy {
+            max_attempts: 4,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(10),
+            timeout: Some(Duration::from_secs(30)),
+        };
+        match self {
+            Endpoint::Token | Endpoint::Login => RetryPolicy {
+                timeout: Some(Duration::from_secs(20)),
+                ..policy
+            },
+            Endpoint::UserBooks => RetryPolicy {
+                timeout: Some(Duration::from_secs(60)),
+                ..policy
+            },
+            Endpoint::Download => RetryPolicy {
+                max_attempts: 5,
+                timeout: None,
+                ..policy
+            },
+            Endpoint::Logout => RetryPolicy {
+                max_attempts: 1,
+                timeout: Some(Duration::from_secs(10)),
+                ..policy
+            },
+            Endpoint::VersionCheck => RetryPolicy {
+                max_attempts: 2,
+                timeout: Some(Duration::from_secs(10)),
+                ..policy
+            },
+            Endpoint::DownloadInfo => policy,
+        }
+    }
+}
+
+/// Transient failures worth another attempt: connection problems, timeouts,
+/// server errors and rate limiting. Everything else (bad credentials,
+/// validation errors) is returned straight away.
+fn is_retryable_status(status: StatusCode) -> bool {
+    status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS
+}
+
+fn is_retryable_error(error: &reqwest::Error) -> bool {
+    error.is_connect() || error.is_timeout() || (error.is_request() && !error.is_builder())
+}
+
+/// Delay requested by a `Retry-After` header, in seconds or as an HTTP date.
+fn retry_after(response: &Response) -> Option<Duration> {
+    let value = response.headers().get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    (date.with_timezone(&Utc) - Utc::now()).to_std().ok()
+}
+
+/// Exponential backoff with jitter: half of the step is fixed, the other half
+/// random, so parallel downloads don't retry in lockstep.
+fn backoff(policy: &RetryPolicy, attempt: u32) -> Duration {
+    let step = policy
+        .base_delay
+        .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+        .min(policy.max_delay);
+    let half = step / 2;
+    let jitter = rand::thread_rng().gen_range(0..=half.as_millis() as u64);
+    half + Duration::from_millis(jitter)
+}
+
+/// Sends the request built by `build` with the endpoint's policy.
+///
+/// `build` is called again for each attempt so per-request headers such as
+/// `X-Request-Check` are fresh. The last response is returned even when its
+/// status is an error, so callers can still inspect it.
+pub async fn send(
+    endpoint: Endpoint,
+    build: impl Fn() -> RequestBuilder,
+) -> Result<Response, reqwest::Error> {
+    send_with_policy(endpoint.name(), &endpoint.policy(), build).await
+}
+
+pub async fn send_with_policy(
+    name: &str,
+    policy: &RetryPolicy,
+    build: impl Fn() -> RequestBuilder,
+) -> Result<Response, reqwest::Error> {
+    let mut attempt = 1;
+    loop {
+        let mut request = build();
+        if let Some(timeout) = policy.timeout {
+            request = request.timeout(timeout);
+        }
+        let last_attempt = attempt >= policy.max_attempts;
+
+        let delay = match request.send().await {
+            Ok(response) if is_retryable_status(response.status()) && !last_attempt => {
+                let delay = retry_after(&response)
+                    .map(|d| d.min(policy.max_delay))
+                    .unwrap_or_else(|| backoff(policy, attempt));
+                tracing::warn!(
+                    "{} attempt {}/{} got {}, retrying in {:?}",
+                    name,
+                    attempt,
+                    policy.max_attempts,
+                    response.status(),
+                    delay
+                );
+                delay
+            }
+            Ok(response) => {
+                tracing::info!(
+                    "{} attempt {}/{} got {}",
+                    name,
+                    attempt,
+                    policy.max_attempts,
+                    response.status()
+                );
+                return Ok(response);
+            }
+            Err(e) if is_retryable_error(&e) && !last_attempt => {
+                let delay = backoff(policy, attempt);
+                tracing::warn!(
+                    "{} attempt {}/{} failed: {}, retrying in {:?}",
+                    name,
+                    attempt,
+                    policy.max_attempts,
+                    e,
+                    delay
+                );
+                delay
+            }
+            Err(e) => {
+                tracing::warn!(
+                    "{} attempt {}/{} failed: {}",
+                    name,
+                    attempt,
+                    policy.max_attempts,
+                    e
+                );
+                return Err(e);
+            }
+        };
+
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+    }
+}
+
+/// Turns `401`/`403` into an auth error so the UI can send the user back to
+/// the login screen. Other statuses are left for the caller.
+pub fn reject_unauthorized(response: Response, code: &'static str) -> Result<Response, AppError> {
+    match response.status() {
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Err(AppError::auth(
+            code,
+            "Session is no longer valid, login again!",
+        )
+        .with_detail(response.status())),
+        _ => Ok(response),
+    }
+}
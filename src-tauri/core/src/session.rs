@@ -0,0 +1,119 @@
+use crate::api_calls::{auth, login, AuthResult};
+use crate::error::{AppError, ErrorKind};
+use crate::store::StoreBackend;
+use chrono::Utc;
+use reqwest::Client;
+use std::future::Future;
+use tokio::sync::Mutex;
+
+/// Tokens this close to their expiry are renewed before use, so a request
+/// does not race the store's clock.
+const EXPIRY_MARGIN_SECS: u64 = 60;
+
+/// Caches the access token for the running app. The lock is held while
+/// logging in, so parallel downloads that all find the token stale wait for
+/// one login instead of each doing their own.
+#[derive(Default)]
+pub struct SessionManager {
+    current: Mutex<Option<AuthResult>>,
+}
+
+fn is_fresh(session: &AuthResult) -> bool {
+    session.expires > Utc::now().timestamp() as u64 + EXPIRY_MARGIN_SECS
+}
+
+impl SessionManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The cached session, the saved one from settings, or a new login, in
+    /// that order.
+    pub async fn get(
+        &self,
+        client: &Client,
+        store: &dyn StoreBackend,
+    ) -> Result<AuthResult, AppError> {
+        let mut current = self.current.lock().await;
+        if let Some(session) = current.as_ref().filter(|s| is_fresh(s)) {
+            return Ok(session.clone());
+        }
+
+        let mut session = auth(client, store, None, None).await?;
+        if !is_fresh(&session) {
+            session = login(client, store, None, None).await?;
+        }
+        *current = Some(session.clone());
+        Ok(session)
+    }
+
+    /// Logs in again after the store rejected `stale_token`. If another task
+    /// already replaced that token, its session is returned instead.
+    pub async fn refresh(
+        &self,
+        client: &Client,
+        store: &dyn StoreBackend,
+        stale_token: &str,
+    ) -> Result<AuthResult, AppError> {
+        let mut current = self.current.lock().await;
+        if let Some(session) = current
+            .as_ref()
+            .filter(|s| s.auth != stale_token && is_fresh(s))
+        {
+            return Ok(session.clone());
+        }
+
+        tracing::info!("Session rejected by the store, logging in again");
+        *current = None;
+        let session = login(client, store, None, None).await?;
+        *current = Some(session.clone());
+        Ok(session)
+    }
+
+    /// Logs in with explicit credentials, e.g. from the login screen. Given
+    /// both, they always go to the store, whatever token is saved; otherwise
+    /// a saved token that is still valid is used.
+    pub async fn login(
+        &self,
+        client: &Client,
+        store: &dyn StoreBackend,
+        email: Option<&str>,
+        password: Option<&str>,
+    ) -> Result<AuthResult, AppError> {
+        let mut current = self.current.lock().await;
+        *current = None;
+        let session = match (email, password) {
+            (Some(_), Some(_)) => login(client, store, email, password).await?,
+            _ => auth(client, store, email, password).await?,
+        };
+        *current = Some(session.clone());
+        Ok(session)
+    }
+
+    pub async fn clear(&self) {
+        *self.current.lock().await = None;
+    }
+
+    /// Runs `call` with a valid session. When the store answers with an auth
+    /// error, the session is refreshed once and `call` retried.
+    pub async fn with_session<T, F, Fut>(
+        &self,
+        client: &Client,
+        store: &dyn StoreBackend,
+        call: F,
+    ) -> Result<T, AppError>
+    where
+        F: Fn(AuthResult) -> Fut,
+        Fut: Future<Output = Result<T, AppError>>,
+    {
+        let session = self.get(client, store).await?;
+        let token = session.auth.clone();
+        match call(session).await {
+            Err(error) if error.kind() == ErrorKind::Auth => {
+                let session = self.refresh(client, store, &token).await?;
+                call(session).await
+            }
+            result => result,
+        }
+    }
+}
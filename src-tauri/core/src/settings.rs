@@ -0,0 +1,251 @@
+use crate::error::AppError;
+use crate::network::NetworkSettings;
+use crate::output::OutputSettings;
+use crate::profiles::{active_profile, profile_dir};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+
+/// Bumped whenever the layout of `settings.json` changes; `migrate` brings
+/// older files up to date when they are read.
+pub const SETTINGS_VERSION: u64 = 1;
+
+/// Everything in `settings.json`. Keys this version does not know are kept
+/// in `other` so a downgrade does not lose them. Login secrets are not here
+/// but in the credential store, and the book list is in the catalog.
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct Settings {
+    pub version: u64,
+    /// Id of the store the user logged in to.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub app: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub initial_token: Option<String>,
+    /// Unix time the access token expires at.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub device_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub device_uid: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub network: Option<NetworkSettings>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<OutputSettings>,
+    #[serde(flatten)]
+    pub other: Map<String, Value>,
+}
+
+/// Serialises every read-modify-write of the settings file in this process.
+static LOCK: Mutex<()> = Mutex::new(());
+
+fn lock() -> MutexGuard<'static, ()> {
+    // A panic while holding the lock leaves the file intact (writes are
+    // atomic), so the poison flag carries no information.
+    LOCK.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn settings_path(profile: &str) -> PathBuf {
+    profile_dir(profile).join("settings.json")
+}
+
+impl Settings {
+    /// The settings of the active profile.
+    pub fn load() -> Result<Settings, AppError> {
+        Self::load_for(&active_profile())
+    }
+
+    pub fn load_for(profile: &str) -> Result<Settings, AppError> {
+        let _guard = lock();
+        read_from(&settings_path(profile))
+    }
+
+    /// Applies `change` to the current settings and saves them, with no other
+    /// update in between.
+    pub fn update<T>(change: impl FnOnce(&mut Settings) -> T) -> Result<T, AppError> {
+        Self::update_for(&active_profile(), change)
+    }
+
+    pub fn update_for<T>(
+        profile: &str,
+        change: impl FnOnce(&mut Settings) -> T,
+    ) -> Result<T, AppError> {
+        let _guard = lock();
+        update_at(&settings_path(profile), change)
+    }
+
+    /// One top-level value as the frontend sees it, `null` when unset.
+    pub fn value(&self, key: &str) -> Value {
+        serde_json::to_value(self)
+            .ok()
+            .and_then(|settings| settings.get(key).cloned())
+            .unwrap_or(Value::Null)
+    }
+}
+
+fn read_from(path: &Path) -> Result<Settings, AppError> {
+    if !path.exists() {
+        return Ok(Settings {
+            version: SETTINGS_VERSION,
+            ..Settings::default()
+        });
+    }
+
+    let data = fs::read_to_string(path).map_err(|e| {
+        AppError::io("1006", "Could not read the settings file").with_detail(format!(
+            "{}: {}",
+            path.display(),
+            e
+        ))
+    })?;
+    let value: Value = serde_json::from_str(&data).map_err(|e| {
+        AppError::integrity("1006", "The settings file is damaged").with_detail(format!(
+            "{}: {}",
+            path.display(),
+            e
+        ))
+    })?;
+    let value = migrate(value)?;
+    serde_json::from_value(value.clone()).map_err(|e| {
+        let key = value.as_object().and_then(invalid_key).unwrap_or("?");
+        AppError::integrity("1006", "The settings file has an invalid value")
+            .with_detail(format!("{}: {}", key, e))
+    })
+}
+
+/// The first top-level key whose value does not fit `Settings`, as serde
+/// errors do not say which one it was.
+fn invalid_key(object: &Map<String, Value>) -> Option<&str> {
+    object
+        .iter()
+        .find(|(key, value)| {
+            let single = Map::from_iter([((*key).clone(), (*value).clone())]);
+            serde_json::from_value::<Settings>(Value::Object(single)).is_err()
+        })
+        .map(|(key, _)| key.as_str())
+}
+
+fn update_at<T>(path: &Path, change: impl FnOnce(&mut Settings) -> T) -> Result<T, AppError> {
+    let mut settings = read_from(path)?;
+    let result = change(&mut settings);
+    settings.version = SETTINGS_VERSION;
+    write_to(path, &settings)?;
+    Ok(result)
+}
+
+/// Writes to a temporary file, flushes it to disk and renames it over the
+/// old one, so a crash leaves either the old or the new settings.
+fn write_to(path: &Path, settings: &Settings) -> Result<(), AppError> {
+    let write = || -> std::io::Result<()> {
+        let parent = path.parent().unwrap_or(Path::new("."));
+        fs::create_dir_all(parent)?;
+        let data = serde_json::to_vec(settings)?;
+        let temp = path.with_extension("json.tmp");
+        let mut file = File::create(&temp)?;
+        file.write_all(&data)?;
+        file.sync_all()?;
+        fs::rename(&temp, path)?;
+        // Persist the rename itself; not possible on every platform.
+        if let Ok(dir) = File::open(parent) {
+            let _ = dir.sync_all();
+        }
+        Ok(())
+    };
+    write().map_err(|e| {
+        AppError::io("1008", "Could not save the settings").with_detail(format!(
+            "{}: {}",
+            path.display(),
+            e
+        ))
+    })
+}
+
+/// Brings a settings file of any older version up to `SETTINGS_VERSION`.
+fn migrate(mut value: Value) -> Result<Value, AppError> {
+    let Some(object) = value.as_object_mut() else {
+        return Err(AppError::integrity("1006", "The settings file is damaged")
+            .with_detail("expected a JSON object"));
+    };
+    let version = object.get("version").and_then(Value::as_u64).unwrap_or(0);
+    if version > SETTINGS_VERSION {
+        return Err(AppError::integrity(
+            "1007",
+            "The settings were saved by a newer version of the app",
+        )
+        .with_detail(format!("settings version {}", version)));
+    }
+
+    if version < 1 {
+        // Unversioned files could hold `null`s and the token lifetime in
+        // seconds instead of a timestamp. Such a token counts as expired.
+        object.retain(|_, v| !v.is_null());
+        if object
+            .get("expires")
+            .and_then(Value::as_u64)
+            .is_some_and(|expires| expires < 1_000_000_000)
+        {
+            object.remove("expires");
+        }
+    }
+
+    object.insert("version".to_string(), SETTINGS_VERSION.into());
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_settings() -> PathBuf {
+        std::env::temp_dir()
+            .join(format!("jreader-settings-{}", uuid::Uuid::new_v4()))
+            .join("settings.json")
+    }
+
+    #[test]
+    fn test_migrates_unversioned_settings() {
+        let path = temp_settings();
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(
+            &path,
+            r#"{"app":"rufoof","expires":3600,"username":null,"theme":"dark"}"#,
+        )
+        .unwrap();
+
+        let settings = read_from(&path).unwrap();
+        assert_eq!(settings.version, SETTINGS_VERSION);
+        assert_eq!(settings.app.as_deref(), Some("rufoof"));
+        assert_eq!(settings.expires, None);
+        assert_eq!(settings.other.get("theme"), Some(&Value::from("dark")));
+
+        update_at(&path, |s| s.username = Some("reader".to_string())).unwrap();
+        let saved: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(saved["version"], SETTINGS_VERSION);
+        assert_eq!(saved["theme"], "dark");
+        assert!(!path.with_extension("json.tmp").exists());
+
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn test_invalid_settings_are_errors() {
+        let path = temp_settings();
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+
+        fs::write(&path, r#"{"expires":"tomorrow"}"#).unwrap();
+        let error = read_from(&path).unwrap_err();
+        assert_eq!(error.code(), "1006");
+        assert!(error.detail().unwrap().starts_with("expires: "));
+
+        fs::write(&path, r#"{"version":99}"#).unwrap();
+        assert_eq!(read_from(&path).unwrap_err().code(), "1007");
+
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+}
@@ -0,0 +1,269 @@
+use crate::book::Book;
+use crate::book_generator::book_generator;
+use crate::calibre::{recorded_store, write_sidecars};
+use crate::error::AppError;
+use crate::jobs::CancelToken;
+use crate::library::{cached_books, record_attempt, update_cached_book};
+use crate::output::{work_dir, OutputLayout, OutputSettings};
+use crate::profiles::profile_path;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use tracing::{info_span, Instrument};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+/// Bumped whenever what goes into a kept source changes. Sources written by
+/// another version are not used, the book has to be downloaded again.
+pub const SOURCES_VERSION: u32 = 1;
+
+const MANIFEST: &str = "manifest.json";
+const COVER: &str = "cover.jpg";
+/// Compressed already, so deflating them again only costs time.
+const STORED_EXTENSIONS: [&str; 6] = ["jpg", "jpeg", "png", "gif", "webp", "datx"];
+
+/// Describes a kept source, stored as `manifest.json` inside it.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct SourceManifest {
+    pub version: u32,
+    pub book_id: String,
+    /// The store file the source was extracted from.
+    pub file_id: String,
+    pub book_type: String,
+    /// The store the book came from; empty in sources kept before it was
+    /// recorded.
+    #[serde(default)]
+    pub store: String,
+    /// Whether the store cover is in the source, so an EPUB can be built
+    /// without fetching it.
+    pub has_cover: bool,
+    pub created_at: u64,
+}
+
+/// Decrypted book folders kept after conversion, one zip per book.
+pub fn sources_dir() -> PathBuf {
+    profile_path("sources")
+}
+
+pub fn source_path(book_id: &str) -> PathBuf {
+    sources_dir().join(format!("{}.zip", book_id))
+}
+
+/// The manifest of the source kept for `book_id`, whatever its version.
+pub fn manifest(book_id: &str) -> Option<SourceManifest> {
+    let mut archive = ZipArchive::new(File::open(source_path(book_id)).ok()?).ok()?;
+    let mut json = String::new();
+    archive
+        .by_name(MANIFEST)
+        .ok()?
+        .read_to_string(&mut json)
+        .ok()?;
+    serde_json::from_str(&json).ok()
+}
+
+/// Whether a usable source of `file_id` is kept for `book_id`.
+pub fn is_kept(book_id: &str, file_id: &str) -> bool {
+    manifest(book_id).is_some_and(|m| m.version == SOURCES_VERSION && m.file_id == file_id)
+}
+
+/// Zips the extraction folder of `book`, written through a temporary file so
+/// an older source stays usable until the new one is complete.
+pub fn keep(
+    book: &Book,
+    file_id: &str,
+    store: &str,
+    cover: Option<&[u8]>,
+) -> Result<PathBuf, AppError> {
+    let folder = work_dir().join(&book.id);
+    let target = source_path(&book.id);
+    let write = || -> Result<(), Box<dyn std::error::Error>> {
+        fs::create_dir_all(sources_dir())?;
+        let temp = target.with_extension("zip.tmp");
+        let mut zip = ZipWriter::new(File::create(&temp)?);
+        let manifest = SourceManifest {
+            version: SOURCES_VERSION,
+            book_id: book.id.clone(),
+            file_id: file_id.to_string(),
+            book_type: book.book_type.clone(),
+            store: store.to_string(),
+            has_cover: cover.is_some(),
+            created_at: Utc::now().timestamp() as u64,
+        };
+        zip.start_file(MANIFEST, SimpleFileOptions::default())?;
+        zip.write_all(&serde_json::to_vec_pretty(&manifest)?)?;
+        if let Some(cover) = cover {
+            zip.start_file(COVER, stored())?;
+            zip.write_all(cover)?;
+        }
+        add_folder(&mut zip, &folder, &folder)?;
+        zip.finish()?;
+        fs::rename(&temp, &target)?;
+        Ok(())
+    };
+    write().map_err(|e| {
+        AppError::io("1023", "Could not keep the book source").with_detail(format!(
+            "{}: {}",
+            target.display(),
+            e
+        ))
+    })?;
+    Ok(target)
+}
+
+fn stored() -> SimpleFileOptions {
+    SimpleFileOptions::default().compression_method(CompressionMethod::Stored)
+}
+
+fn add_folder(zip: &mut ZipWriter<File>, root: &Path, dir: &Path) -> io::Result<()> {
+    let mut entries: Vec<PathBuf> = fs::read_dir(dir)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<io::Result<_>>()?;
+    entries.sort();
+    for path in entries {
+        if path.is_dir() {
+            add_folder(zip, root, &path)?;
+            continue;
+        }
+        let Ok(name) = path.strip_prefix(root) else {
+            continue;
+        };
+        let name = name.to_string_lossy().replace('\\', "/");
+        let compressed = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| STORED_EXTENSIONS.contains(&e.to_lowercase().as_str()));
+        let options = if compressed {
+            stored()
+        } else {
+            SimpleFileOptions::default()
+        };
+        let options = options.large_file(fs::metadata(&path)?.len() >= u32::MAX as u64);
+        zip.start_file(name, options)?;
+        io::copy(&mut File::open(&path)?, zip)?;
+    }
+    Ok(())
+}
+
+/// Unpacks the kept source of `book_id` into its extraction folder, in
+/// place of whatever is there.
+pub fn restore(book_id: &str) -> Result<SourceManifest, AppError> {
+    let manifest = manifest(book_id)
+        .filter(|m| m.version == SOURCES_VERSION)
+        .ok_or_else(|| {
+            AppError::integrity(
+                "1024",
+                "No usable source is kept for this book, download it again",
+            )
+            .with_detail(source_path(book_id).display().to_string())
+        })?;
+
+    let folder = work_dir().join(book_id);
+    let unpack = || -> Result<(), Box<dyn std::error::Error>> {
+        if folder.exists() {
+            fs::remove_dir_all(&folder)?;
+        }
+        let mut archive = ZipArchive::new(File::open(source_path(book_id))?)?;
+        for i in 0..archive.len() {
+            let mut file = archive.by_index(i)?;
+            if file.is_dir() || file.name() == MANIFEST {
+                continue;
+            }
+            let Some(name) = file.enclosed_name() else {
+                continue;
+            };
+            let path = folder.join(name);
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            io::copy(&mut file, &mut File::create(&path)?)?;
+        }
+        Ok(())
+    };
+    unpack().map_err(|e| {
+        AppError::integrity("1024", "The kept source of this book is damaged").with_detail(e)
+    })?;
+    Ok(manifest)
+}
+
+pub fn remove(book_id: &str) -> io::Result<()> {
+    let path = source_path(book_id);
+    if path.exists() {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+/// Builds the book again from its kept source, without the store. The
+/// output replaces the converted book and the attempt goes into its
+/// conversion history.
+pub async fn regenerate_book(book_id: &str, cancel: &CancelToken) -> Result<String, AppError> {
+    let started_at = Utc::now().timestamp() as u64;
+    let span = info_span!("regenerate", book_id);
+    let (file_id, result) = regenerate(book_id, cancel).instrument(span.clone()).await;
+
+    let status = record_attempt(book_id, file_id, started_at, &result);
+    span.in_scope(|| match &result {
+        Ok(path) => tracing::info!("Regenerated to {}", path),
+        Err(e) => tracing::warn!("Regeneration {}: {}", status, e),
+    });
+    if let Err(e) = fs::remove_dir_all(work_dir().join(book_id)) {
+        if e.kind() != io::ErrorKind::NotFound {
+            tracing::warn!("Could not remove the restored source: {}", e);
+        }
+    }
+    result
+}
+
+async fn regenerate(
+    book_id: &str,
+    cancel: &CancelToken,
+) -> (Option<String>, Result<String, AppError>) {
+    let mut file_id = None;
+    let result = async {
+        let book = cached_books()?
+            .into_iter()
+            .find(|b| b.id == book_id)
+            .ok_or_else(|| {
+                AppError::integrity("701", format!("Book with id {} not found.", book_id))
+            })?;
+        cancel.check()?;
+
+        let owned_id = book_id.to_string();
+        let manifest = tokio::task::spawn_blocking(move || restore(&owned_id))
+            .await
+            .map_err(|e| {
+                AppError::conversion("701-1", "Book extraction task failed").with_detail(e)
+            })??;
+        file_id = Some(manifest.file_id.clone());
+        cancel.check()?;
+
+        let cover = if manifest.has_cover {
+            fs::read(work_dir().join(book_id).join(COVER)).ok()
+        } else {
+            tracing::warn!("No cover is kept for this book, building without it");
+            None
+        };
+        let generated = book_generator(book.clone(), cover.as_deref(), cancel)
+            .await
+            .map_err(|e| AppError::from(e).with_code("701-3"))?;
+        if OutputSettings::load().layout == OutputLayout::Calibre {
+            let store = match manifest.store.as_str() {
+                "" => recorded_store(&generated),
+                store => store.to_string(),
+            };
+            if let Err(e) = write_sidecars(&book, &generated, &store, cover.as_deref()) {
+                tracing::warn!("Could not write the Calibre metadata: {}", e);
+            }
+        }
+        let path = generated.display().to_string();
+        update_cached_book(book_id, |cached| {
+            cached.book_path = Some(path.clone());
+            cached.downloaded_file_id = Some(manifest.file_id);
+        })?;
+        Ok(path)
+    }
+    .await;
+    (file_id, result)
+}
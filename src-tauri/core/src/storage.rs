@@ -0,0 +1,316 @@
+use crate::book::Book;
+use crate::cross_platform::get_app_data_path;
+use crate::error::AppError;
+use crate::helpers::clear_residue;
+use crate::journal::{journals, Stage};
+use crate::library::cached_books;
+use crate::output::{output_dir, work_dir};
+use crate::profiles::profile_path;
+use crate::sources::sources_dir;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Room a book needs while it is converted, in bytes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpaceNeeded {
+    /// Package, combined zip and extraction folder side by side.
+    pub work: u64,
+    pub output: u64,
+    /// The output is moved out of the extraction folder rather than built
+    /// anew, so it takes no more room when both are on one drive. Across
+    /// drives it is copied and the output drive needs room for all of it.
+    pub moved: bool,
+}
+
+/// A rough estimate from the package size, which decryption needs about
+/// three times over. EPUBs are then built anew; audio and PDF files are
+/// moved out of the extraction folder.
+pub fn space_needed(book: &Book) -> SpaceNeeded {
+    SpaceNeeded {
+        work: book.size * 3,
+        output: book.size,
+        moved: book.book_type != "epub",
+    }
+}
+
+/// Free bytes on the drive holding `path`, which need not exist yet.
+pub fn free_space(path: &Path) -> io::Result<u64> {
+    let existing = path
+        .ancestors()
+        .find(|p| p.exists())
+        .unwrap_or(Path::new("."));
+    fs4::available_space(existing)
+}
+
+/// Checks there is room to convert `book` before anything is downloaded.
+/// Working files already on disk from an earlier attempt count as used.
+pub fn preflight(book: &Book) -> Result<(), AppError> {
+    let needed = space_needed(book);
+    let work_needed = needed.work.saturating_sub(working_size(&book.id));
+    let mut checks = vec![(work_dir(), work_needed)];
+    let output = output_dir();
+    match (free_space(&work_dir()), free_space(&output)) {
+        // Most likely the same drive; it has to hold both.
+        (Ok(work), Ok(out)) if work == out => {
+            if !needed.moved {
+                checks[0].1 += needed.output;
+            }
+        }
+        _ => checks.push((output, needed.output)),
+    }
+
+    for (path, needed) in checks {
+        let free = match free_space(&path) {
+            Ok(free) => free,
+            Err(e) => {
+                tracing::warn!("Could not check free space at {}: {}", path.display(), e);
+                continue;
+            }
+        };
+        if free < needed {
+            return Err(AppError::io(
+                "1020",
+                format!(
+                    "Not enough free space: {} needs {} MB, {} MB are free",
+                    book.title,
+                    megabytes(needed),
+                    megabytes(free)
+                ),
+            )
+            .with_detail(path.display().to_string()));
+        }
+    }
+    Ok(())
+}
+
+fn megabytes(bytes: u64) -> u64 {
+    bytes.div_ceil(1024 * 1024)
+}
+
+/// Bytes used by a file or everything in a folder.
+pub fn disk_usage(path: &Path) -> u64 {
+    let Ok(metadata) = fs::symlink_metadata(path) else {
+        return 0;
+    };
+    if !metadata.is_dir() {
+        return metadata.len();
+    }
+    fs::read_dir(path)
+        .map(|entries| entries.flatten().map(|e| disk_usage(&e.path())).sum())
+        .unwrap_or(0)
+}
+
+/// The book a working file belongs to: `1234.zip.body` and `1234/` are
+/// both `1234`.
+fn working_book_id(path: &Path) -> Option<String> {
+    let name = path.file_name()?.to_str()?;
+    Some(name.split('.').next()?.to_string())
+}
+
+fn working_entries() -> Vec<PathBuf> {
+    fs::read_dir(work_dir())
+        .map(|entries| entries.flatten().map(|e| e.path()).collect())
+        .unwrap_or_default()
+}
+
+fn working_size(book_id: &str) -> u64 {
+    working_entries()
+        .iter()
+        .filter(|path| working_book_id(path).as_deref() == Some(book_id))
+        .map(|path| disk_usage(path))
+        .sum()
+}
+
+#[derive(Debug, Serialize, Default, Clone, PartialEq)]
+pub struct BookStorage {
+    pub book_id: String,
+    pub title: String,
+    /// Downloaded packages, whole or partial.
+    pub packages: u64,
+    /// Extraction folders.
+    pub extracted: u64,
+    /// The source kept for regenerating the book.
+    pub sources: u64,
+    /// The converted book.
+    pub output: u64,
+}
+
+impl BookStorage {
+    fn total(&self) -> u64 {
+        self.packages + self.extracted + self.sources + self.output
+    }
+}
+
+/// Where the space of the current profile goes, in bytes.
+#[derive(Debug, Serialize, Default, Clone)]
+pub struct StorageReport {
+    /// Totals for `packages`, `extracted`, `sources`, `output`, `catalog`
+    /// and `temp`.
+    pub categories: BTreeMap<String, u64>,
+    /// Books with anything on disk, biggest first.
+    pub books: Vec<BookStorage>,
+    pub free_work: Option<u64>,
+    pub free_output: Option<u64>,
+}
+
+pub fn storage_report() -> Result<StorageReport, AppError> {
+    let mut books: BTreeMap<String, BookStorage> = BTreeMap::new();
+    for book in cached_books()? {
+        let output = book
+            .book_path
+            .as_deref()
+            .map(|p| disk_usage(Path::new(p)))
+            .unwrap_or(0);
+        books.insert(
+            book.id.clone(),
+            BookStorage {
+                book_id: book.id,
+                title: book.title,
+                output,
+                ..BookStorage::default()
+            },
+        );
+    }
+    for path in working_entries() {
+        let Some(book_id) = working_book_id(&path) else {
+            continue;
+        };
+        let entry = books.entry(book_id.clone()).or_insert_with(|| BookStorage {
+            book_id,
+            ..BookStorage::default()
+        });
+        if path.is_dir() {
+            entry.extracted += disk_usage(&path);
+        } else if !path.to_string_lossy().ends_with(".journal.json") {
+            entry.packages += disk_usage(&path);
+        }
+    }
+    let kept = fs::read_dir(sources_dir())
+        .map(|entries| entries.flatten().map(|e| e.path()).collect::<Vec<_>>())
+        .unwrap_or_default();
+    for path in kept {
+        let Some(book_id) = working_book_id(&path) else {
+            continue;
+        };
+        books
+            .entry(book_id.clone())
+            .or_insert_with(|| BookStorage {
+                book_id,
+                ..BookStorage::default()
+            })
+            .sources += disk_usage(&path);
+    }
+
+    let mut books: Vec<BookStorage> = books.into_values().filter(|b| b.total() > 0).collect();
+    books.sort_by_key(|b| std::cmp::Reverse(b.total()));
+
+    let categories = BTreeMap::from([
+        (
+            "packages".to_string(),
+            books.iter().map(|b| b.packages).sum(),
+        ),
+        (
+            "extracted".to_string(),
+            books.iter().map(|b| b.extracted).sum(),
+        ),
+        ("sources".to_string(), books.iter().map(|b| b.sources).sum()),
+        ("output".to_string(), disk_usage(&output_dir())),
+        (
+            "catalog".to_string(),
+            disk_usage(&profile_path("library.db")),
+        ),
+        ("temp".to_string(), disk_usage(&temp_dir())),
+    ]);
+    Ok(StorageReport {
+        categories,
+        books,
+        free_work: free_space(&work_dir()).ok(),
+        free_output: free_space(&output_dir()).ok(),
+    })
+}
+
+fn temp_dir() -> PathBuf {
+    get_app_data_path(Some("temp"))
+}
+
+/// What `cleanup` removed.
+#[derive(Debug, Serialize, Default, Clone, PartialEq)]
+pub struct CleanupReport {
+    pub removed: Vec<String>,
+    pub freed: u64,
+}
+
+/// Removes working files no job can use any more: anything without a
+/// journal, `.zip.body` files already combined into a zip, and the temp
+/// folder. Jobs with a journal keep the rest so they can still resume.
+/// Must not run while a job is in progress.
+pub fn cleanup() -> Result<CleanupReport, AppError> {
+    let mut report = CleanupReport::default();
+    let journals = journals();
+    let journaled: HashSet<&str> = journals.iter().map(|j| j.book_id.as_str()).collect();
+
+    let mut orphans = HashSet::new();
+    for path in working_entries() {
+        match working_book_id(&path) {
+            Some(book_id) if journaled.contains(book_id.as_str()) => {}
+            Some(book_id) => {
+                orphans.insert(book_id);
+            }
+            None => {}
+        }
+    }
+    for book_id in orphans {
+        report.freed += working_size(&book_id);
+        clear_residue(&book_id)?;
+        report.removed.push(book_id);
+    }
+
+    for journal in &journals {
+        let combined = journal.stages.iter().any(|s| s.stage > Stage::Downloaded);
+        let body = work_dir().join(format!("{}.zip.body", journal.book_id));
+        if combined && body.exists() {
+            report.freed += disk_usage(&body);
+            fs::remove_file(&body)?;
+            report.removed.push(body.display().to_string());
+        }
+    }
+
+    let temp = temp_dir();
+    if temp.exists() {
+        report.freed += disk_usage(&temp);
+        fs::remove_dir_all(&temp)?;
+        report.removed.push(temp.display().to_string());
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_space_needed_depends_on_the_book_type() {
+        let book = |book_type: &str| Book {
+            book_type: book_type.to_string(),
+            size: 100,
+            ..Book::default()
+        };
+        assert_eq!(
+            space_needed(&book("epub")),
+            SpaceNeeded {
+                work: 300,
+                output: 100,
+                moved: false
+            }
+        );
+        assert!(space_needed(&book("mp3")).moved);
+        assert_eq!(
+            working_book_id(Path::new("/work/1234.zip.body")).as_deref(),
+            Some("1234")
+        );
+        assert!(free_space(&std::env::temp_dir().join("missing/dir")).is_ok());
+    }
+}
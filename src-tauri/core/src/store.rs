@@ -0,0 +1,205 @@
+use crate::book::Book;
+use crate::request::Endpoint;
+use crate::settings::Settings;
+use serde_json::Value;
+use std::sync::Arc;
+
+/// Everything that differs between the stores the app can log in to.
+/// API calls and commands only go through this trait, never through
+/// `app_type` string matches.
+pub trait StoreBackend: Send + Sync {
+    /// Stored as `app` in the settings and sent by the login screen.
+    fn id(&self) -> &'static str;
+
+    /// API origin without a trailing slash, e.g. `https://api.jarirreader.com`.
+    fn base_url(&self) -> String;
+
+    fn website(&self) -> &'static str;
+
+    fn tos_url(&self) -> &'static str;
+
+    /// The store API does not name publishers, so every book gets this one.
+    fn publisher(&self) -> &'static str;
+
+    fn book_page(&self, book_id: &str) -> String {
+        format!("{}/book/{}", self.website(), book_id)
+    }
+
+    /// Path of a store endpoint. `None` for requests that do not go to the
+    /// store API, such as the package download itself.
+    fn path(&self, endpoint: Endpoint) -> Option<&'static str> {
+        match endpoint {
+            Endpoint::Token => Some("/v7/login/token"),
+            Endpoint::Login => Some("/v7/login/login"),
+            Endpoint::UserBooks => Some("/v7/books/get-user-books"),
+            Endpoint::DownloadInfo => Some("/v7/books/file/download"),
+            Endpoint::Logout => Some("/v7/logout"),
+            Endpoint::Download | Endpoint::VersionCheck => None,
+        }
+    }
+
+    fn url(&self, endpoint: Endpoint) -> String {
+        format!(
+            "{}{}",
+            self.base_url(),
+            self.path(endpoint).unwrap_or_default()
+        )
+    }
+
+    /// Maps one entry of `get-user-books` into a `Book`.
+    fn parse_book(&self, item: &Value) -> Book {
+        let str_field = |name: &str| {
+            item.get(name)
+                .and_then(|v| v.as_str())
+                .unwrap_or_default()
+                .to_string()
+        };
+        let id_field = |name: &str| {
+            item.get(name)
+                .and_then(|v| v.as_u64())
+                .unwrap_or_default()
+                .to_string()
+        };
+        let thumb = item
+            .get("cover_thumb_url")
+            .and_then(|v| v.as_str())
+            .map(|s| s.to_string());
+
+        Book {
+            id: id_field("book_id"),
+            title: str_field("title"),
+            name: str_field("title"),
+            url: str_field("book_file_url"),
+            publisher: self.publisher().to_string(),
+            authors: item
+                .get("authors_name")
+                .and_then(|v| v.as_array())
+                .map(|authors| {
+                    authors
+                        .iter()
+                        .map(|a| a.as_str().unwrap_or_default().to_string())
+                        .collect()
+                })
+                .unwrap_or_default(),
+            cover: thumb.clone(),
+            book_type: str_field("file_type"),
+            thumb,
+            access: item
+                .get("book_access")
+                .and_then(|v| v.as_u64())
+                .unwrap_or_default()
+                == 1,
+            file_md5: str_field("book_file_md5"),
+            file_id: id_field("bookfile_id"),
+            latest_file_id: id_field("latest_file_id"),
+            size: item
+                .get("size")
+                .and_then(|v| v.as_u64())
+                .unwrap_or_default(),
+            book_path: None,
+            key: vec![],
+            header: "".to_string(),
+            ..Book::default()
+        }
+    }
+}
+
+/// `JREADER_API_URL` points every store at another server, e.g. a local
+/// mock store.
+fn api_origin(default: &str) -> String {
+    match std::env::var("JREADER_API_URL") {
+        Ok(url) => url.trim_end_matches('/').to_string(),
+        Err(_) => default.to_string(),
+    }
+}
+
+pub struct Jarir;
+
+impl StoreBackend for Jarir {
+    fn id(&self) -> &'static str {
+        "jarir"
+    }
+
+    fn base_url(&self) -> String {
+        api_origin("https://api.jarirreader.com")
+    }
+
+    fn website(&self) -> &'static str {
+        "https://jarirreader.com"
+    }
+
+    fn tos_url(&self) -> &'static str {
+        "https://jarirreader.com/site/tos"
+    }
+
+    fn publisher(&self) -> &'static str {
+        "جرير للنشر"
+    }
+
+    fn book_page(&self, book_id: &str) -> String {
+        format!("{}/book/{}/github-abdumu", self.website(), book_id)
+    }
+}
+
+pub struct Rufoof;
+
+impl StoreBackend for Rufoof {
+    fn id(&self) -> &'static str {
+        "rufoof"
+    }
+
+    fn base_url(&self) -> String {
+        api_origin("https://api.rufoof.com")
+    }
+
+    fn website(&self) -> &'static str {
+        "https://rufoof.com"
+    }
+
+    fn tos_url(&self) -> &'static str {
+        "https://rufoof.com/privacy"
+    }
+
+    fn publisher(&self) -> &'static str {
+        "رفوف"
+    }
+}
+
+/// The store for an `app` value, Jarir for anything unknown.
+pub fn store_for(app_type: &str) -> Arc<dyn StoreBackend> {
+    match app_type {
+        "rufoof" => Arc::new(Rufoof),
+        _ => Arc::new(Jarir),
+    }
+}
+
+/// The store the user is logged in to.
+pub fn current_store() -> Arc<dyn StoreBackend> {
+    let app_type = Settings::load()
+        .ok()
+        .and_then(|settings| settings.app)
+        .unwrap_or_default();
+    store_for(&app_type)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_store_for_app_type() {
+        assert_eq!(store_for("rufoof").id(), "rufoof");
+        assert_eq!(store_for("").id(), "jarir");
+
+        let book = Rufoof.parse_book(&serde_json::json!({
+            "book_id": 42,
+            "title": "Title",
+            "bookfile_id": 7,
+            "book_access": 1,
+        }));
+        assert_eq!(book.id, "42");
+        assert_eq!(book.file_id, "7");
+        assert_eq!(book.publisher, "رفوف");
+        assert!(book.access);
+    }
+}
@@ -0,0 +1,76 @@
+#![cfg(feature = "cli")]
+
+mod common;
+
+use common::store::{MockStore, BOOK_ID, BOOK_TITLE, EMAIL, PASSWORD, USERNAME};
+use serde_json::Value;
+use std::path::Path;
+use tokio::process::Command;
+
+/// Runs the CLI with `--json` against the mock store, returning whether it
+/// succeeded and what it printed.
+async fn cli(store: &MockStore, data_dir: &Path, args: &[&str]) -> (bool, Value) {
+    let output = Command::new(env!("CARGO_BIN_EXE_jarir-reader-cli"))
+        .arg("--json")
+        .args(args)
+        .env("JREADER_DATA_DIR", data_dir)
+        .env("JREADER_API_URL", store.base_url())
+        .env_remove("JREADER_PASSWORD")
+        .output()
+        .await
+        .unwrap();
+    let printed = serde_json::from_slice(&output.stdout).unwrap_or_else(|e| {
+        panic!(
+            "{:?} printed no JSON ({}): {}",
+            args,
+            e,
+            String::from_utf8_lossy(&output.stderr)
+        )
+    });
+    (output.status.success(), printed)
+}
+
+#[tokio::test]
+async fn cli_logs_in_lists_and_downloads() {
+    let store = MockStore::start().await;
+    let data_dir = std::env::temp_dir().join(format!("jreader-cli-{}", uuid::Uuid::new_v4()));
+
+    let (ok, login) = cli(
+        &store,
+        &data_dir,
+        &["login", "--email", EMAIL, "--password", PASSWORD],
+    )
+    .await;
+    assert!(ok);
+    assert_eq!(login["username"], USERNAME);
+    assert_eq!(login["books"], 1);
+
+    let (ok, list) = cli(&store, &data_dir, &["list", "--status", "not_downloaded"]).await;
+    assert!(ok);
+    assert_eq!(list["total"], 1);
+    assert_eq!(list["books"][0]["id"], BOOK_ID);
+    assert!(list["books"][0].get("key").is_none());
+
+    // Nothing was downloaded yet, so there is nothing to convert.
+    let (ok, convert) = cli(&store, &data_dir, &["convert", BOOK_ID]).await;
+    assert!(!ok);
+    assert_eq!(convert["error"]["code"], "1022");
+
+    let (ok, download) = cli(&store, &data_dir, &["download", "--search", BOOK_TITLE]).await;
+    assert!(ok);
+    let path = download[0]["path"].as_str().unwrap();
+    assert!(Path::new(path).exists());
+
+    let (_, status) = cli(&store, &data_dir, &["status"]).await;
+    assert_eq!(status["logged_in"], true);
+    assert_eq!(status["downloaded"], 1);
+    assert_eq!(status["unfinished"], Value::Array(vec![]));
+
+    let (ok, _) = cli(&store, &data_dir, &["logout"]).await;
+    assert!(ok);
+    let (_, status) = cli(&store, &data_dir, &["status"]).await;
+    assert_eq!(status["logged_in"], false);
+    assert!(Path::new(path).exists());
+
+    let _ = std::fs::remove_dir_all(data_dir);
+}
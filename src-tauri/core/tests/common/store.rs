@@ -0,0 +1,276 @@
+//! A fake Jarir/Rufoof store serving one synthetic, properly encrypted book.
+
+use super::{MockRequest, MockResponse, MockServer};
+use base64::{engine::general_purpose, Engine as _};
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use jarir_reader_core::store::StoreBackend;
+use openssl::sha::Sha1;
+use openssl::symm::{encrypt, Cipher};
+use serde_json::json;
+use std::collections::HashMap;
+use std::io::{Cursor, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+pub const EMAIL: &str = "reader@example.com";
+pub const PASSWORD: &str = "secret";
+pub const USERNAME: &str = "Mock Reader";
+pub const ACCESS_TOKEN: &str = "mock-access-token";
+pub const BOOK_ID: &str = "1234";
+pub const FILE_ID: &str = "5678";
+pub const BOOK_TITLE: &str = "كتاب تجريبي";
+pub const CHAPTER_TEXT: &str = "هذا نص الفصل الأول من الكتاب التجريبي.";
+
+const BOOK_KEY: [u8; 16] = [
+    115, 220, 110, 163, 78, 234, 63, 185, 97, 130, 86, 66, 220, 46, 13, 160,
+];
+
+/// The two halves a real store hands out: a `header` in the download info,
+/// AES-encrypted for whoever asks, and the `.body` file on the CDN.
+pub struct SyntheticBook {
+    pub head: Vec<u8>,
+    pub body: Vec<u8>,
+}
+
+impl SyntheticBook {
+    pub fn header_for(&self, access_token: &str) -> String {
+        let encrypted = encrypt(
+            Cipher::aes_256_cbc(),
+            &header_key(access_token),
+            Some(b"1234567812345678"),
+            &self.head,
+        )
+        .unwrap();
+        general_purpose::STANDARD.encode(encrypted)
+    }
+}
+
+pub struct MockStore {
+    pub server: MockServer,
+    pub book: Arc<SyntheticBook>,
+    generation: Arc<AtomicUsize>,
+}
+
+/// The token the store currently accepts. The first one is `ACCESS_TOKEN`.
+fn token_for(generation: usize) -> String {
+    match generation {
+        0 => ACCESS_TOKEN.to_string(),
+        n => format!("{}-{}", ACCESS_TOKEN, n),
+    }
+}
+
+impl MockStore {
+    pub async fn start() -> Self {
+        let book = Arc::new(synthetic_book());
+        let base_url = Arc::new(OnceLock::<String>::new());
+        let generation = Arc::new(AtomicUsize::new(0));
+
+        let handler_book = book.clone();
+        let handler_base = base_url.clone();
+        let handler_generation = generation.clone();
+        let server = MockServer::start(move |request| {
+            let token = token_for(handler_generation.load(Ordering::SeqCst));
+            route(request, &handler_book, handler_base.get().unwrap(), &token)
+        })
+        .await;
+        base_url.set(server.url("")).unwrap();
+
+        MockStore {
+            server,
+            book,
+            generation,
+        }
+    }
+
+    /// Invalidates every issued token, as when the session is ended
+    /// server-side. The next login gets a new one.
+    pub fn revoke_tokens(&self) {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Origin of the mock server, also usable as `JREADER_API_URL`.
+    pub fn base_url(&self) -> String {
+        self.server.url("")
+    }
+
+    pub fn backend(&self) -> MockBackend {
+        MockBackend {
+            base_url: self.base_url(),
+        }
+    }
+
+    pub fn requests_to(&self, path: &str) -> usize {
+        self.server
+            .requests()
+            .iter()
+            .filter(|r| r.path == path)
+            .count()
+    }
+}
+
+/// Store backend talking to the mock server.
+pub struct MockBackend {
+    base_url: String,
+}
+
+impl StoreBackend for MockBackend {
+    fn id(&self) -> &'static str {
+        "mock"
+    }
+
+    fn base_url(&self) -> String {
+        self.base_url.clone()
+    }
+
+    fn website(&self) -> &'static str {
+        "https://store.example.com"
+    }
+
+    fn tos_url(&self) -> &'static str {
+        "https://store.example.com/tos"
+    }
+
+    fn publisher(&self) -> &'static str {
+        "Mock Publisher"
+    }
+}
+
+fn form(request: &MockRequest) -> HashMap<String, String> {
+    let query = format!("http://form/?{}", String::from_utf8_lossy(&request.body));
+    reqwest::Url::parse(&query)
+        .map(|url| url.query_pairs().into_owned().collect())
+        .unwrap_or_default()
+}
+
+fn unauthorized() -> MockResponse {
+    MockResponse::new(401, json!({ "error": "invalid_token" }).to_string())
+}
+
+fn route(request: &MockRequest, book: &SyntheticBook, base_url: &str, token: &str) -> MockResponse {
+    let form = form(request);
+    let has_token = form.get("access_token").map(String::as_str) == Some(token);
+    let expires = chrono::Utc::now().timestamp() + 3600;
+
+    match request.path.as_str() {
+        "/v7/login/token" => MockResponse::json(json!({
+            "access_token": "mock-initial-token",
+            "expires_in": expires,
+        })),
+        "/v7/login/login" => {
+            if form.get("email").map(String::as_str) != Some(EMAIL)
+                || form.get("password").map(String::as_str) != Some(PASSWORD)
+            {
+                return MockResponse::json(json!({ "error": "wrong credentials" }));
+            }
+            MockResponse::json(json!({
+                "result": {
+                    "user": { "fullName": USERNAME },
+                    "access_token": token,
+                }
+            }))
+        }
+        "/v7/books/get-user-books" if has_token => MockResponse::json(json!({
+            "result": [{
+                "book_id": BOOK_ID.parse::<u64>().unwrap(),
+                "title": BOOK_TITLE,
+                "book_file_url": "",
+                "authors_name": ["مؤلف تجريبي"],
+                "file_type": "epub",
+                "book_access": 1,
+                "book_file_md5": "",
+                "bookfile_id": FILE_ID.parse::<u64>().unwrap(),
+                "latest_file_id": FILE_ID.parse::<u64>().unwrap(),
+                "size": book.body.len(),
+            }]
+        })),
+        "/v7/books/file/download" if has_token => {
+            if form.get("file_id").map(String::as_str) != Some(FILE_ID) {
+                return MockResponse::new(404, "");
+            }
+            MockResponse::json(json!({
+                "result": {
+                    "body": format!("{}/files/{}.body", base_url, BOOK_ID),
+                    "header": book.header_for(token),
+                }
+            }))
+        }
+        "/v7/books/get-user-books" | "/v7/books/file/download" => unauthorized(),
+        "/v7/logout" => MockResponse::json(json!({ "result": true })),
+        path if path == format!("/files/{}.body", BOOK_ID) => {
+            MockResponse::new(200, book.body.clone())
+        }
+        _ => MockResponse::new(404, ""),
+    }
+}
+
+fn zip_bytes(entries: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+    for (name, data) in entries {
+        writer.start_file(*name, options).unwrap();
+        writer.write_all(data).unwrap();
+    }
+    writer.finish().unwrap().into_inner()
+}
+
+fn rc4(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut s: Vec<u8> = (0..=255).collect();
+    let mut j = 0usize;
+    for i in 0..256 {
+        j = (j + s[i] as usize + key[i % key.len()] as usize) % 256;
+        s.swap(i, j);
+    }
+    let (mut i, mut j) = (0usize, 0usize);
+    data.iter()
+        .map(|byte| {
+            i = (i + 1) % 256;
+            j = (j + s[i] as usize) % 256;
+            s.swap(i, j);
+            byte ^ s[(s[i] as usize + s[j] as usize) % 256]
+        })
+        .collect()
+}
+
+/// Text files in a package are zlib-compressed, then RC4-encrypted.
+fn encrypt_text(text: &str) -> Vec<u8> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(text.as_bytes()).unwrap();
+    rc4(&BOOK_KEY, &encoder.finish().unwrap())
+}
+
+/// The AES key the app derives from the user's access token.
+fn header_key(access_token: &str) -> Vec<u8> {
+    let mut hasher = Sha1::new();
+    hasher.update(format!("{}platform", access_token).as_bytes());
+    let hex: String = hasher
+        .finish()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    hex.as_bytes()[..32].to_vec()
+}
+
+pub fn synthetic_book() -> SyntheticBook {
+    let info = json!({ "type": "epub", "chapters": 1, "language": "ar" }).to_string();
+    let toc = json!([{ "offset": 0, "title": "الفصل الأول" }]).to_string();
+    let chapter = encrypt_text(CHAPTER_TEXT);
+    let package = zip_bytes(&[
+        ("Index/info.json", info.as_bytes()),
+        ("Index/toc.json", toc.as_bytes()),
+        ("Text/chapter-001.html", &chapter),
+        ("Text/chapter-001.html.spans", b"[]"),
+    ]);
+
+    // The store splits a zip of {header: RC4 key, body: package} in two and
+    // encrypts the first part with a key only the logged-in user can derive.
+    let combined = zip_bytes(&[("header", &BOOK_KEY), ("body", &package)]);
+    let (head, body) = combined.split_at(128);
+
+    SyntheticBook {
+        head: head.to_vec(),
+        body: body.to_vec(),
+    }
+}
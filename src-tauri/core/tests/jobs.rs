@@ -0,0 +1,23 @@
+use jarir_reader_core::jobs::JobRegistry;
+use std::sync::Arc;
+
+#[test]
+fn one_exclusive_job_per_book() {
+    let jobs = Arc::new(JobRegistry::new());
+    let starts: Vec<_> = (0..8)
+        .map(|_| {
+            let jobs = jobs.clone();
+            std::thread::spawn(move || jobs.start_exclusive("1234"))
+        })
+        .collect();
+    let handles: Vec<_> = starts
+        .into_iter()
+        .filter_map(|start| start.join().unwrap())
+        .collect();
+    assert_eq!(handles.len(), 1);
+    assert!(jobs.start_exclusive("5678").is_some());
+
+    drop(handles);
+    assert!(jobs.list().is_empty());
+    assert!(jobs.start_exclusive("1234").is_some());
+}
@@ -0,0 +1,48 @@
+#![cfg(feature = "pdf")]
+
+use jarir_reader_core::book::Book;
+use jarir_reader_core::book_generator::book_generator;
+use jarir_reader_core::jobs::CancelToken;
+use jarir_reader_core::library::offline_books;
+use jarir_reader_core::output::work_dir;
+use std::path::Path;
+
+// The data dir is process-wide, so the whole flow is one test.
+#[tokio::test]
+async fn generated_pdf_is_found_offline() {
+    let data_dir = std::env::temp_dir().join(format!("jreader-pdf-{}", uuid::Uuid::new_v4()));
+    std::env::set_var("JREADER_DATA_DIR", &data_dir);
+
+    // What the extraction leaves for a PDF book.
+    let package = work_dir().join("4321");
+    std::fs::create_dir_all(package.join("Index")).unwrap();
+    std::fs::create_dir_all(package.join("Text")).unwrap();
+    std::fs::write(package.join("Index").join("info.json"), r#"{"type":"pdf"}"#).unwrap();
+    std::fs::write(package.join("Text").join("DATA.DATA"), b"%PDF-1.4").unwrap();
+
+    let book = Book {
+        id: "4321".to_string(),
+        title: "Notes: 1/2".to_string(),
+        access: true,
+        ..Book::default()
+    };
+    let pdf = book_generator(book, None, &CancelToken::new())
+        .await
+        .unwrap();
+
+    assert_eq!(pdf.file_name().unwrap(), "Notes 1 2.pdf");
+    assert!(pdf.starts_with(data_dir.join("books")));
+    assert_eq!(std::fs::read(&pdf).unwrap(), b"%PDF-1.4");
+    assert!(!package.join("Text").join("DATA.DATA").exists());
+
+    let books = offline_books().unwrap();
+    assert_eq!(books.len(), 1);
+    assert_eq!(books[0].book_type, "pdf");
+    assert_eq!(books[0].title, "Notes 1 2");
+    assert_eq!(
+        books[0].book_path.as_deref().map(Path::new),
+        Some(pdf.as_path())
+    );
+
+    let _ = std::fs::remove_dir_all(data_dir);
+}
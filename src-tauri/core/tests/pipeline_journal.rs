@@ -0,0 +1,122 @@
+#![cfg(all(feature = "network", feature = "epub"))]
+
+mod common;
+
+use common::store::{MockStore, BOOK_ID, EMAIL, FILE_ID, PASSWORD};
+use jarir_reader_core::api_calls::{
+    auth, download_and_generate_book, download_book, get_download_info, get_user_books,
+};
+use jarir_reader_core::book::Book;
+use jarir_reader_core::download::part_path;
+use jarir_reader_core::jobs::{CancelToken, ResidueGuard};
+use jarir_reader_core::journal::{recover, Journal, Stage};
+use jarir_reader_core::library::cached_books;
+use jarir_reader_core::network::{build_client, NetworkSettings};
+use jarir_reader_core::output::work_dir;
+use jarir_reader_core::progress::DownloadProgress;
+use jarir_reader_core::session::SessionManager;
+use jarir_reader_core::storage::{cleanup, storage_report};
+
+fn no_progress(_: DownloadProgress) {}
+
+// The data dir is process-wide, so the whole flow is one test.
+#[tokio::test]
+async fn interrupted_conversion_resumes_from_its_journal() {
+    let store = MockStore::start().await;
+    let data_dir = std::env::temp_dir().join(format!("jreader-journal-{}", uuid::Uuid::new_v4()));
+    std::env::set_var("JREADER_DATA_DIR", &data_dir);
+    let client = build_client(&NetworkSettings::default()).unwrap();
+    let backend = store.backend();
+    let session = SessionManager::new();
+    auth(&client, &backend, Some(EMAIL), Some(PASSWORD))
+        .await
+        .unwrap();
+    get_user_books(&client, &backend, &session).await.unwrap();
+
+    // The app died right after the download finished.
+    let book = cached_books().unwrap()[0].clone();
+    let info = get_download_info(&client, &backend, &session, &book)
+        .await
+        .unwrap();
+    let package = download_book(
+        &client,
+        &Book {
+            url: info.url.clone(),
+            header: info.header.clone(),
+            ..book.clone()
+        },
+        &no_progress,
+        &CancelToken::new(),
+    )
+    .await
+    .unwrap();
+    let mut journal = Journal {
+        url: info.url,
+        header: info.header,
+        ..Journal::new(BOOK_ID, FILE_ID)
+    };
+    journal.record(Stage::Downloaded, &package).unwrap();
+
+    let interrupted = recover(&cached_books().unwrap());
+    assert_eq!(interrupted.len(), 1);
+    assert_eq!(interrupted[0].id, BOOK_ID);
+
+    let epub = download_and_generate_book(
+        &client,
+        &backend,
+        &session,
+        BOOK_ID,
+        &no_progress,
+        &CancelToken::new(),
+    )
+    .await
+    .unwrap();
+    assert!(std::path::Path::new(&epub).exists());
+    assert_eq!(store.requests_to("/v7/books/file/download"), 1);
+    assert_eq!(store.requests_to(&format!("/files/{}.body", BOOK_ID)), 1);
+    assert_eq!(Journal::load(BOOK_ID), None);
+    assert!(!package.exists());
+
+    // Jobs for books the catalog does not know are cleaned up.
+    Journal::new("gone", "1").save().unwrap();
+    std::fs::write(work_dir().join("gone.zip"), b"package").unwrap();
+    assert!(recover(&cached_books().unwrap()).is_empty());
+    assert!(!work_dir().join("gone.zip").exists());
+    assert_eq!(Journal::load("gone"), None);
+
+    // Cleanup keeps what a journaled job can resume from and drops the rest.
+    let orphan = work_dir().join("orphan");
+    std::fs::create_dir_all(&orphan).unwrap();
+    std::fs::write(orphan.join("chapter.html"), b"<p>").unwrap();
+    std::fs::write(work_dir().join("kept.zip"), b"package").unwrap();
+    Journal::new("kept", "1").save().unwrap();
+    let report = storage_report().unwrap();
+    assert!(report.categories["output"] > 0);
+    assert_eq!(report.categories["extracted"], 3);
+    let cleaned = cleanup().unwrap();
+    assert_eq!(cleaned.removed[0], "orphan");
+    assert!(!data_dir.join("temp").exists());
+    assert_eq!(cleaned.freed, 3);
+    assert!(!orphan.exists());
+    assert!(work_dir().join("kept.zip").exists());
+
+    // A failed job keeps its files only when its journal can resume it; the
+    // partial download stays either way.
+    let failed = work_dir().join("failed");
+    std::fs::create_dir_all(&failed).unwrap();
+    std::fs::write(part_path(&failed.with_extension("zip")), b"pack").unwrap();
+    Journal::new("failed", "1").save().unwrap();
+    drop(ResidueGuard::new("failed", &CancelToken::new()));
+    assert!(!failed.exists());
+    assert_eq!(Journal::load("failed"), None);
+    assert!(part_path(&failed.with_extension("zip")).exists());
+    let mut journal = Journal::new("kept", "1");
+    journal
+        .record(Stage::Downloaded, &work_dir().join("kept.zip"))
+        .unwrap();
+    drop(ResidueGuard::new("kept", &CancelToken::new()));
+    assert!(work_dir().join("kept.zip").exists());
+    assert!(Journal::load("kept").is_some());
+
+    let _ = std::fs::remove_dir_all(data_dir);
+}
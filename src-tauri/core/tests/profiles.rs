@@ -0,0 +1,96 @@
+use jarir_reader_core::book::Book;
+use jarir_reader_core::credentials::{access_token, save_credentials, Credentials};
+use jarir_reader_core::library::{all_profile_books, apply_sync};
+use jarir_reader_core::profiles::{
+    active_profile, add_profile, list_profiles, profile_dir, profile_path, remove_profile,
+    switch_profile, DEFAULT_PROFILE,
+};
+use jarir_reader_core::settings::Settings;
+
+fn book(id: &str) -> Book {
+    Book {
+        id: id.to_string(),
+        access: true,
+        ..Book::default()
+    }
+}
+
+fn login(app: &str, token: &str) {
+    Settings::update(|s| s.app = Some(app.to_string())).unwrap();
+    save_credentials(&Credentials {
+        email: format!("{}@example.com", app),
+        password: "secret".to_string(),
+        access_token: Some(token.to_string()),
+    })
+    .unwrap();
+}
+
+// The data dir and the active profile are process-wide, so the whole flow is
+// one test.
+#[test]
+fn profiles_keep_accounts_and_catalogs_apart() {
+    let data_dir = std::env::temp_dir().join(format!("jreader-profiles-{}", uuid::Uuid::new_v4()));
+    std::env::set_var("JREADER_DATA_DIR", &data_dir);
+
+    assert_eq!(active_profile(), DEFAULT_PROFILE);
+    assert_eq!(profile_path("books"), data_dir.join("books"));
+    login("jarir", "token-a");
+    apply_sync(vec![book("1")]).unwrap();
+
+    let rufoof = add_profile(" Rufoof ").unwrap();
+    assert_eq!(rufoof.name, "Rufoof");
+    assert_eq!(add_profile("Rufoof").unwrap_err().code(), "1013");
+    switch_profile(&rufoof.id).unwrap();
+    assert_eq!(
+        profile_path("books"),
+        data_dir.join("profiles").join(&rufoof.id).join("books")
+    );
+    assert_eq!(Settings::load().unwrap().app, None);
+    assert_eq!(access_token().unwrap(), None);
+    login("rufoof", "token-b");
+    apply_sync(vec![book("1"), book("2")]).unwrap();
+
+    let merged: Vec<(String, String)> = all_profile_books()
+        .unwrap()
+        .into_iter()
+        .map(|b| (b.profile, b.book.id))
+        .collect();
+    let tag = |profile: &str, id: &str| (profile.to_string(), id.to_string());
+    assert_eq!(
+        merged,
+        [
+            tag(DEFAULT_PROFILE, "1"),
+            tag(&rufoof.id, "1"),
+            tag(&rufoof.id, "2")
+        ]
+    );
+
+    // The active profile stays until another one is picked.
+    assert_eq!(remove_profile(&rufoof.id).unwrap_err().code(), "1013");
+    switch_profile(DEFAULT_PROFILE).unwrap();
+    assert_eq!(Settings::load().unwrap().app.as_deref(), Some("jarir"));
+    assert_eq!(access_token().unwrap().as_deref(), Some("token-a"));
+
+    remove_profile(&rufoof.id).unwrap();
+    assert!(!profile_dir(&rufoof.id).exists());
+
+    // Converted books outlive the profile, nothing else does.
+    let kobo = add_profile("Kobo").unwrap();
+    switch_profile(&kobo.id).unwrap();
+    login("jarir", "token-c");
+    let epub = profile_path("books").join("book.epub");
+    std::fs::create_dir_all(epub.parent().unwrap()).unwrap();
+    std::fs::write(&epub, b"epub").unwrap();
+    switch_profile(DEFAULT_PROFILE).unwrap();
+    remove_profile(&kobo.id).unwrap();
+    assert!(epub.exists());
+    let left: Vec<_> = std::fs::read_dir(profile_dir(&kobo.id))
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect();
+    assert_eq!(left, ["books"]);
+    assert_eq!(list_profiles().unwrap().profiles.len(), 1);
+    assert_eq!(remove_profile(DEFAULT_PROFILE).unwrap_err().code(), "1013");
+
+    let _ = std::fs::remove_dir_all(data_dir);
+}
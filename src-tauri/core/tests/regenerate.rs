@@ -0,0 +1,94 @@
+#![cfg(all(feature = "network", feature = "epub"))]
+
+mod common;
+
+use common::store::{MockStore, BOOK_ID, EMAIL, FILE_ID, PASSWORD};
+use jarir_reader_core::api_calls::{auth, download_and_generate_book, get_user_books};
+use jarir_reader_core::jobs::CancelToken;
+use jarir_reader_core::library::{cached_books, conversion_history};
+use jarir_reader_core::network::{build_client, NetworkSettings};
+use jarir_reader_core::output::{change_output_settings, work_dir, OutputSettings};
+use jarir_reader_core::progress::DownloadProgress;
+use jarir_reader_core::session::SessionManager;
+use jarir_reader_core::sources::{self, regenerate_book, SOURCES_VERSION};
+use jarir_reader_core::storage::storage_report;
+use std::path::Path;
+
+fn no_progress(_: DownloadProgress) {}
+
+// The data dir is process-wide, so the whole flow is one test.
+#[tokio::test]
+async fn kept_sources_rebuild_books_without_the_store() {
+    let store = MockStore::start().await;
+    let data_dir = std::env::temp_dir().join(format!("jreader-sources-{}", uuid::Uuid::new_v4()));
+    std::env::set_var("JREADER_DATA_DIR", &data_dir);
+    let client = build_client(&NetworkSettings::default()).unwrap();
+    let backend = store.backend();
+    let session = SessionManager::new();
+    auth(&client, &backend, Some(EMAIL), Some(PASSWORD))
+        .await
+        .unwrap();
+    get_user_books(&client, &backend, &session).await.unwrap();
+
+    // Without the option nothing is kept.
+    let epub = download_and_generate_book(
+        &client,
+        &backend,
+        &session,
+        BOOK_ID,
+        &no_progress,
+        &CancelToken::new(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(sources::manifest(BOOK_ID), None);
+    assert_eq!(
+        regenerate_book(BOOK_ID, &CancelToken::new())
+            .await
+            .unwrap_err()
+            .code(),
+        "1024"
+    );
+
+    change_output_settings(OutputSettings {
+        keep_sources: true,
+        ..OutputSettings::load()
+    })
+    .unwrap();
+    download_and_generate_book(
+        &client,
+        &backend,
+        &session,
+        BOOK_ID,
+        &no_progress,
+        &CancelToken::new(),
+    )
+    .await
+    .unwrap();
+    let manifest = sources::manifest(BOOK_ID).unwrap();
+    assert_eq!(manifest.version, SOURCES_VERSION);
+    assert_eq!(manifest.file_id, FILE_ID);
+    assert!(sources::is_kept(BOOK_ID, FILE_ID));
+    assert!(!work_dir().join(BOOK_ID).exists());
+    assert!(storage_report().unwrap().categories["sources"] > 0);
+
+    // The output is lost and the store is gone.
+    std::fs::remove_file(&epub).unwrap();
+    let downloads = store.requests_to("/v7/books/file/download");
+    drop(store);
+    let regenerated = regenerate_book(BOOK_ID, &CancelToken::new())
+        .await
+        .unwrap();
+    assert_eq!(regenerated, epub);
+    assert!(Path::new(&regenerated).exists());
+    assert!(!work_dir().join(BOOK_ID).exists());
+    assert_eq!(downloads, 2);
+
+    let book = cached_books().unwrap().remove(0);
+    assert_eq!(book.book_path.as_deref(), Some(epub.as_str()));
+    let history = conversion_history(BOOK_ID).unwrap();
+    assert_eq!(history.len(), 4);
+    assert_eq!(history[0].status, "done");
+
+    let _ = std::fs::remove_dir_all(data_dir);
+}
@@ -0,0 +1,165 @@
+#![cfg(feature = "network")]
+
+mod common;
+
+use common::{range_start, MockResponse, MockServer};
+use jarir_reader_core::download::{fetch_resumable, part_path};
+use jarir_reader_core::error::{AppError, ErrorKind};
+use jarir_reader_core::jobs::CancelToken;
+use jarir_reader_core::progress::DownloadProgress;
+use reqwest::Client;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+fn package() -> Vec<u8> {
+    (0..50_000u32).map(|i| (i % 251) as u8).collect()
+}
+
+fn temp_dest() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("jreader-test-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir.join("1234.zip")
+}
+
+fn no_progress(_: DownloadProgress) {}
+
+async fn fetch(client: &Client, url: &str, dest: &Path) -> Result<(), AppError> {
+    fetch_resumable(
+        client,
+        url,
+        dest,
+        "1234",
+        None,
+        &no_progress,
+        &CancelToken::new(),
+    )
+    .await
+}
+
+/// Serves `data` honouring `Range`/`If-Range`, but cuts the very first
+/// response short to simulate a dropped connection.
+fn ranged_handler(
+    data: Vec<u8>,
+    etag: &'static str,
+) -> impl Fn(&common::MockRequest) -> MockResponse + Send + Sync {
+    let calls = Arc::new(AtomicUsize::new(0));
+    move |request| {
+        let call = calls.fetch_add(1, Ordering::SeqCst);
+        let validator_ok = request.header("if-range").filter(|v| *v != etag).is_none();
+        match range_start(request) {
+            Some(start) if validator_ok && start < data.len() => {
+                MockResponse::new(206, data[start..].to_vec())
+                    .header("ETag", etag)
+                    .header(
+                        "Content-Range",
+                        &format!("bytes {}-{}/{}", start, data.len() - 1, data.len()),
+                    )
+            }
+            _ => {
+                let response = MockResponse::new(200, data.clone()).header("ETag", etag);
+                if call == 0 {
+                    response.cut_after(data.len() / 3)
+                } else {
+                    response
+                }
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn resumes_interrupted_download_with_range() {
+    let data = package();
+    let server = MockServer::start(ranged_handler(data.clone(), "\"v1\"")).await;
+    let client = Client::new();
+    let dest = temp_dest();
+    let url = server.url("/book.zip");
+
+    let first = fetch(&client, &url, &dest).await;
+    assert!(first.is_err());
+    assert!(!dest.exists());
+    let kept = std::fs::metadata(part_path(&dest)).unwrap().len();
+    assert!(kept > 0 && kept < data.len() as u64);
+
+    fetch(&client, &url, &dest).await.unwrap();
+    assert_eq!(std::fs::read(&dest).unwrap(), data);
+    assert!(!part_path(&dest).exists());
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(
+        requests[1].header("range"),
+        Some(&*format!("bytes={}-", kept))
+    );
+    assert_eq!(requests[1].header("if-range"), Some("\"v1\""));
+}
+
+#[tokio::test]
+async fn falls_back_to_full_download_when_range_is_ignored() {
+    let data = package();
+    let calls = Arc::new(AtomicUsize::new(0));
+    let body = data.clone();
+    let server = MockServer::start(move |_| {
+        let response = MockResponse::new(200, body.clone()).header("ETag", "\"v1\"");
+        if calls.fetch_add(1, Ordering::SeqCst) == 0 {
+            response.cut_after(body.len() / 2)
+        } else {
+            response
+        }
+    })
+    .await;
+    let client = Client::new();
+    let dest = temp_dest();
+    let url = server.url("/book.zip");
+
+    assert!(fetch(&client, &url, &dest).await.is_err());
+    fetch(&client, &url, &dest).await.unwrap();
+
+    assert_eq!(std::fs::read(&dest).unwrap(), data);
+    assert!(server.requests()[1].header("range").is_some());
+}
+
+#[tokio::test]
+async fn restarts_when_remote_file_changed() {
+    let data = package();
+    let dest = temp_dest();
+    let first = MockServer::start(ranged_handler(data.clone(), "\"v1\"")).await;
+    let client = Client::new();
+    assert!(fetch(&client, &first.url("/book.zip"), &dest)
+        .await
+        .is_err());
+
+    let updated: Vec<u8> = data.iter().map(|b| b.wrapping_add(1)).collect();
+    let second = MockServer::start(ranged_handler(updated.clone(), "\"v2\"")).await;
+    let url = second.url("/book.zip");
+    // The new server cuts its first answer too; the retry after that is clean.
+    let _ = fetch(&client, &url, &dest).await;
+    fetch(&client, &url, &dest).await.unwrap();
+
+    assert_eq!(std::fs::read(&dest).unwrap(), updated);
+}
+
+#[tokio::test]
+async fn cancelled_download_sends_no_request() {
+    let server = MockServer::start(|_| MockResponse::new(200, package())).await;
+    let dest = temp_dest();
+    let cancel = CancelToken::new();
+    cancel.cancel();
+
+    let error = fetch_resumable(
+        &Client::new(),
+        &server.url("/book.zip"),
+        &dest,
+        "1234",
+        None,
+        &no_progress,
+        &cancel,
+    )
+    .await
+    .unwrap_err();
+
+    assert_eq!(error.kind(), ErrorKind::Cancelled);
+    assert!(server.requests().is_empty());
+    assert!(!dest.exists());
+}
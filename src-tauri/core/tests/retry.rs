@@ -0,0 +1,103 @@
+#![cfg(feature = "network")]
+
+mod common;
+
+use common::{MockResponse, MockServer};
+use jarir_reader_core::error::ErrorKind;
+use jarir_reader_core::request::{reject_unauthorized, send_with_policy, RetryPolicy};
+use reqwest::Client;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+fn fast_policy() -> RetryPolicy {
+    RetryPolicy {
+        max_attempts: 4,
+        base_delay: Duration::from_millis(10),
+        max_delay: Duration::from_millis(50),
+        timeout: Some(Duration::from_secs(5)),
+    }
+}
+
+/// Answers with `failures` in order, then succeeds.
+async fn flaky_server(failures: Vec<MockResponse>) -> MockServer {
+    let calls = Arc::new(AtomicUsize::new(0));
+    MockServer::start(move |_| {
+        let call = calls.fetch_add(1, Ordering::SeqCst);
+        failures
+            .get(call)
+            .cloned()
+            .unwrap_or_else(|| MockResponse::new(200, "ok"))
+    })
+    .await
+}
+
+#[tokio::test]
+async fn retries_server_errors_and_rate_limits() {
+    let server = flaky_server(vec![
+        MockResponse::new(503, ""),
+        MockResponse::new(429, "").header("Retry-After", "0"),
+    ])
+    .await;
+    let client = Client::new();
+    let url = server.url("/v7/books/get-user-books");
+
+    let response = send_with_policy("test", &fast_policy(), || client.post(&url))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+    assert_eq!(server.requests().len(), 3);
+}
+
+#[tokio::test]
+async fn gives_up_after_max_attempts() {
+    let server = MockServer::start(|_| MockResponse::new(500, "")).await;
+    let client = Client::new();
+    let url = server.url("/v7/login/token");
+
+    let response = send_with_policy("test", &fast_policy(), || client.post(&url))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 500);
+    assert_eq!(server.requests().len(), 4);
+}
+
+#[tokio::test]
+async fn surfaces_auth_failures_immediately() {
+    let server = flaky_server(vec![MockResponse::new(401, "")]).await;
+    let client = Client::new();
+    let url = server.url("/v7/books/file/download");
+
+    let response = send_with_policy("test", &fast_policy(), || client.post(&url))
+        .await
+        .unwrap();
+    let error = reject_unauthorized(response, "801").unwrap_err();
+
+    assert_eq!(error.kind(), ErrorKind::Auth);
+    assert_eq!(error.code(), "801");
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn retries_connection_errors() {
+    // Nothing listens on the port once the listener is dropped.
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let client = Client::new();
+    let url = format!("http://127.0.0.1:{}/v7/login/login", port);
+    let attempts = AtomicUsize::new(0);
+
+    let result = send_with_policy("test", &fast_policy(), || {
+        attempts.fetch_add(1, Ordering::SeqCst);
+        client.post(&url)
+    })
+    .await;
+
+    assert!(result.unwrap_err().is_connect());
+    assert_eq!(attempts.load(Ordering::SeqCst), 4);
+}
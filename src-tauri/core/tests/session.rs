@@ -0,0 +1,61 @@
+#![cfg(feature = "network")]
+
+mod common;
+
+use common::store::{MockStore, ACCESS_TOKEN, BOOK_ID, EMAIL, FILE_ID, PASSWORD};
+use jarir_reader_core::api_calls::get_download_info;
+use jarir_reader_core::book::Book;
+use jarir_reader_core::network::{build_client, NetworkSettings};
+use jarir_reader_core::session::SessionManager;
+
+// The data dir is process-wide, so the whole flow is one test.
+#[tokio::test]
+async fn parallel_requests_share_one_login_and_one_refresh() {
+    let store = MockStore::start().await;
+    let data_dir = std::env::temp_dir().join(format!("jreader-session-{}", uuid::Uuid::new_v4()));
+    std::env::set_var("JREADER_DATA_DIR", &data_dir);
+    let client = build_client(&NetworkSettings::default()).unwrap();
+    let backend = store.backend();
+    let session = SessionManager::new();
+    let book = Book {
+        id: BOOK_ID.to_string(),
+        file_id: FILE_ID.to_string(),
+        ..Book::default()
+    };
+
+    let user = session
+        .login(&client, &backend, Some(EMAIL), Some(PASSWORD))
+        .await
+        .unwrap();
+    assert_eq!(user.auth, ACCESS_TOKEN);
+
+    let parallel = || async {
+        let info = || get_download_info(&client, &backend, &session, &book);
+        let (a, b, c, d) = tokio::join!(info(), info(), info(), info());
+        [a, b, c, d]
+    };
+    for info in parallel().await {
+        assert_eq!(info.unwrap().header, store.book.header_for(ACCESS_TOKEN));
+    }
+    assert_eq!(store.requests_to("/v7/login/login"), 1);
+
+    // Every request gets a 401 with the old token, but only one logs in.
+    store.revoke_tokens();
+    for info in parallel().await {
+        assert!(info.is_ok());
+    }
+    assert_eq!(store.requests_to("/v7/login/login"), 2);
+    assert_ne!(
+        session.get(&client, &backend).await.unwrap().auth,
+        ACCESS_TOKEN
+    );
+
+    // Credentials typed in again are used, not the saved token.
+    session
+        .login(&client, &backend, Some(EMAIL), Some(PASSWORD))
+        .await
+        .unwrap();
+    assert_eq!(store.requests_to("/v7/login/login"), 3);
+
+    let _ = std::fs::remove_dir_all(data_dir);
+}
@@ -0,0 +1,27 @@
+use jarir_reader_core::profiles::DEFAULT_PROFILE;
+use jarir_reader_core::settings::Settings;
+use serde_json::Value;
+
+// The data dir is process-wide, so the whole flow is one test.
+#[test]
+fn concurrent_updates_are_not_lost() {
+    let data_dir = std::env::temp_dir().join(format!("jreader-settings-{}", uuid::Uuid::new_v4()));
+    std::env::set_var("JREADER_DATA_DIR", &data_dir);
+
+    let writers: Vec<_> = (0..8)
+        .map(|i| {
+            std::thread::spawn(move || {
+                Settings::update_for(DEFAULT_PROFILE, |s| {
+                    s.other.insert(format!("key-{}", i), Value::from(i));
+                })
+                .unwrap();
+            })
+        })
+        .collect();
+    for writer in writers {
+        writer.join().unwrap();
+    }
+
+    assert_eq!(Settings::load_for(DEFAULT_PROFILE).unwrap().other.len(), 8);
+    let _ = std::fs::remove_dir_all(data_dir);
+}
@@ -0,0 +1,204 @@
+#![cfg(all(feature = "network", feature = "epub"))]
+
+mod common;
+
+use common::store::{
+    MockStore, ACCESS_TOKEN, BOOK_ID, BOOK_TITLE, CHAPTER_TEXT, EMAIL, FILE_ID, PASSWORD, USERNAME,
+};
+use jarir_reader_core::api_calls::{
+    auth, download_and_generate_book, get_user_books, logout, sync_library,
+};
+use jarir_reader_core::credentials::{access_token, load_credentials};
+use jarir_reader_core::cross_platform::get_app_data_path;
+use jarir_reader_core::error::ErrorKind;
+use jarir_reader_core::jobs::CancelToken;
+use jarir_reader_core::library::{cached_books, conversion_history, offline_books, LibraryChanges};
+use jarir_reader_core::network::{build_client, NetworkSettings};
+use jarir_reader_core::output::{
+    change_output_settings, purge, work_dir, OutputLayout, OutputSettings,
+};
+use jarir_reader_core::progress::DownloadProgress;
+use jarir_reader_core::session::SessionManager;
+use std::io::Read;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Reads every text entry of the generated EPUB into one string.
+fn epub_text(path: &str) -> String {
+    let mut archive = zip::ZipArchive::new(std::fs::File::open(path).unwrap()).unwrap();
+    let mut text = String::new();
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i).unwrap();
+        let _ = entry.read_to_string(&mut text);
+    }
+    text
+}
+
+// The data dir is process-wide, so the whole flow is one test.
+#[tokio::test]
+async fn login_list_download_and_convert_against_mock_store() {
+    let store = MockStore::start().await;
+    let data_dir = std::env::temp_dir().join(format!("jreader-e2e-{}", uuid::Uuid::new_v4()));
+    std::env::set_var("JREADER_DATA_DIR", &data_dir);
+    let client = build_client(&NetworkSettings::default()).unwrap();
+    let backend = store.backend();
+    let session = SessionManager::new();
+
+    // A login saved in plain text by an older version moves to the
+    // credential store, its book list to the catalog.
+    let settings_path = get_app_data_path(Some("settings.json"));
+    std::fs::create_dir_all(&data_dir).unwrap();
+    std::fs::write(
+        &settings_path,
+        format!(
+            r#"{{"email":"{}","password":"{}","books":{{"cached_at":1,"items":[{{"id":"old"}}]}}}}"#,
+            EMAIL, PASSWORD
+        ),
+    )
+    .unwrap();
+    let legacy = load_credentials().unwrap().unwrap();
+    assert_eq!(
+        (legacy.email.as_str(), legacy.password.as_str()),
+        (EMAIL, PASSWORD)
+    );
+    assert_eq!(cached_books().unwrap()[0].id, "old");
+    let settings = std::fs::read_to_string(&settings_path).unwrap();
+    assert!(!settings.contains(PASSWORD) && !settings.contains("books"));
+
+    let error = auth(&client, &backend, Some(EMAIL), Some("wrong"))
+        .await
+        .unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::Auth);
+
+    let user = auth(&client, &backend, Some(EMAIL), Some(PASSWORD))
+        .await
+        .unwrap();
+    assert_eq!(user.username, USERNAME);
+    assert_eq!(user.auth, ACCESS_TOKEN);
+    assert_eq!(user.app_type, "mock");
+    assert_eq!(access_token().unwrap().as_deref(), Some(ACCESS_TOKEN));
+    let settings = std::fs::read_to_string(&settings_path).unwrap();
+    assert!(!settings.contains(ACCESS_TOKEN));
+
+    let books = get_user_books(&client, &backend, &session).await.unwrap();
+    assert_eq!(books.len(), 2);
+    assert_eq!(books[0].id, BOOK_ID);
+    assert_eq!(books[0].title, BOOK_TITLE);
+    assert!(books[0].access);
+    assert_eq!(books[0].publisher, "Mock Publisher");
+    // The store no longer lists the imported book; it is kept without access.
+    assert_eq!(books[1].id, "old");
+    assert!(!books[1].access);
+
+    let downloaded = AtomicU64::new(0);
+    let on_progress = |progress: DownloadProgress| {
+        downloaded.store(progress.downloaded, Ordering::SeqCst);
+    };
+    let epub = download_and_generate_book(
+        &client,
+        &backend,
+        &session,
+        BOOK_ID,
+        &on_progress,
+        &CancelToken::new(),
+    )
+    .await
+    .unwrap();
+
+    assert!(epub.ends_with(".epub"));
+    assert!(std::path::Path::new(&epub).starts_with(data_dir.join("books")));
+    assert!(epub_text(&epub).contains(CHAPTER_TEXT));
+    assert_eq!(
+        downloaded.load(Ordering::SeqCst),
+        store.book.body.len() as u64
+    );
+    let package = work_dir().join(BOOK_ID);
+    assert!(!package.exists());
+    assert!(!package.with_extension("zip").exists());
+    assert!(!package.with_extension("zip.body").exists());
+    assert_eq!(store.requests_to("/v7/books/file/download"), 1);
+
+    // The download came from the catalog and is recorded there.
+    assert_eq!(store.requests_to("/v7/books/get-user-books"), 1);
+    let catalog = cached_books().unwrap();
+    assert_eq!(catalog[0].book_path.as_deref(), Some(epub.as_str()));
+    assert_eq!(catalog[0].downloaded_file_id.as_deref(), Some(FILE_ID));
+    let sync = sync_library(&client, &backend, &session).await.unwrap();
+    assert_eq!(sync.changes, LibraryChanges::default());
+    assert_eq!(sync.books[0].book_path.as_deref(), Some(epub.as_str()));
+    assert!(!sync.books[0].update_available);
+    let history = conversion_history(BOOK_ID).unwrap();
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0].status, "done");
+    assert_eq!(history[0].output.as_deref(), Some(epub.as_str()));
+
+    // A new output folder takes the converted book along.
+    change_output_settings(OutputSettings {
+        dir: Some("library".to_string()),
+        layout: OutputLayout::Author,
+        ..OutputSettings::default()
+    })
+    .unwrap();
+    let moved = cached_books().unwrap()[0].book_path.clone().unwrap();
+    assert!(std::path::Path::new(&moved).starts_with(data_dir.join("library")));
+    assert!(epub_text(&moved).contains(CHAPTER_TEXT));
+    assert!(!std::path::Path::new(&epub).exists());
+
+    // The Calibre layout gives the book a folder of its own, with metadata.
+    change_output_settings(OutputSettings {
+        dir: Some("library".to_string()),
+        layout: OutputLayout::Calibre,
+        ..OutputSettings::default()
+    })
+    .unwrap();
+    let moved = cached_books().unwrap()[0].book_path.clone().unwrap();
+    let folder = std::path::Path::new(&moved).parent().unwrap().to_path_buf();
+    assert_eq!(
+        folder.file_name().unwrap().to_string_lossy(),
+        format!("{} ({})", BOOK_TITLE, BOOK_ID)
+    );
+    let opf = std::fs::read_to_string(folder.join("metadata.opf")).unwrap();
+    assert!(opf.contains(&format!("<dc:title>{}</dc:title>", BOOK_TITLE)));
+    assert!(opf.contains(&format!(">{}</dc:identifier>", BOOK_ID)));
+    assert!(opf.contains("<dc:subject>epub</dc:subject>"));
+
+    // Books in nested folders are found offline, once each.
+    let loose = folder.parent().unwrap().join("Scan.pdf");
+    std::fs::write(&loose, b"%PDF").unwrap();
+    let offline = offline_books().unwrap();
+    assert_eq!(offline.len(), 2);
+    assert_eq!(offline[0].book_path.as_deref(), Some(moved.as_str()));
+    assert_eq!(offline[1].book_type, "pdf");
+    std::fs::remove_file(&loose).unwrap();
+
+    assert!(logout(&client, &backend).await.unwrap());
+    assert_eq!(store.requests_to("/v7/logout"), 1);
+    assert_eq!(access_token().unwrap(), None);
+    assert!(!get_app_data_path(Some("credentials.enc")).exists());
+
+    // Logging out keeps the library; only a confirmed purge deletes books.
+    assert_eq!(
+        cached_books().unwrap()[0].book_path.as_deref(),
+        Some(moved.as_str())
+    );
+
+    // Metadata written after logging out still names the store.
+    change_output_settings(OutputSettings {
+        dir: Some("calibre".to_string()),
+        layout: OutputLayout::Calibre,
+        ..OutputSettings::default()
+    })
+    .unwrap();
+    let moved = cached_books().unwrap()[0].book_path.clone().unwrap();
+    let folder = std::path::Path::new(&moved).parent().unwrap().to_path_buf();
+    let opf = std::fs::read_to_string(folder.join("metadata.opf")).unwrap();
+    assert!(opf.contains(&format!(r#"opf:scheme="mock" id="store_id">{}<"#, BOOK_ID)));
+    assert_eq!(purge(true, false).unwrap_err().code(), "1018");
+    assert!(std::path::Path::new(&moved).exists());
+    purge(true, true).unwrap();
+    assert!(!std::path::Path::new(&moved).exists());
+    assert!(!folder.exists());
+    assert!(cached_books().unwrap().is_empty());
+    assert!(data_dir.join("calibre").exists());
+
+    let _ = std::fs::remove_dir_all(data_dir);
+}
@@ -1,9 +0,0 @@
-pub mod api_calls;
-pub mod audio;
-pub mod book;
-pub mod book_generator;
-pub mod cross_platform;
-pub mod decrypt;
-pub mod epub;
-pub mod helpers;
-pub mod transliteration;
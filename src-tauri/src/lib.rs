@@ -1,49 +1,248 @@
-use reqwest::header::{HeaderMap, HeaderValue, CONTENT_TYPE, USER_AGENT};
 use reqwest::Client;
-use reqwest::ClientBuilder;
 use serde_json::Value;
 use tauri::{AppHandle, Emitter, Manager, State};
 use tauri_plugin_opener::OpenerExt;
 
-mod backend;
+pub use jarir_reader_core as backend;
+
 use crate::backend::api_calls::{
-    auth, check_for_new_version, download_and_generate_book, get_user_books, logout, pre_auth,
+    check_for_new_version, download_and_generate_book, get_user_books, logout, pre_auth,
+    sync_library,
 };
 use crate::backend::book::Book;
-use crate::backend::cross_platform::get_app_data_path;
-use crate::backend::helpers::get_settings;
+use crate::backend::catalog::{BookPage, BookQuery, Conversion};
+use crate::backend::error::AppError;
+use crate::backend::jobs::{JobInfo, JobRegistry};
+use crate::backend::journal;
+use crate::backend::library::{
+    all_profile_books, cached_books, conversion_history, offline_books, query_books, LibrarySync,
+    ProfileBook,
+};
+use crate::backend::logging;
+use crate::backend::network::{build_client, NetworkSettings};
+use crate::backend::output::{self, change_output_settings, output_dir, OutputSettings};
+use crate::backend::profiles::{self, Profile, Profiles};
+use crate::backend::progress::DownloadProgress;
+use crate::backend::queue::{DownloadQueue, Job, JobFuture, QueueState, QueueStatus};
+use crate::backend::session::SessionManager;
+use crate::backend::settings::Settings;
+use crate::backend::sources;
+use crate::backend::storage::{self, CleanupReport, StorageReport};
+use crate::backend::store::{current_store, store_for};
+use std::sync::{Arc, RwLock};
+use tracing::level_filters::LevelFilter;
 
-struct HttpClient(Client);
+/// The shared HTTP client, rebuilt when the network settings change.
+struct HttpClient(RwLock<Client>);
 
-fn create_http_client() -> Client {
-    let mut headers = HeaderMap::new();
-    headers.insert(
-        CONTENT_TYPE,
-        HeaderValue::from_static("application/x-www-form-urlencoded"),
-    );
-    headers.insert(USER_AGENT, HeaderValue::from_static("okhttp/4.3.1"));
+impl HttpClient {
+    fn new() -> Self {
+        HttpClient(RwLock::new(Self::from_settings()))
+    }
 
-    ClientBuilder::new()
-        .default_headers(headers)
-        .danger_accept_invalid_certs(true)
-        .build()
-        .unwrap()
+    /// A client for the saved network settings, or the defaults if those are
+    /// invalid.
+    fn from_settings() -> Client {
+        build_client(&NetworkSettings::load()).unwrap_or_else(|e| {
+            tracing::warn!("Invalid network settings, using defaults: {}", e);
+            build_client(&NetworkSettings::default()).unwrap()
+        })
+    }
+
+    fn get(&self) -> Client {
+        self.0.read().unwrap().clone()
+    }
 }
 
 #[tauri::command]
 fn visit_book(app_handle: AppHandle, book_id: String) {
     app_handle
         .opener()
-        .open_url(format!("https://jarirreader.com/book/{}/github-abdumu", book_id),None::<&str>)
+        .open_url(current_store().book_page(&book_id), None::<&str>)
         .unwrap();
 }
 
+/// Runs one download-and-convert job, registered so `cancel_job` can stop it.
+/// Refused while the book already has a job, from the queue or otherwise,
+/// as both would work on the same files.
+async fn run_book_job(
+    app_handle: &AppHandle,
+    client: &Client,
+    book_id: &str,
+) -> Result<String, AppError> {
+    let jobs = app_handle.state::<Arc<JobRegistry>>();
+    let Some(job) = jobs.start_exclusive(book_id) else {
+        return Err(AppError::integrity(
+            "1014",
+            "الكتاب قيد التحميل بالفعل، انتظر حتى ينتهي أو ألغه",
+        ));
+    };
+    let _ = app_handle.emit("job-started", &job.info);
+    let on_progress = |progress: DownloadProgress| {
+        let _ = app_handle.emit("download-progress", progress);
+    };
+    let store = current_store();
+    let session = app_handle.state::<SessionManager>();
+    let result =
+        download_and_generate_book(client, &*store, &session, book_id, &on_progress, &job.token)
+            .await;
+    let _ = app_handle.emit("job-finished", &job.info);
+    result
+}
+
+#[tauri::command]
+async fn download_book(
+    app_handle: AppHandle,
+    state: State<'_, HttpClient>,
+    book_id: String,
+) -> Result<String, AppError> {
+    let client = &state.get();
+    run_book_job(&app_handle, client, &book_id).await
+}
+
+/// Builds a converted book again from its kept source, without the store.
+/// Runs as a job so `cancel_job` can stop it; refused while the book is
+/// being converted.
+#[tauri::command]
+async fn regenerate_book(
+    app_handle: AppHandle,
+    jobs: State<'_, Arc<JobRegistry>>,
+    book_id: String,
+) -> Result<String, AppError> {
+    let Some(job) = jobs.start_exclusive(&book_id) else {
+        return Err(AppError::integrity(
+            "1014",
+            "انتظر حتى ينتهي تحويل الكتاب أو ألغه",
+        ));
+    };
+    let _ = app_handle.emit("job-started", &job.info);
+    let result = sources::regenerate_book(&book_id, &job.token).await;
+    let _ = app_handle.emit("job-finished", &job.info);
+    result
+}
+
+#[tauri::command]
+fn cancel_job(jobs: State<'_, Arc<JobRegistry>>, job_id: String) -> bool {
+    jobs.cancel(&job_id)
+}
+
+#[tauri::command]
+fn list_jobs(jobs: State<'_, Arc<JobRegistry>>) -> Vec<JobInfo> {
+    jobs.list()
+}
+
+#[tauri::command]
+async fn queue_add(
+    state: State<'_, HttpClient>,
+    session: State<'_, SessionManager>,
+    queue: State<'_, Arc<DownloadQueue>>,
+    book_ids: Vec<String>,
+) -> Result<QueueState, AppError> {
+    let books = get_user_books(&state.get(), &*current_store(), &session).await?;
+    let wanted: Vec<Book> = books
+        .into_iter()
+        .filter(|b| book_ids.contains(&b.id))
+        .collect();
+    queue.enqueue(&wanted)?;
+    Ok(queue.snapshot())
+}
+
+#[tauri::command]
+async fn queue_add_all(
+    state: State<'_, HttpClient>,
+    session: State<'_, SessionManager>,
+    queue: State<'_, Arc<DownloadQueue>>,
+) -> Result<QueueState, AppError> {
+    let books = get_user_books(&state.get(), &*current_store(), &session).await?;
+    queue.enqueue_missing(&books)?;
+    Ok(queue.snapshot())
+}
+
+#[tauri::command]
+fn queue_action(
+    queue: State<'_, Arc<DownloadQueue>>,
+    jobs: State<'_, Arc<JobRegistry>>,
+    action: String,
+    book_id: Option<String>,
+    value: Option<usize>,
+) -> Result<QueueState, AppError> {
+    let book_id = book_id.unwrap_or_default();
+    match action.as_str() {
+        "pause" => queue.set_paused(true)?,
+        "resume" => queue.set_paused(false)?,
+        "remove" => {
+            jobs.cancel_book(&book_id);
+            queue.remove(&book_id)?
+        }
+        "move" => queue.reorder(&book_id, value.unwrap_or(0))?,
+        "parallelism" => queue.set_parallelism(value.unwrap_or(1))?,
+        "clear" => queue.clear_finished()?,
+        _ => {}
+    }
+    Ok(queue.snapshot())
+}
+
+fn start_download_queue(app: &tauri::App) {
+    let queue = DownloadQueue::load();
+    app.manage(queue.clone());
+
+    // Finish the jobs the last run was in the middle of.
+    match cached_books() {
+        Ok(books) => {
+            let interrupted = journal::recover(&books);
+            if let Err(e) = queue.enqueue(&interrupted) {
+                tracing::warn!("Could not queue interrupted jobs: {}", e);
+            }
+        }
+        Err(e) => tracing::warn!("Skipping job recovery: {}", e),
+    }
+
+    let handle = app.handle().clone();
+    let job: Job = Arc::new(move |book_id: String| -> JobFuture {
+        let handle = handle.clone();
+        Box::pin(async move {
+            let client = handle.state::<HttpClient>().get();
+            run_book_job(&handle, &client, &book_id).await
+        })
+    });
+    let handle = app.handle().clone();
+    let on_change = Arc::new(move |state: QueueState| {
+        let _ = handle.emit("queue-changed", state);
+    });
+    tauri::async_runtime::spawn(queue.run(job, on_change));
+}
+
+#[tauri::command]
+fn network_settings(
+    state: State<'_, HttpClient>,
+    settings: Option<NetworkSettings>,
+) -> Result<NetworkSettings, AppError> {
+    if let Some(settings) = settings {
+        let client = build_client(&settings)?;
+        settings.save()?;
+        *state.0.write().unwrap() = client;
+    }
+    Ok(NetworkSettings::load())
+}
+
+/// Reads the output settings, or saves new ones and moves the converted
+/// books to match them once no download is writing there.
 #[tauri::command]
-async fn download_book(state: State<'_, HttpClient>, book_id: String) -> Result<String, String> {
-    let client = &state.0;
-    download_and_generate_book(client, &book_id)
-        .await
-        .map_err(|e| e.to_string())
+fn output_settings(
+    queue: State<'_, Arc<DownloadQueue>>,
+    jobs: State<'_, Arc<JobRegistry>>,
+    settings: Option<OutputSettings>,
+) -> Result<OutputSettings, AppError> {
+    let Some(settings) = settings else {
+        return Ok(OutputSettings::load());
+    };
+    if downloads_busy(&queue, &jobs) {
+        return Err(AppError::integrity(
+            "1014",
+            "أكمل التحميلات الجارية أو ألغها قبل تغيير مجلد الكتب",
+        ));
+    }
+    change_output_settings(settings)
 }
 
 #[tauri::command]
@@ -52,20 +251,164 @@ fn open_file(app_handle: AppHandle, file_path: String) {
 }
 
 #[tauri::command]
-async fn check_updates(state: State<'_, HttpClient>) -> Result<Value, String> {
-    let client = &state.0;
-    check_for_new_version(client)
-        .await
-        .map_err(|e| e.to_string())
+async fn check_updates(state: State<'_, HttpClient>) -> Result<Value, AppError> {
+    let client = &state.get();
+    check_for_new_version(client).await
+}
+
+#[tauri::command]
+async fn get_books(
+    state: State<'_, HttpClient>,
+    session: State<'_, SessionManager>,
+) -> Result<Vec<Book>, AppError> {
+    let client = &state.get();
+    get_user_books(client, &*current_store(), &session).await
+}
+
+/// The downloaded library, built without any store call.
+#[tauri::command]
+fn get_offline_books() -> Result<Vec<Book>, AppError> {
+    offline_books()
+}
+
+#[tauri::command]
+async fn sync_books(
+    state: State<'_, HttpClient>,
+    session: State<'_, SessionManager>,
+) -> Result<LibrarySync, AppError> {
+    let client = &state.get();
+    sync_library(client, &*current_store(), &session).await
+}
+
+/// Search, filters, sorting and paging over the local catalog.
+#[tauri::command]
+fn search_books(query: BookQuery) -> Result<BookPage, AppError> {
+    query_books(&query)
+}
+
+#[tauri::command]
+fn book_history(book_id: String) -> Result<Vec<Conversion>, AppError> {
+    conversion_history(&book_id)
+}
+
+/// The catalogs of all profiles merged, each book tagged with its profile.
+#[tauri::command]
+fn get_all_books() -> Result<Vec<ProfileBook>, AppError> {
+    all_profile_books()
+}
+
+#[tauri::command]
+fn get_profiles() -> Result<Profiles, AppError> {
+    profiles::list_profiles()
+}
+
+#[tauri::command]
+fn add_profile(name: String) -> Result<Profile, AppError> {
+    profiles::add_profile(&name)
+}
+
+/// Whether a download is queued or running, or a conversion is in progress.
+fn downloads_busy(queue: &DownloadQueue, jobs: &JobRegistry) -> bool {
+    queue
+        .snapshot()
+        .items
+        .iter()
+        .any(|i| matches!(i.status, QueueStatus::Queued | QueueStatus::Running))
+        || !jobs.list().is_empty()
+}
+
+/// Switches accounts. Refused while downloads are queued or running, as
+/// those belong to the profile being left.
+#[tauri::command]
+async fn switch_profile(
+    state: State<'_, HttpClient>,
+    session: State<'_, SessionManager>,
+    queue: State<'_, Arc<DownloadQueue>>,
+    jobs: State<'_, Arc<JobRegistry>>,
+    profile_id: String,
+) -> Result<Profile, AppError> {
+    if downloads_busy(&queue, &jobs) {
+        return Err(AppError::integrity(
+            "1014",
+            "أكمل التحميلات الجارية أو ألغها قبل تبديل الحساب",
+        ));
+    }
+
+    let profile = profiles::switch_profile(&profile_id)?;
+    session.clear().await;
+    // Each profile keeps its own queue.
+    queue.reload();
+    // Network settings are per profile too.
+    *state.0.write().unwrap() = HttpClient::from_settings();
+    Ok(profile)
+}
+
+/// Clears the cached packages and the catalog of the current profile, and
+/// the converted books when `delete_converted` is set and `confirm`ed.
+#[tauri::command]
+fn purge_library(
+    queue: State<'_, Arc<DownloadQueue>>,
+    jobs: State<'_, Arc<JobRegistry>>,
+    delete_converted: bool,
+    confirm: bool,
+) -> Result<(), AppError> {
+    if downloads_busy(&queue, &jobs) {
+        return Err(AppError::integrity(
+            "1014",
+            "أكمل التحميلات الجارية أو ألغها قبل مسح البيانات",
+        ));
+    }
+    output::purge(delete_converted, confirm)
+}
+
+#[tauri::command]
+fn storage_report() -> Result<StorageReport, AppError> {
+    storage::storage_report()
+}
+
+/// Removes orphaned working files and temp data. Refused while jobs run, as
+/// their files would look orphaned.
+#[tauri::command]
+fn cleanup_storage(
+    queue: State<'_, Arc<DownloadQueue>>,
+    jobs: State<'_, Arc<JobRegistry>>,
+) -> Result<CleanupReport, AppError> {
+    if downloads_busy(&queue, &jobs) {
+        return Err(AppError::integrity(
+            "1014",
+            "أكمل التحميلات الجارية أو ألغها قبل التنظيف",
+        ));
+    }
+    storage::cleanup()
+}
+
+/// Zips the logs, redacted settings and version info for a bug report, into
+/// the output folder unless `path` says otherwise.
+#[tauri::command]
+fn export_diagnostics(path: Option<String>) -> Result<String, AppError> {
+    let target = path.map(std::path::PathBuf::from).unwrap_or_else(|| {
+        output_dir().join(format!(
+            "jreader-diagnostics-{}.zip",
+            chrono::Utc::now().format("%Y%m%d-%H%M%S")
+        ))
+    });
+    logging::export_diagnostics(&target).map(|path| path.display().to_string())
 }
 
+/// Removes an account. Its converted books stay in its output folder.
 #[tauri::command]
-async fn get_books(state: State<'_, HttpClient>) -> Result<Vec<Book>, String> {
-    let client = &state.0;
-    match get_user_books(client).await {
-        Ok(books) => Ok(books),
-        Err(e) => Err(e),
+fn remove_profile(
+    queue: State<'_, Arc<DownloadQueue>>,
+    jobs: State<'_, Arc<JobRegistry>>,
+    profile_id: String,
+) -> Result<(), AppError> {
+    if downloads_busy(&queue, &jobs) {
+        return Err(AppError::integrity(
+            "1014",
+            "أكمل التحميلات الجارية أو ألغها قبل حذف الحساب",
+        ));
     }
+    profiles::remove_profile(&profile_id)
 }
 
 #[tauri::command]
@@ -75,7 +418,7 @@ fn base_action(app_handle: AppHandle, action: String) {
             app_handle.exit(0);
         }
         "folder" => {
-            let book_path_root = get_app_data_path(Some("books"));
+            let book_path_root = output_dir();
             let books_path = book_path_root.as_path();
             if books_path.exists() {
                 app_handle
@@ -96,37 +439,23 @@ fn base_action(app_handle: AppHandle, action: String) {
                 .unwrap();
         }
         "BookPage" => {
-            let app_type = get_settings(Some("app")).unwrap_or_else(|| {
-                println!("No settings found for action: app_type");
-                Value::Null
-            });
-            let app_type = app_type["type"].as_str().unwrap_or("jarir");
-            let website = match app_type {
-                "jarir" => "https://jarirreader.com",
-                "rufoof" => "https://rufoof.com",
-                _ => "https://jarirreader.com",
-            };
-            app_handle.opener().open_url(website, None::<&str>).unwrap();
+            app_handle
+                .opener()
+                .open_url(current_store().website(), None::<&str>)
+                .unwrap();
         }
         "TOS" => {
-            let app_type = get_settings(Some("app")).unwrap_or_else(|| {
-                println!("No settings found for action: app_type");
-                Value::Null
-            });
-            let app_type = app_type["type"].as_str().unwrap_or("jarir");
-            let website = match app_type {
-                "jarir" => "https://jarirreader.com/site/tos",
-                "rufoof" => "https://rufoof.com/privacy",
-                _ => "https://jarirreader.com/site/tos",
-            };
-            app_handle.opener().open_url(website, None::<&str>).unwrap();
+            app_handle
+                .opener()
+                .open_url(current_store().tos_url(), None::<&str>)
+                .unwrap();
         }
         _ => {}
     }
 }
 
 #[tauri::command]
-async fn settings_action(action: String) -> Result<Value, String> {
+async fn settings_action(action: String) -> Result<Value, AppError> {
     if action == "os" {
         #[cfg(target_os = "android")]
         return Ok(Value::String("android".to_string()));
@@ -134,59 +463,65 @@ async fn settings_action(action: String) -> Result<Value, String> {
         return Ok(Value::String("desktop".to_string()));
     }
 
-    let result = get_settings(Some(action.as_str())).unwrap_or_else(|| {
-        println!("No settings found for action: {}", action);
-        Value::Null
-    });
-
-    if result.is_null() {
-        return Ok(Value::Null);
-    }
-    Ok(result)
+    Ok(Settings::load()?.value(&action))
 }
 
 #[tauri::command]
 async fn auth_action(
     state: State<'_, HttpClient>,
+    session: State<'_, SessionManager>,
     email: Option<String>,
     password: Option<String>,
     app_type: Option<String>,
-) -> Result<(), String> {
-    let client = &state.0;
+) -> Result<(), AppError> {
+    let client = &state.get();
     if email.is_none() || password.is_none() {
-        return Err("كلمة المرور واسم المستخدم مطلوبين".to_string());
-    }
-    let result = auth(
-        client,
-        Some(&*email.unwrap()),
-        Some(&*password.unwrap()),
-        Some(&*app_type.unwrap_or("jarir".parse().unwrap())),
-    )
-    .await;
-    match result {
-        Ok(_) => Ok(()),
-        Err(e) => Err(e.to_string()),
+        return Err(AppError::auth("505z", "كلمة المرور واسم المستخدم مطلوبين"));
     }
+    let store = store_for(app_type.as_deref().unwrap_or_default());
+    let result = session
+        .login(
+            client,
+            &*store,
+            Some(&*email.unwrap()),
+            Some(&*password.unwrap()),
+        )
+        .await;
+    result.map(|_| ())
 }
 
 #[tauri::command]
-async fn pre_auth_action(state: State<'_, HttpClient>) -> Result<bool, String> {
-    let client = &state.0;
-    pre_auth(client).await.map_err(|_| "false".to_string())
+async fn pre_auth_action(
+    state: State<'_, HttpClient>,
+    session: State<'_, SessionManager>,
+) -> Result<bool, AppError> {
+    let client = &state.get();
+    pre_auth(client, &*current_store(), &session).await
 }
 
 #[tauri::command]
-async fn logout_action(state: State<'_, HttpClient>) -> Result<(), String> {
-    let client = &state.0;
-    logout(client).await.map(|_| ()).map_err(|e| e.to_string())
+async fn logout_action(
+    state: State<'_, HttpClient>,
+    session: State<'_, SessionManager>,
+) -> Result<(), AppError> {
+    let client = &state.get();
+    session.clear().await;
+    logout(client, &*current_store()).await.map(|_| ())
 }
 
 #[cfg(desktop)]
 pub fn run() {
+    logging::init(LevelFilter::INFO);
     let builder = tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_dialog::init())
-        .manage(HttpClient(create_http_client()))
+        .manage(HttpClient::new())
+        .manage(SessionManager::new())
+        .manage(Arc::new(JobRegistry::new()))
+        .setup(|app| {
+            start_download_queue(app);
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             visit_book,
             download_book,
@@ -197,7 +532,28 @@ pub fn run() {
             pre_auth_action,
             logout_action,
             get_books,
-            check_updates
+            sync_books,
+            get_offline_books,
+            get_all_books,
+            search_books,
+            book_history,
+            get_profiles,
+            add_profile,
+            switch_profile,
+            remove_profile,
+            check_updates,
+            queue_add,
+            queue_add_all,
+            queue_action,
+            cancel_job,
+            list_jobs,
+            network_settings,
+            output_settings,
+            purge_library,
+            storage_report,
+            cleanup_storage,
+            export_diagnostics,
+            regenerate_book
         ]);
 
     builder
@@ -208,12 +564,18 @@ pub fn run() {
 #[cfg(mobile)]
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
-    let client = create_http_client();
+    logging::init(LevelFilter::INFO);
     let builder = tauri::Builder::default()
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_sharesheet::init())
         .plugin(tauri_plugin_opener::init())
-        .manage(HttpClient(client))
+        .manage(HttpClient::new())
+        .manage(SessionManager::new())
+        .manage(Arc::new(JobRegistry::new()))
+        .setup(|app| {
+            start_download_queue(app);
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             visit_book,
             download_book,
@@ -224,7 +586,28 @@ pub fn run() {
             pre_auth_action,
             logout_action,
             get_books,
-            check_updates
+            sync_books,
+            get_offline_books,
+            get_all_books,
+            search_books,
+            book_history,
+            get_profiles,
+            add_profile,
+            switch_profile,
+            remove_profile,
+            check_updates,
+            queue_add,
+            queue_add_all,
+            queue_action,
+            cancel_job,
+            list_jobs,
+            network_settings,
+            output_settings,
+            purge_library,
+            storage_report,
+            cleanup_storage,
+            export_diagnostics,
+            regenerate_book
         ]);
 
     builder
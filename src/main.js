@@ -39,7 +39,7 @@ const actions = {
       action: "auth",
       email,
       password,
-      app_type,
+      appType: app_type,
     });
   },
   async getUsername() {
@@ -58,6 +58,30 @@ const actions = {
     // console.log("Getting books");
     return await invoke("get_books");
   },
+  async syncBooks() {
+    return await invoke("sync_books");
+  },
+  async getOfflineBooks() {
+    return await invoke("get_offline_books");
+  },
+  async searchBooks(query) {
+    return await invoke("search_books", { query });
+  },
+  async getAllBooks() {
+    return await invoke("get_all_books");
+  },
+  async getProfiles() {
+    return await invoke("get_profiles");
+  },
+  async addProfile(name) {
+    return await invoke("add_profile", { name });
+  },
+  async switchProfile(profileId) {
+    return await invoke("switch_profile", { profileId });
+  },
+  async removeProfile(profileId) {
+    return await invoke("remove_profile", { profileId });
+  },
   visitBookPage(bookId) {
     //Unhandled Promise Rejection: invalid args `bookId` for command `visit_book`: command visit_book missing required key bookId
     // console.log("Visiting book page with ID:", bookId);
@@ -67,9 +91,39 @@ const actions = {
     // console.log("Downloading book:", book);
     return await invoke("download_book", { bookId: book.id.toString() });
   },
+  async regenerateBook(book) {
+    return await invoke("regenerate_book", { bookId: book.id.toString() });
+  },
   openBook(book) {
     // console.log("Opening book from actions.openBook:", book);
-    invoke("open_book", { filePath: book.book_path });
+    invoke("open_file", { filePath: book.book_path });
+  },
+  async queueAll() {
+    return await invoke("queue_add_all");
+  },
+  async queueAction(action, bookId, value) {
+    return await invoke("queue_action", { action, bookId, value });
+  },
+  async cancelJob(jobId) {
+    return await invoke("cancel_job", { jobId });
+  },
+  async storageReport() {
+    return await invoke("storage_report");
+  },
+  async cleanupStorage() {
+    return await invoke("cleanup_storage");
+  },
+  async purgeLibrary(deleteConverted, confirm) {
+    return await invoke("purge_library", { deleteConverted, confirm });
+  },
+  async exportDiagnostics() {
+    return await invoke("export_diagnostics");
+  },
+  async networkSettings(settings) {
+    return await invoke("network_settings", { settings });
+  },
+  async outputSettings(settings) {
+    return await invoke("output_settings", { settings });
   },
   async checkUpdate() {
     // console.log("Checking for updates");
@@ -120,6 +174,22 @@ const root = () => ({
       // console.log("Custom message received:", title, body);
       message(body, { title });
     });
+    listen("download-progress", (event) => {
+      this.onDownloadProgress(event.payload);
+    });
+    listen("job-started", (event) => {
+      if (this.downloadingBookId === event.payload.book_id) {
+        this.currentJobId = event.payload.job_id;
+      }
+    });
+    listen("job-finished", (event) => {
+      if (this.currentJobId === event.payload.job_id) {
+        this.currentJobId = null;
+      }
+    });
+    listen("queue-changed", (event) => {
+      this.onQueueChanged(event.payload);
+    });
   },
   checkConnection() {
     // console.log("Checking connection");
@@ -182,6 +252,8 @@ const root = () => ({
       this.currentOs = os || "desktop";
     });
 
+    this.loadProfiles();
+
     this.downloadedBooks = Alpine.reactive({});
     this.showLoading = true;
     this.loadingTitle = "جار المصادقة مع الخادم...";
@@ -197,12 +269,12 @@ const root = () => ({
       }
     } catch (err) {
       console.error("Pre-auth error:", err);
-      if (
-        err &&
-        err.message &&
-        (err.message.indexOf("(504) ") > -1 ||
-          err.message.indexOf("(502) ") > -1)
-      ) {
+      if (err && err.kind === "network") {
+        this.showLoading = false;
+        this.openOfflineLibrary();
+        return;
+      }
+      if (err && err.kind === "auth") {
         this.authError = "بيانات الدخول خاطئة أو أنه لايوجد إتصال بالأنترنت!";
         console.log("Auth error:", this.authError);
         this.showAlert(this.authError);
@@ -249,6 +321,16 @@ const root = () => ({
     }
   },
 
+  //errors from the backend are {code, kind, message, detail}
+  backToLoginOnAuthError(err) {
+    if (!err || err.kind !== "auth") {
+      return false;
+    }
+    this.authenticated = false;
+    this.authError = "انتهت صلاحية الجلسة، قم بتسجيل الدخول مرة أخرى.";
+    return true;
+  },
+
   showAlert(alertText) {
     // console.log("Showing alert:", alertText);
     this.alertText = alertText;
@@ -308,14 +390,9 @@ const root = () => ({
       // console.log("Auth result:", res);
     } catch (err) {
       // console.error("Auth error:", err);
-      if (
-        err &&
-        err.message &&
-        (err.message.indexOf("(504) ") > -1 ||
-          err.message.indexOf("(502) ") > -1)
-      ) {
+      if (err && err.kind === "network") {
         this.authError = "بيانات الدخول خاطئة أو أنه لايوجد إتصال بالأنترنت!";
-      } else if (err && err.message && err.message.indexOf("(505) ") > -1) {
+      } else if (err && err.kind === "auth") {
         this.authError = "بيانات الدخول خاطئة!";
       } else {
         this.authError = err.message || "حصلت مشكلة في تسجيل الدخول!";
@@ -329,6 +406,7 @@ const root = () => ({
     }
 
     this.authenticated = true;
+    this.offline = false;
     this.loadingTitle = "";
 
     this.showLoading = false;
@@ -350,8 +428,11 @@ const root = () => ({
 
   async logout() {
     // console.log("Logging out");
+    if (!this.requireOnline()) {
+      return;
+    }
     const logoutConfirm = await this.confirmAsync(
-      "هل أنت متأكد من تسجيل الخروج وحذف كتبتك المحملة؟",
+      "هل أنت متأكد من تسجيل الخروج؟ ستبقى كتبك المحولة على جهازك.",
     );
     if (logoutConfirm) {
       this.showLoading = true;
@@ -366,11 +447,80 @@ const root = () => ({
         this.loadingTitle = "";
         this.books = {};
         this.downloadedBooks = {};
-        this.showAlert("تم تسجيل الخروج وحذف بياناتك من على حاسوبك!.");
+        this.showAlert("تم تسجيل الخروج وحذف بيانات الدخول من على حاسوبك.");
       }
     }
   },
 
+  /**
+   * storage: what the books take on disk, and cleaning up leftovers
+   */
+  storage: null,
+  storageLabels: {
+    packages: "الملفات المحملة",
+    extracted: "الملفات المستخرجة",
+    sources: "المصادر المحفوظة",
+    output: "الكتب المحولة",
+    catalog: "بيانات المكتبة",
+    temp: "الملفات المؤقتة",
+  },
+  formatSize(bytes) {
+    return (bytes / (1024 * 1024)).toFixed(1) + " MB";
+  },
+  loadStorage() {
+    actions
+      .storageReport()
+      .then((report) => {
+        this.storage = report;
+      })
+      .catch((error) => {
+        this.showAlert("تعذر حساب المساحة: " + (error.message || error));
+      });
+  },
+  cleanupStorage() {
+    actions
+      .cleanupStorage()
+      .then((report) => {
+        this.showAlert("تم تحرير " + this.formatSize(report.freed));
+        this.loadStorage();
+      })
+      .catch((error) => {
+        this.showAlert("تعذر التنظيف: " + (error.message || error));
+      });
+  },
+
+  exportDiagnostics() {
+    actions
+      .exportDiagnostics()
+      .then((path) => {
+        this.showAlert("تم حفظ ملف التشخيص في: " + path);
+      })
+      .catch((error) => {
+        this.showAlert("تعذر تصدير ملف التشخيص: " + (error.message || error));
+      });
+  },
+
+  async purgeLibrary(deleteConverted) {
+    const purgeConfirm = await this.confirmAsync(
+      deleteConverted
+        ? "سيتم حذف الكتب المحولة والملفات المؤقتة وبيانات المكتبة نهائياً. هل أنت متأكد؟"
+        : "سيتم حذف الملفات المؤقتة وبيانات المكتبة، وتبقى الكتب المحولة. هل أنت متأكد؟",
+    );
+    if (!purgeConfirm) {
+      return;
+    }
+    actions
+      .purgeLibrary(deleteConverted, true)
+      .then(() => {
+        this.books = {};
+        this.downloadedBooks = {};
+        this.showAlert("تم مسح البيانات");
+      })
+      .catch((error) => {
+        this.showAlert("تعذر مسح البيانات: " + (error.message || error));
+      });
+  },
+
   openFolder() {
     // console.log("Opening folder");
     actions.openFolder();
@@ -378,6 +528,136 @@ const root = () => ({
   toggleAbout() {
     // console.log("Toggling about section");
     this.showAbout = !this.showAbout;
+    if (this.showAbout) {
+      actions.networkSettings(null).then((settings) => {
+        this.network = settings;
+      });
+      actions.outputSettings(null).then((settings) => {
+        this.output = settings;
+      });
+    }
+  },
+
+  /**
+   * profiles: one per account, each with its own store and books
+   */
+  profiles: [],
+  activeProfile: "default",
+  allProfiles: false,
+  loadProfiles() {
+    actions.getProfiles().then((list) => {
+      this.profiles = list.profiles;
+      this.activeProfile = list.active;
+    });
+  },
+  profileName(profileId) {
+    if (profileId === "default") {
+      return "الحساب الأساسي";
+    }
+    const profile = this.profiles.find((item) => item.id === profileId);
+    return profile ? profile.name : "";
+  },
+  async addProfile() {
+    const name = prompt("اسم الحساب الجديد:");
+    if (!name || name.trim() === "") {
+      return;
+    }
+    try {
+      const profile = await actions.addProfile(name);
+      await this.switchProfile(profile.id);
+    } catch (error) {
+      this.showAlert(error.message || "حصلت مشكلة في إضافة الحساب!");
+    }
+  },
+  //the page reloads so every view starts again with the new account
+  async switchProfile(profileId) {
+    if (profileId === this.activeProfile) {
+      return;
+    }
+    try {
+      await actions.switchProfile(profileId);
+      window.location.reload();
+    } catch (error) {
+      this.showAlert(error.message || "حصلت مشكلة في تبديل الحساب!");
+    }
+  },
+  async removeProfile(profileId) {
+    const removeConfirm = await this.confirmAsync(
+      `هل أنت متأكد من حذف حساب "${this.profileName(profileId)}"؟ تبقى الكتب المحولة في مجلدها.`,
+    );
+    if (!removeConfirm) {
+      return;
+    }
+    try {
+      await actions.removeProfile(profileId);
+      this.loadProfiles();
+    } catch (error) {
+      this.showAlert(error.message || "حصلت مشكلة في حذف الحساب!");
+    }
+  },
+  //books of every profile in one list, each tagged with {profile, profile_name}
+  toggleAllProfiles() {
+    if (this.allProfiles) {
+      this.allProfiles = false;
+      if (this.offline) {
+        this.openOfflineLibrary();
+      } else {
+        this.getBooks(":from all profiles");
+      }
+      return;
+    }
+    actions
+      .getAllBooks()
+      .then((books) => {
+        this.books = books;
+        this.booksCount = books.length;
+        this.allProfiles = true;
+      })
+      .catch(() => {
+        this.showAlert("حصلت مشكلة في عرض كتب كل الحسابات!");
+      });
+  },
+
+  /**
+   * network settings
+   */
+  network: null,
+  saveNetworkSettings() {
+    if (
+      this.network.accept_invalid_certs &&
+      !confirm("تعطيل التحقق من الشهادات يعرض كلمة المرور للخطر. هل أنت متأكد؟")
+    ) {
+      return;
+    }
+    actions
+      .networkSettings(this.network)
+      .then((settings) => {
+        this.network = settings;
+        this.showAlert("تم حفظ إعدادات الشبكة");
+      })
+      .catch((error) => {
+        this.showAlert("إعدادات الشبكة غير صحيحة: " + (error.message || error));
+      });
+  },
+
+  /**
+   * output settings: where converted books go and how they are arranged
+   */
+  output: null,
+  saveOutputSettings() {
+    this.showLoading = true;
+    actions
+      .outputSettings({ ...this.output, dir: this.output.dir || null })
+      .then((settings) => {
+        this.output = settings;
+        this.showAlert("تم حفظ مجلد الكتب ونقل الكتب المحولة إليه");
+      })
+      .catch((error) => {
+        this.showAlert("تعذر نقل بعض الكتب: " + (error.message || error));
+      })
+      .finally(() => {
+        this.showLoading = false;
+      });
   },
   visitDeveloperPage() {
     // console.log("Visiting developer page");
@@ -395,6 +675,70 @@ const root = () => ({
   downloadedBooks: {},
   booksCount: 0,
   selectedBook: null,
+  search: "",
+  status: "",
+  sort: "store",
+  //filter and sort run on the local catalog; the search box filters the result
+  queryBooks() {
+    if (this.offline || this.allProfiles) {
+      return;
+    }
+    actions
+      .searchBooks({ status: this.status || null, sort: this.sort })
+      .then((page) => {
+        this.books = page.books;
+        this.booksCount = page.total;
+      })
+      .catch(() => {
+        this.showAlert("حصلت مشكلة في ترتيب الكتب!");
+      });
+  },
+  filteredBooks() {
+    if (!Array.isArray(this.books)) {
+      return [];
+    }
+    const query = this.search.trim().toLowerCase();
+    if (query === "") {
+      return this.books;
+    }
+    return this.books.filter(
+      (book) =>
+        book.title.toLowerCase().includes(query) ||
+        (book.authors || []).some((author) =>
+          author.toLowerCase().includes(query),
+        ),
+    );
+  },
+
+  /**
+   * offline library: downloaded books only, no store calls
+   */
+  offline: false,
+  openOfflineLibrary() {
+    actions
+      .getOfflineBooks()
+      .then((books) => {
+        this.books = books;
+        this.booksCount = books.length;
+        this.offline = true;
+        this.showTos = false;
+        this.showAlert("لا يوجد اتصال بالمتجر، تعرض الكتب المحملة فقط.");
+      })
+      .catch(() => {
+        this.showAlert("حصلت مشكلة في عرض الكتب المحملة!");
+      });
+  },
+  goOnline() {
+    window.location.reload();
+  },
+  //online-only actions call this first
+  requireOnline() {
+    if (this.offline) {
+      this.showAlert("هذا الإجراء غير متاح بدون اتصال بالمتجر.");
+      return false;
+    }
+    return true;
+  },
   getBooks(fromWhere) {
     // console.log("Getting books", fromWhere);
     this.showLoading = true;
@@ -412,6 +756,13 @@ const root = () => ({
       })
       .catch((error) => {
         // console.error("Error getting books:", error);
+        if (this.backToLoginOnAuthError(error)) {
+          return;
+        }
+        if (error && error.kind === "network") {
+          this.openOfflineLibrary();
+          return;
+        }
         this.showAlert("حصلت مشكلة في جلب الكتب!");
       })
       .finally(() => {
@@ -421,7 +772,47 @@ const root = () => ({
         // console.log("Books count:", this.booksCount);
       });
   },
-  toggleBookInfo(bookId) {
+  //payload is {books, changes: {added, revoked, updated, removed}}
+  syncBooks() {
+    if (!this.requireOnline()) {
+      return;
+    }
+    this.showLoading = true;
+    this.loadingTitle = "جار مزامنة المكتبة ...";
+
+    actions
+      .syncBooks()
+      .then((sync) => {
+        this.books = sync.books;
+        const changes = sync.changes;
+        const lines = [];
+        if (changes.added.length) {
+          lines.push(`كتب جديدة: ${changes.added.length}`);
+        }
+        if (changes.updated.length) {
+          lines.push(`كتب لها نسخة محدثة: ${changes.updated.length}`);
+        }
+        if (changes.revoked.length) {
+          lines.push(`كتب لم تعد متاحة: ${changes.revoked.length}`);
+        }
+        if (changes.removed.length) {
+          lines.push(`كتب لم تعد في مكتبة المتجر: ${changes.removed.length}`);
+        }
+        this.showAlert(lines.length ? lines.join("، ") : "المكتبة محدثة.");
+      })
+      .catch((error) => {
+        if (this.backToLoginOnAuthError(error)) {
+          return;
+        }
+        this.showAlert("حصلت مشكلة في مزامنة المكتبة!");
+      })
+      .finally(() => {
+        this.showLoading = false;
+        this.loadingTitle = "";
+        this.booksCount = this.books.length;
+      });
+  },
+  toggleBookInfo(bookId, profileId) {
     if (this.showBookInfo) {
       this.showBookInfo = false;
       this.selectedBook = null;
@@ -431,7 +822,9 @@ const root = () => ({
     //disable scrollbar and show only bookinfo
     document.body.style.overflow = "hidden";
 
-    this.selectedBook = this.books.find((book) => book.id === bookId);
+    this.selectedBook = this.books.find(
+      (book) => book.id === bookId && book.profile === profileId,
+    );
 
     if (!this.selectedBook) {
       this.showAlert("حصلت مشكلة في عرض بيانات الكتاب!");
@@ -445,7 +838,16 @@ const root = () => ({
     actions.visitBookPage(bookId);
   },
   downloadBook(wantedBook) {
+    if (!this.requireOnline()) {
+      return;
+    }
     const book = JSON.parse(JSON.stringify(wantedBook));
+    if (book.profile && book.profile !== this.activeProfile) {
+      this.showAlert(
+        `بدّل إلى حساب "${book.profile_name}" لتحميل هذا الكتاب.`,
+      );
+      return;
+    }
     if (book.type === "mp3") {
       this.showAlert("نأسف، لايمكن تحميل كتب الصوت حالياً.");
       return;
@@ -454,6 +856,8 @@ const root = () => ({
     this.toggleBookInfo();
     // console.log("Downloading book:", book);
     this.showLoading = true;
+    this.downloadingTitle = book.title;
+    this.downloadingBookId = book.id.toString();
     this.loadingTitle = `جاري تحميل كتاب ${book.title}...`;
 
     actions
@@ -463,17 +867,136 @@ const root = () => ({
         this.showAlert("تم تحميل الكتاب بنجاح، اضغط على الكتاب لعرضه.");
 
         this.downloadedBooks[book.id] = res;
+        const listed = this.books.find((item) => item.id === book.id);
+        if (listed) {
+          listed.book_path = res;
+          listed.update_available = false;
+        }
       })
       .catch((error) => {
         // console.error("Error downloading book:", error);
+        if (this.backToLoginOnAuthError(error)) {
+          return;
+        }
+        if (error && error.kind === "cancelled") {
+          this.showAlert("تم إلغاء تحميل الكتاب.");
+          return;
+        }
         this.showAlert(`صادفنا خطأ، تأكد من بيانات الدخول أو الانترنت ...`);
       })
       .finally(() => {
         this.showLoading = false;
         this.loadingTitle = "";
+        this.downloadingTitle = "";
+        this.downloadingBookId = null;
+        this.currentJobId = null;
       });
   },
 
+  //rebuilds a converted book from its kept source, works offline
+  regenerateBook(wantedBook) {
+    const book = JSON.parse(JSON.stringify(wantedBook));
+    this.toggleBookInfo();
+    this.showLoading = true;
+    this.downloadingBookId = book.id.toString();
+    this.loadingTitle = `جاري إعادة بناء كتاب ${book.title}...`;
+
+    actions
+      .regenerateBook(book)
+      .then((res) => {
+        this.showAlert("تمت إعادة بناء الكتاب بنجاح.");
+        this.downloadedBooks[book.id] = res;
+        const listed = this.books.find((item) => item.id === book.id);
+        if (listed) {
+          listed.book_path = res;
+        }
+      })
+      .catch((error) => {
+        if (error && error.kind === "cancelled") {
+          this.showAlert("تم إلغاء إعادة بناء الكتاب.");
+          return;
+        }
+        if (error && error.code === "1024") {
+          this.showAlert("لا يوجد مصدر محفوظ لهذا الكتاب، حمّله من جديد.");
+          return;
+        }
+        this.showAlert("تعذرت إعادة بناء الكتاب: " + (error.message || error));
+      })
+      .finally(() => {
+        this.showLoading = false;
+        this.loadingTitle = "";
+        this.downloadingBookId = null;
+        this.currentJobId = null;
+      });
+  },
+
+  queueRunning: 0,
+  downloadAll() {
+    if (!this.requireOnline()) {
+      return;
+    }
+    actions
+      .queueAll()
+      .then((queue) => {
+        this.onQueueChanged(queue);
+        this.showAlert("تمت إضافة الكتب غير المحملة إلى قائمة التحميل.");
+      })
+      .catch((error) => {
+        if (this.backToLoginOnAuthError(error)) {
+          return;
+        }
+        this.showAlert("حصلت مشكلة في إضافة الكتب لقائمة التحميل!");
+      });
+  },
+  //payload is {items: [{book_id, title, status, book_path, error}], paused, parallelism}
+  onQueueChanged(queue) {
+    if (!queue || !queue.items) {
+      return;
+    }
+    this.queueRunning = queue.items.filter(
+      (item) => item.status === "queued" || item.status === "running",
+    ).length;
+    for (const item of queue.items) {
+      if (item.status === "done" && item.book_path) {
+        this.downloadedBooks[item.book_id] = item.book_path;
+      }
+    }
+  },
+
+  downloadingTitle: "",
+  downloadingBookId: null,
+  currentJobId: null,
+  cancelDownload() {
+    if (this.currentJobId) {
+      actions.cancelJob(this.currentJobId);
+    }
+  },
+  //payload is {book_id, downloaded, total, rate}
+  onDownloadProgress(progress) {
+    if (!this.showLoading || !this.downloadingTitle) {
+      return;
+    }
+    let status = this.formatBytes(progress.downloaded);
+    if (progress.total) {
+      const percent = Math.floor((progress.downloaded / progress.total) * 100);
+      status = `${Math.min(percent, 100)}% - ${status} / ${this.formatBytes(progress.total)}`;
+    }
+    if (progress.rate) {
+      status += ` (${this.formatBytes(progress.rate)}/s)`;
+    }
+    this.loadingTitle = `جاري تحميل كتاب ${this.downloadingTitle}... ${status}`;
+  },
+  formatBytes(bytes) {
+    const units = ["B", "KB", "MB", "GB"];
+    let value = bytes;
+    let unit = 0;
+    while (value >= 1024 && unit < units.length - 1) {
+      value /= 1024;
+      unit++;
+    }
+    return `${value.toFixed(unit === 0 ? 0 : 1)} ${units[unit]}`;
+  },
+
   //for android
   shareBook(filePath, title, save) {
     if (filePath) {